@@ -0,0 +1,572 @@
+use gh_actions_scaler::config::{Config, MachineConfig, PullPolicy, DEFAULT_DOCKER_IMAGE};
+use gh_actions_scaler::display::{render_grid, Cell};
+use gh_actions_scaler::machine::{
+    missing_image_message, ContainerState, Machine, RunnerInfo, RunnerQuery,
+};
+use gh_actions_scaler::output::{MachineRow, MachineStatus, OutputFormat, RunnerRow};
+use gh_actions_scaler::preflight::{MachinePreflight, PreflightIssue, PreflightReport};
+use std::collections::HashSet;
+use std::error::Error;
+use std::io;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use termcolor::Color;
+use tracing::{error, info, warn};
+
+/// Prints the configured machines with their ping latency and the number of their live runners.
+/// A machine that responds to a ping but fails to list its runners is reported as degraded.
+/// Returns whether the runners of all machines were retrieved successfully.
+pub fn list_machines(config: &Config, format: OutputFormat) -> Result<bool, Box<dyn Error>> {
+    let mut is_ok = true;
+    let mut rows: Vec<MachineRow> = vec![];
+    for machine_config in &config.machines {
+        let machine = Machine::new(Arc::clone(machine_config));
+        let (status, latency, runners, error) = match machine.ping() {
+            Ok(latency) => match machine.fetch_runners() {
+                Ok(runners) => (
+                    MachineStatus::Online,
+                    Some(latency),
+                    Some(
+                        runners
+                            .iter()
+                            .filter(|runner| runner.state().is_alive())
+                            .count(),
+                    ),
+                    None,
+                ),
+                Err(err) => (
+                    MachineStatus::Degraded,
+                    Some(latency),
+                    None,
+                    Some(err.to_string()),
+                ),
+            },
+            Err(err) => (MachineStatus::Offline, None, None, Some(err.to_string())),
+        };
+        if status != MachineStatus::Online {
+            is_ok = false;
+        }
+
+        rows.push(MachineRow {
+            id: &machine_config.id,
+            host: &machine_config.ssh.host,
+            port: machine_config.ssh.port,
+            status,
+            latency_ms: latency.map(|latency| latency.as_millis() as u64),
+            runners,
+            error,
+        });
+    }
+
+    print!("{}", format.formatter().format_machines(&rows));
+
+    Ok(is_ok)
+}
+
+/// The status of a machine printed by the `status` subcommand.
+struct MachineSummary {
+    ssh_error: Option<String>,
+    /// The number of the running runners, or `None` if unknown
+    running_runners: Option<usize>,
+    /// Whether the runner image exists, or `None` if unknown
+    image_cached: Option<bool>,
+}
+
+impl MachineSummary {
+    fn fetch(machine: &Machine) -> MachineSummary {
+        if let Err(err) = machine.ping() {
+            return MachineSummary {
+                ssh_error: Some(err.to_string()),
+                running_runners: None,
+                image_cached: None,
+            };
+        }
+
+        MachineSummary {
+            ssh_error: None,
+            running_runners: RunnerQuery::new()
+                .in_state(ContainerState::Running)
+                .execute(machine)
+                .map(|runners| runners.len())
+                .ok(),
+            image_cached: machine.is_runner_image_cached().ok(),
+        }
+    }
+}
+
+/// Prints a summary of every machine, checking all machines concurrently. A machine that doesn't
+/// respond within its connect and command timeouts is reported as unreachable.
+/// Returns whether all machines were reachable.
+pub fn status(config: &Config) -> Result<bool, Box<dyn Error>> {
+    let (tx, rx) = mpsc::channel();
+    for (index, machine_config) in config.machines.iter().enumerate() {
+        let machine = Machine::new(Arc::clone(machine_config));
+        let tx = tx.clone();
+        thread::spawn(move || {
+            // The receiver is gone if timed out already.
+            let _ = tx.send((index, MachineSummary::fetch(&machine)));
+        });
+    }
+    drop(tx);
+
+    let timeout = config
+        .machines
+        .iter()
+        .map(|c| Duration::from_secs(c.ssh.connect_timeout_secs + c.ssh.command_timeout_secs))
+        .max()
+        .unwrap_or_default();
+    let deadline = Instant::now() + timeout;
+    let mut summaries: Vec<Option<MachineSummary>> = config.machines.iter().map(|_| None).collect();
+    while let Ok((index, summary)) =
+        rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+    {
+        summaries[index] = Some(summary);
+    }
+
+    let mut is_ok = true;
+    let mut rows: Vec<Vec<Cell>> = vec![];
+    for (machine_config, summary) in config.machines.iter().zip(summaries) {
+        let summary = summary.unwrap_or_else(|| MachineSummary {
+            ssh_error: Some(format!("timed out after {} second(s)", timeout.as_secs())),
+            running_runners: None,
+            image_cached: None,
+        });
+        let ssh = match &summary.ssh_error {
+            None => ("ok".to_string(), Some(Color::Green)),
+            Some(err) => {
+                is_ok = false;
+                error!(
+                    machine = %machine_config.id,
+                    "[{}] Failed to connect: {}",
+                    machine_config.id, err
+                );
+                ("err".to_string(), Some(Color::Red))
+            }
+        };
+        rows.push(vec![
+            (machine_config.id.clone(), None),
+            (
+                format!("{}:{}", machine_config.ssh.host, machine_config.ssh.port),
+                None,
+            ),
+            (
+                if machine_config.enabled.unwrap_or(true) {
+                    "yes"
+                } else {
+                    "no"
+                }
+                .to_string(),
+                None,
+            ),
+            ssh,
+            (
+                match summary.running_runners {
+                    Some(running) => format!("{}/{}", running, machine_config.runners.max),
+                    None => "N/A".to_string(),
+                },
+                None,
+            ),
+            (
+                match summary.image_cached {
+                    Some(true) => "yes",
+                    Some(false) => "no",
+                    None => "unknown",
+                }
+                .to_string(),
+                None,
+            ),
+        ]);
+    }
+
+    print!(
+        "{}",
+        render_grid(
+            &["ID", "ADDRESS", "ENABLED", "SSH", "RUNNERS", "IMAGE"],
+            &rows,
+            io::stdout().is_terminal(),
+        )
+    );
+
+    Ok(is_ok)
+}
+
+/// Verifies the SSH connectivity to all machines and prints the result of each machine.
+/// Returns whether all machines were reachable.
+pub fn test_ssh(config: &Config) -> Result<bool, Box<dyn Error>> {
+    let mut is_ok = true;
+    println!(
+        "{:<20} {:<30} {:>5} {:>12} RESULT",
+        "ID", "HOST", "PORT", "LATENCY (ms)"
+    );
+    for machine_config in &config.machines {
+        let (latency, result) = match Machine::new(Arc::clone(machine_config)).ping() {
+            Ok(latency) => (latency.as_millis().to_string(), "ok".to_string()),
+            Err(err) => {
+                is_ok = false;
+                ("-".to_string(), format!("error: {}", err))
+            }
+        };
+        println!(
+            "{:<20} {:<30} {:>5} {:>12} {}",
+            machine_config.id, machine_config.ssh.host, machine_config.ssh.port, latency, result
+        );
+    }
+
+    Ok(is_ok)
+}
+
+/// Checks whether the machines that the runners are placed on are ready to run the runners,
+/// concurrently, and prints the result of each machine. The sessions opened for the checks are
+/// kept in `machines` for later use. Returns the IDs of the machines that have an error.
+pub fn preflight(config: &Config, machines: &[Machine]) -> HashSet<String> {
+    let placeable_machines: Vec<&Machine> = machines
+        .iter()
+        .filter(|machine| config.places_runners_on(machine.config()))
+        .collect();
+    let results: Vec<Result<PreflightReport, Vec<PreflightIssue>>> = thread::scope(|scope| {
+        let handles: Vec<_> = placeable_machines
+            .iter()
+            .map(|machine| scope.spawn(move || MachinePreflight::check(machine)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle.join().unwrap_or_else(|_| {
+                    Err(vec![PreflightIssue::Error(
+                        "The preflight check panicked".to_string(),
+                    )])
+                })
+            })
+            .collect()
+    });
+
+    let mut failed_machine_ids: HashSet<String> = HashSet::new();
+    let mut lines: Vec<String> = vec![format!(
+        "{:<20} {:<10} {:>10} {:>10} {:<6} RESULT",
+        "ID", "DOCKER", "DISK", "MEMORY", "IMAGE"
+    )];
+    for (machine, result) in placeable_machines.into_iter().zip(results) {
+        let machine_config = machine.config();
+        match result {
+            Ok(report) => {
+                for warning in &report.warnings {
+                    warn!(
+                        machine = %machine_config.id,
+                        "[{}] Preflight check: {}",
+                        machine_config.id, warning
+                    );
+                }
+                let version = report.docker_version;
+                lines.push(format!(
+                    "{:<20} {:<10} {:>10} {:>10} {:<6} {}",
+                    machine_config.id,
+                    format!("{}.{}.{}", version.0, version.1, version.2),
+                    format_size(report.disk_usage.map(|usage| usage.available_bytes)),
+                    format_size(report.memory.map(|memory| memory.available_bytes)),
+                    if report.image_cached { "cached" } else { "-" },
+                    if report.warnings.is_empty() {
+                        "ok".to_string()
+                    } else {
+                        format!("{} warning(s)", report.warnings.len())
+                    }
+                ));
+            }
+            Err(issues) => {
+                failed_machine_ids.insert(machine_config.id.clone());
+                for issue in &issues {
+                    error!(
+                        machine = %machine_config.id,
+                        "[{}] Preflight check: {}",
+                        machine_config.id, issue
+                    );
+                }
+                lines.push(format!(
+                    "{:<20} {:<10} {:>10} {:>10} {:<6} {}",
+                    machine_config.id,
+                    "-",
+                    "-",
+                    "-",
+                    "-",
+                    issues
+                        .iter()
+                        .map(|issue| issue.to_string())
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                ));
+            }
+        }
+    }
+
+    for line in lines {
+        println!("{}", line);
+    }
+    failed_machine_ids
+}
+
+/// Formats the specified number of bytes in GiB, e.g. `12.3GiB`.
+fn format_size(bytes: Option<u64>) -> String {
+    match bytes {
+        Some(bytes) => format!("{:.1}GiB", bytes as f64 / (1u64 << 30) as f64),
+        None => "-".to_string(),
+    }
+}
+
+/// Prints the runner containers of all machines.
+/// Returns whether the runners of all machines were retrieved successfully.
+pub fn list_runners(config: &Config, format: OutputFormat) -> Result<bool, Box<dyn Error>> {
+    let mut is_ok = true;
+    let mut runners_by_machine: Vec<(&str, Vec<RunnerInfo>)> = vec![];
+    for machine_config in &config.machines {
+        match fetch_runners(machine_config) {
+            Ok(runners) => runners_by_machine.push((&machine_config.id, runners)),
+            Err(err) => {
+                is_ok = false;
+                error!(
+                    machine = %machine_config.id,
+                    "[{}] Failed to retrieve the list of runners: {}",
+                    machine_config.id, err
+                );
+            }
+        }
+    }
+
+    let rows: Vec<RunnerRow> = runners_by_machine
+        .iter()
+        .flat_map(|(machine_id, runners)| {
+            runners
+                .iter()
+                .map(|runner| RunnerRow { machine_id, runner })
+        })
+        .collect();
+
+    print!("{}", format.formatter().format_runners(&rows));
+
+    Ok(is_ok)
+}
+
+/// Prints the images cached on all machines, marking the runner image of each machine, and
+/// reports the machines that lack their runner image according to `docker.pull_policy`.
+/// Returns whether the images of all machines were retrieved successfully.
+pub fn list_images(config: &Config) -> Result<bool, Box<dyn Error>> {
+    let mut is_ok = true;
+    let mut rows: Vec<Vec<Cell>> = vec![];
+    for machine_config in &config.machines {
+        let images = match Machine::new(Arc::clone(machine_config)).list_docker_images() {
+            Ok(images) => images,
+            Err(err) => {
+                is_ok = false;
+                error!(
+                    machine = %machine_config.id,
+                    "[{}] Failed to list the container images: {}",
+                    machine_config.id, err
+                );
+                continue;
+            }
+        };
+
+        let runner_image = machine_config
+            .docker
+            .image
+            .as_deref()
+            .unwrap_or(DEFAULT_DOCKER_IMAGE);
+        for image in &images {
+            let short_id = image.id.trim_start_matches("sha256:");
+            rows.push(vec![
+                (machine_config.id.clone(), None),
+                (image.repository.clone(), None),
+                (image.tag.clone(), None),
+                (short_id[..short_id.len().min(12)].to_string(), None),
+                (format_size(Some(image.size_bytes)), None),
+                (image.created_at.to_rfc3339(), None),
+                if image.is(runner_image) {
+                    ("yes".to_string(), Some(Color::Green))
+                } else {
+                    ("no".to_string(), None)
+                },
+            ]);
+        }
+
+        if !images.iter().any(|image| image.is(runner_image)) {
+            match machine_config.docker.pull_policy.unwrap_or_default() {
+                PullPolicy::Always => {}
+                PullPolicy::IfNotPresent => info!(
+                    machine = %machine_config.id,
+                    "[{}] The runner image will be pulled when a runner starts: {}",
+                    machine_config.id, runner_image
+                ),
+                PullPolicy::Never => warn!(
+                    machine = %machine_config.id,
+                    "[{}] {}",
+                    machine_config.id,
+                    missing_image_message(runner_image, PullPolicy::Never)
+                ),
+            }
+        }
+    }
+
+    print!(
+        "{}",
+        render_grid(
+            &[
+                "MACHINE",
+                "REPOSITORY",
+                "TAG",
+                "IMAGE ID",
+                "SIZE",
+                "CREATED",
+                "RUNNER"
+            ],
+            &rows,
+            io::stdout().is_terminal(),
+        )
+    );
+
+    Ok(is_ok)
+}
+
+/// Removes the unused container images from all machines.
+/// Returns whether the images of all machines were pruned successfully.
+pub fn prune(config: &Config) -> Result<bool, Box<dyn Error>> {
+    let mut is_ok = true;
+    let mut total_reclaimed: u64 = 0;
+    for machine_config in &config.machines {
+        match Machine::new(Arc::clone(machine_config)).prune_docker_images() {
+            Ok(reclaimed) => total_reclaimed += reclaimed,
+            Err(err) => {
+                is_ok = false;
+                error!(
+                    machine = %machine_config.id,
+                    "[{}] Failed to prune the unused container images: {}",
+                    machine_config.id, err
+                );
+            }
+        }
+    }
+
+    info!(
+        "Reclaimed {} bytes from {} machine(s)",
+        total_reclaimed,
+        config.machines.len()
+    );
+    Ok(is_ok)
+}
+
+/// Stops the live runner containers of all machines, or kills them if `force` is `true`.
+/// Returns whether all runner containers were stopped successfully.
+pub fn cleanup(config: &Config, force: bool, timeout_secs: u32) -> Result<bool, Box<dyn Error>> {
+    let mut is_ok = true;
+    for machine_config in &config.machines {
+        let machine = Machine::new(Arc::clone(machine_config));
+        let runners = match machine.fetch_runners() {
+            Ok(runners) => runners,
+            Err(err) => {
+                is_ok = false;
+                error!(
+                    machine = %machine_config.id,
+                    "[{}] Failed to retrieve the list of runners: {}",
+                    machine_config.id, err
+                );
+                continue;
+            }
+        };
+
+        for runner in runners.iter().filter(|runner| runner.state().is_alive()) {
+            let result = if force {
+                machine.force_stop_runner(runner.container_id())
+            } else {
+                machine.stop_runner(runner.container_id(), timeout_secs)
+            };
+            if let Err(err) = result {
+                is_ok = false;
+                error!(
+                    machine = %machine_config.id,
+                    container_id = runner.container_id(),
+                    "[{}] Failed to stop the container {}: {}",
+                    machine_config.id,
+                    runner.container_id(),
+                    err
+                );
+            }
+        }
+    }
+
+    Ok(is_ok)
+}
+
+fn fetch_runners(machine_config: &Arc<MachineConfig>) -> Result<Vec<RunnerInfo>, Box<dyn Error>> {
+    Machine::new(Arc::clone(machine_config)).fetch_runners()
+}
+
+/// Prints the output of the specified runner container as it arrives, looking up the machine
+/// that runs it. Returns whether the container was found.
+pub fn tail_logs(
+    config: &Config,
+    container_id: &str,
+    lines: usize,
+) -> Result<bool, Box<dyn Error>> {
+    let Some((machine, runner)) = find_runner(config, container_id) else {
+        error!("No machine has the container: {}", container_id);
+        return Ok(false);
+    };
+
+    let mut stdout = io::stdout();
+    for line in machine.tail_runner_logs(runner.container_id(), lines)? {
+        writeln!(stdout, "{}", line)?;
+        stdout.flush()?;
+    }
+    Ok(true)
+}
+
+/// Downloads the output of the specified runner container into `local_path`, looking up
+/// the machine that runs it. Returns whether the container was found.
+pub fn save_logs(
+    config: &Config,
+    container_id: &str,
+    local_path: &Path,
+) -> Result<bool, Box<dyn Error>> {
+    let Some((machine, runner)) = find_runner(config, container_id) else {
+        error!("No machine has the container: {}", container_id);
+        return Ok(false);
+    };
+
+    machine.copy_logs_via_sftp(runner.container_id(), local_path)?;
+    info!(
+        machine = machine.id(),
+        "[{}] Saved the output of {} to: {}",
+        machine.id(),
+        runner.container_id(),
+        local_path.display()
+    );
+    Ok(true)
+}
+
+/// Finds the machine that has the specified runner container. A short container ID is accepted
+/// as well, e.g. `0123456789ab`.
+fn find_runner(config: &Config, container_id: &str) -> Option<(Machine, RunnerInfo)> {
+    for machine_config in &config.machines {
+        let machine = Machine::new(Arc::clone(machine_config));
+        let runners = match machine.fetch_runners() {
+            Ok(runners) => runners,
+            Err(err) => {
+                error!(
+                    machine = %machine_config.id,
+                    "[{}] Failed to retrieve the list of runners: {}",
+                    machine_config.id, err
+                );
+                continue;
+            }
+        };
+
+        if let Some(runner) = runners
+            .into_iter()
+            .find(|runner| runner.container_id().starts_with(container_id))
+        {
+            return Some((machine, runner));
+        }
+    }
+    None
+}
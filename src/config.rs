@@ -1,73 +1,282 @@
-mod resolver;
+mod merge;
+mod remote;
+pub mod resolver;
+mod watch;
 
+use crate::config::remote::parse_config_url;
 use crate::config::resolver::ConfigResolver;
 use clap::ValueEnum;
-use log::warn;
 use log::LevelFilter;
 use once_cell::sync::Lazy;
 use regex::Regex;
-use serde::Deserialize;
-use std::collections::HashSet;
-use std::fmt::{Debug, Formatter};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
 use std::{env, fmt, fs, io};
+use tracing::warn;
+use url::Url;
 
-#[derive(Debug, Deserialize, PartialEq)]
+/// The runner container image used when `docker.image` is not specified.
+pub const DEFAULT_DOCKER_IMAGE: &str = "ghcr.io/myoung34/docker-github-actions-runner:ubuntu-focal";
+
+/// The prefix of the runner container names used when `docker.container_name_prefix` is not specified.
+pub const DEFAULT_CONTAINER_NAME_PREFIX: &str = "github-self-hosted-runner";
+
+/// The available disk space below which a preflight warning is reported,
+/// used when `docker.min_free_disk_bytes` is not specified.
+pub const DEFAULT_MIN_FREE_DISK_BYTES: u64 = 5 * 1024 * 1024 * 1024;
+
+/// The available disk space below which a preflight error is reported,
+/// used when `docker.critical_free_disk_bytes` is not specified.
+pub const DEFAULT_CRITICAL_FREE_DISK_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// The default number of seconds a runner can stay idle before it's stopped.
+pub const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 300;
+
+/// The smallest memory limit of a container Docker accepts.
+const MIN_MEMORY_LIMIT_BYTES: u64 = 6 * 1024 * 1024;
+
+/// The pattern of a machine tag, i.e. alphanumeric characters and hyphens.
+static TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z0-9-]+$").unwrap());
+
+/// The longest runner name GitHub accepts.
+const MAX_RUNNER_NAME_LEN: usize = 64;
+
+/// The API endpoint URL of GitHub.com.
+const DEFAULT_GITHUB_API_ENDPOINT_URL: &str = "https://api.github.com";
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
+    /// The log level. Default: `info`
     #[serde(default)]
     pub log_level: LogLevel,
+    /// How the queued workflow runs are distributed across the machines. Default: `round-robin`
+    #[serde(default)]
+    pub selection_strategy: SelectionStrategy,
+    /// The maximum number of machines. Default: unlimited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_machines: Option<usize>,
+    /// The file that the scaling decisions are appended to as JSON lines. Default: none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_log: Option<PathBuf>,
+    /// The tag of the machines to start the runners on. Default: all machines
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag_filter: Option<String>,
+    /// The GitHub settings.
+    #[serde(default)]
     pub github: GithubConfig,
+    /// The default settings of all machines.
     #[serde(default)]
     pub machine_defaults: MachineDefaultsConfig,
-    pub machines: Vec<MachineConfig>,
+    /// The machines that run the runner containers. At least one machine is required.
+    #[serde(default)]
+    pub machines: Vec<Arc<MachineConfig>>,
 }
 
 impl Config {
+    /// Returns whether the scaler starts new runners on the specified machine, i.e. the machine is
+    /// enabled and tagged with `tag_filter` if specified.
+    pub fn places_runners_on(&self, machine: &MachineConfig) -> bool {
+        machine.enabled.unwrap_or(true)
+            && self
+                .tag_filter
+                .as_deref()
+                .is_none_or(|tag| machine.has_tag(tag))
+    }
+
+    /// Returns a copy of this configuration that has only the machines tagged with `tag`.
+    pub fn with_machines_tagged(&self, tag: &str) -> Config {
+        Config {
+            machines: self
+                .machines
+                .iter()
+                .filter(|machine| machine.has_tag(tag))
+                .cloned()
+                .collect(),
+            ..self.clone()
+        }
+    }
+
     pub fn try_from<T: AsRef<Path> + ?Sized>(config_file: &T) -> Result<Self, ConfigError> {
         let config_file = config_file.as_ref();
-        let parsed_config: Config = match fs::read_to_string(config_file) {
-            Ok(content) => match serde_yaml_ng::from_str(content.as_str()) {
-                Ok(config) => Ok(config),
-                Err(cause) => Err(ConfigError::ParseFailure {
-                    path: config_file.to_str().unwrap().to_string(),
-                    cause,
-                }),
-            },
+        let parsed_config = Self::parse(config_file)?;
+        Self::resolve_config(
+            &ConfigResolver::from(Self::config_dir(config_file)),
+            &parsed_config,
+        )
+    }
+
+    /// Reads the configuration file and overlays the override file on top of it
+    /// using [`Config::merge`]. Note that `${file:...}` is resolved relative to
+    /// the directory of the base configuration file.
+    pub fn try_from_with_overrides<T, U>(
+        config_file: &T,
+        override_file: &U,
+    ) -> Result<Self, ConfigError>
+    where
+        T: AsRef<Path> + ?Sized,
+        U: AsRef<Path> + ?Sized,
+    {
+        let config_file = config_file.as_ref();
+        let merged_config = Self::merge(
+            Self::parse(config_file)?,
+            Self::parse(override_file.as_ref())?,
+        );
+        Self::resolve_config(
+            &ConfigResolver::from(Self::config_dir(config_file)),
+            &merged_config,
+        )
+    }
+
+    /// Reads the configuration from the specified file or URL, e.g. `config.yaml` or
+    /// `https://example.com/config.yaml`, overlaying the optional override file on top of it.
+    /// See [`Config::from_url`] for how a URL is read.
+    pub fn load(config_file: &Path, override_file: Option<&Path>) -> Result<Self, ConfigError> {
+        match (
+            config_file.to_str().and_then(parse_config_url),
+            override_file,
+        ) {
+            (Some(url), Some(override_file)) => Self::from_url_with_overrides(&url, override_file),
+            (Some(url), None) => Self::from_url(&url),
+            (None, Some(override_file)) => {
+                Self::try_from_with_overrides(config_file, override_file)
+            }
+            (None, None) => Self::try_from(config_file),
+        }
+    }
+
+    /// Serializes this configuration into YAML. Use [`Config::redacted`] first
+    /// if the output may be exposed.
+    pub fn to_yaml_string(&self) -> Result<String, serde_yaml_ng::Error> {
+        serde_yaml_ng::to_string(self)
+    }
+
+    /// Returns a copy of this configuration whose credentials are replaced with `[REDACTED]`.
+    /// The values of `docker.extra_env` are redacted as well, because they often hold secrets
+    /// such as tokens and proxy credentials.
+    pub fn redacted(&self) -> Config {
+        fn redact(value: &mut String) {
+            if !value.is_empty() {
+                *value = "[REDACTED]".to_string();
+            }
+        }
+        fn redact_ssh(ssh: &mut SshConfig) {
+            redact(&mut ssh.password);
+            redact(&mut ssh.private_key);
+            redact(&mut ssh.private_key_passphrase);
+        }
+        fn redact_docker(docker: &mut DockerConfig) {
+            docker.extra_env.values_mut().for_each(redact);
+        }
+
+        let mut config = self.clone();
+        redact(&mut config.github.personal_access_token);
+        redact_ssh(&mut config.machine_defaults.ssh);
+        redact_docker(&mut config.machine_defaults.docker);
+        for machine in &mut config.machines {
+            let machine = Arc::make_mut(machine);
+            redact_ssh(&mut machine.ssh);
+            redact_docker(&mut machine.docker);
+        }
+        config
+    }
+
+    fn parse(config_file: &Path) -> Result<Config, ConfigError> {
+        match fs::read_to_string(config_file) {
+            Ok(content) => Self::parse_str(config_file.to_str().unwrap(), &content),
             Err(cause) => Err(ConfigError::ReadFailure {
                 path: config_file.to_str().unwrap().to_string(),
                 cause,
             }),
-        }?;
+        }
+    }
 
-        let config_dir = {
-            let mut buf = config_file.to_path_buf();
-            buf.pop();
-            if buf.components().next().is_none() {
-                buf.push(PathBuf::from_str(".").unwrap());
-            }
-            buf
-        };
+    /// Parses the content of the configuration file at the specified path or URL.
+    fn parse_str(path: &str, content: &str) -> Result<Config, ConfigError> {
+        serde_yaml_ng::from_str(content).map_err(|cause| ConfigError::ParseFailure {
+            path: path.to_string(),
+            cause,
+        })
+    }
 
-        Self::resolve_config(&config_dir, &parsed_config)
+    fn config_dir(config_file: &Path) -> PathBuf {
+        let mut buf = config_file.to_path_buf();
+        buf.pop();
+        if buf.components().next().is_none() {
+            buf.push(PathBuf::from_str(".").unwrap());
+        }
+        buf
     }
 }
 
 impl Config {
-    fn resolve_config(config_dir: &PathBuf, parsed_config: &Config) -> Result<Config, ConfigError> {
-        let resolver = resolver::ConfigResolver::from(&config_dir);
-        let resolved_machine_defaults =
-            Self::resolve_machine_defaults_config(&parsed_config.machine_defaults, &resolver)?;
-        Ok(Config {
-            log_level: parsed_config.log_level,
-            github: Self::resolve_github_config(&parsed_config.github, &resolver)?,
-            machines: Self::resolve_machine_configs(
-                &resolved_machine_defaults,
+    fn resolve_config(
+        resolver: &ConfigResolver,
+        parsed_config: &Config,
+    ) -> Result<Config, ConfigError> {
+        // Keep validating after a validation failure, so that all of them are reported at once.
+        let mut failures = ValidationFailureCollector::default();
+        let github =
+            failures.collect(Self::resolve_github_config(&parsed_config.github, resolver))?;
+        let machine_defaults = failures.collect(Self::resolve_machine_defaults_config(
+            &parsed_config.machine_defaults,
+            resolver,
+        ))?;
+        // The machines can't be validated without the valid defaults.
+        let machines = match &machine_defaults {
+            Some(machine_defaults) => Self::resolve_machine_configs(
+                &parsed_config.github.runners,
+                machine_defaults,
                 &parsed_config.machines,
-                &resolver,
+                parsed_config.max_machines,
+                resolver,
+                &mut failures,
             )?,
-            machine_defaults: resolved_machine_defaults,
+            None => vec![],
+        };
+        if let Some(tag) = &parsed_config.tag_filter {
+            if !TAG_RE.is_match(tag) {
+                failures.push(format!(
+                    "An invalid tag '{}' in 'tag_filter'. A tag must contain only alphanumeric characters and '-'.",
+                    tag
+                ));
+            } else if !machines.is_empty() && !machines.iter().any(|m| m.has_tag(tag)) {
+                failures.push(format!(
+                    "No machine is tagged with '{}' in 'tag_filter'.",
+                    tag
+                ));
+            }
+        }
+        if let Some(github) = &github {
+            for machine in &machines {
+                let runner_name_len = max_runner_name_len(&github.runners, &machine.id);
+                if runner_name_len > MAX_RUNNER_NAME_LEN {
+                    failures.push(format!(
+                        "The runner names of machine '{}' can be {} characters long, but GitHub allows up to {} characters. Shorten 'github.runners.name_prefix' or 'github.runners.runner_name_template'.",
+                        machine.id, runner_name_len, MAX_RUNNER_NAME_LEN
+                    ));
+                }
+            }
+        }
+        failures.finish()?;
+
+        Ok(Config {
+            log_level: parsed_config.log_level,
+            selection_strategy: parsed_config.selection_strategy,
+            max_machines: parsed_config.max_machines,
+            audit_log: parsed_config.audit_log.clone(),
+            tag_filter: parsed_config.tag_filter.clone(),
+            // Never fails because there's no validation failure.
+            github: github.unwrap(),
+            machines,
+            machine_defaults: machine_defaults.unwrap(),
         })
     }
 
@@ -75,61 +284,95 @@ impl Config {
         c: &GithubConfig,
         r: &ConfigResolver,
     ) -> Result<GithubConfig, ConfigError> {
-        // Validate the repo URL and extract the API endpoint prefix, repo user and name from it.
+        // Validate the repo URL and extract the repo user and name from it.
         static GITHUB_REPO_URL_RE: Lazy<Regex> =
-            Lazy::new(|| Regex::new(r"^((?:http|https)://[^/]+)/([^/]+)/([^/]+)/?").unwrap());
+            Lazy::new(|| Regex::new(r"^(?:http|https)://[^/]+/([^/]+)/([^/]+)/?").unwrap());
+        let scope = r.resolve(&c.runners.scope)?;
+        let org_name = r.resolve(&c.runners.org_name)?;
         let repo_url = r.resolve(&c.runners.repo_url)?;
-        if repo_url.is_empty() {
-            return Err(ConfigError::ValidationFailure {
-                message: "An empty or missing URL in 'github.runners.repo_url'.".to_string(),
-            });
-        }
-        let (api_endpoint_url, repo_user, repo_name) =
-            if let Some(captures) = GITHUB_REPO_URL_RE.captures(repo_url.as_str()) {
-                let endpoint_prefix = captures.get(1).unwrap().as_str();
-                let repo_user = captures.get(2).unwrap().as_str();
-                let repo_name = captures.get(3).unwrap().as_str();
-                let api_endpoint_url = if endpoint_prefix.contains("://github.com") {
-                    "https://api.github.com".to_string()
-                } else {
-                    format!("{}/api/v3", endpoint_prefix)
-                };
-
-                (
-                    api_endpoint_url,
-                    repo_user.to_string(),
-                    repo_name.to_string(),
-                )
-            } else {
+        if scope == "org" {
+            static GITHUB_ORG_NAME_RE: Lazy<Regex> =
+                Lazy::new(|| Regex::new(r"^[A-Za-z0-9](?:[A-Za-z0-9-]*[A-Za-z0-9])?$").unwrap());
+            if org_name.is_empty() {
+                return Err(ConfigError::ValidationFailure {
+                    message: "An empty or missing value in 'github.runners.org_name', which is required when 'github.runners.scope' is 'org'.".to_string(),
+                });
+            }
+            if !GITHUB_ORG_NAME_RE.is_match(&org_name) {
                 return Err(ConfigError::ValidationFailure {
                     message: format!(
-                        "Not a GitHub or GHE URL '{}' in github.runners.repo_url.",
-                        repo_url
+                        "An invalid organization name '{}' in 'github.runners.org_name'.",
+                        org_name
                     ),
                 });
-            };
+            }
+        } else if repo_url.is_empty() {
+            return Err(ConfigError::ValidationFailure {
+                message: "An empty or missing URL in 'github.runners.repo_url'.".to_string(),
+            });
+        }
+        // The repo URL is optional for an 'org' scope.
+        let (repo_user, repo_name) = if repo_url.is_empty() {
+            (String::new(), String::new())
+        } else if let Some(captures) = GITHUB_REPO_URL_RE.captures(repo_url.as_str()) {
+            let repo_user = captures.get(1).unwrap().as_str();
+            let repo_name = captures.get(2).unwrap().as_str();
+            (repo_user.to_string(), repo_name.to_string())
+        } else {
+            return Err(ConfigError::ValidationFailure {
+                message: format!(
+                    "An invalid GitHub or GHE URL '{}' in 'github.runners.repo_url'.",
+                    repo_url
+                ),
+            });
+        };
+        let api_endpoint_url =
+            Self::resolve_api_endpoint_url(&r.resolve(&c.runners.api_endpoint_url)?, &repo_url)?;
 
         let config = GithubConfig {
             personal_access_token: r.resolve(&c.personal_access_token)?,
             runners: GithubRunnerConfig {
                 name_prefix: r.resolve(&c.runners.name_prefix)?,
-                scope: r.resolve(&c.runners.scope)?,
+                runner_name_template: r.resolve(&c.runners.runner_name_template)?,
+                scope,
+                org_name,
                 repo_url,
                 api_endpoint_url,
                 repo_user,
                 repo_name,
+                runner_group: Self::resolve_opt(&c.runners.runner_group, r)?,
+                use_registration_token: c.runners.use_registration_token,
+                deregister_offline: c.runners.deregister_offline,
+                work_dir: Self::resolve_opt(&c.runners.work_dir, r)?,
+                ephemeral: c.runners.ephemeral,
+                unset_config_vars: c.runners.unset_config_vars,
             },
+            max_runs: c.max_runs,
+            retry: c.retry.clone(),
+            api_version: r.resolve(&c.api_version)?,
+            timeout_secs: c.timeout_secs,
+            connect_timeout_secs: c.connect_timeout_secs,
         };
 
         // Validate the personal access token.
+        const TOKEN_PREFIXES_MESSAGE: &str = "A GitHub token must start with 'ghp_' (classic personal access token), 'github_pat_' (fine-grained personal access token), 'ghs_' (GitHub App installation access token) or 'gho_' (OAuth access token).";
         if config.personal_access_token.is_empty() {
             return Err(ConfigError::ValidationFailure {
-                message: "An empty or missing value in 'github.personal_access_token'. A GitHub personal access token must start with 'ghp_'.".to_string(),
+                message: format!(
+                    "An empty or missing value in 'github.personal_access_token'. {}",
+                    TOKEN_PREFIXES_MESSAGE
+                ),
             });
         }
-        if !config.personal_access_token.starts_with("ghp_") {
+        if !["ghp_", "github_pat_", "ghs_", "gho_"]
+            .iter()
+            .any(|prefix| config.personal_access_token.starts_with(prefix))
+        {
             return Err(ConfigError::ValidationFailure {
-                message: "An invalid value in 'github.personal_access_token'. A GitHub personal access token must start with 'ghp_'.".to_string(),
+                message: format!(
+                    "An invalid value in 'github.personal_access_token'. {}",
+                    TOKEN_PREFIXES_MESSAGE
+                ),
             });
         }
 
@@ -140,22 +383,148 @@ impl Config {
             });
         }
 
-        if config.runners.scope != "repo" {
+        // The length of the runner names is validated with each machine's ID later.
+        let literal_name_prefix = config.runners.name_prefix.replace("{machine_id}", "");
+        if !literal_name_prefix
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
             return Err(ConfigError::ValidationFailure {
-                message: format!("An unsupported value '{}' in 'github.runners.scope'. 'repo' is the only supported value at the moment.", config.runners.scope)
+                message: format!(
+                    "An invalid value '{}' in 'github.runners.name_prefix'. It must contain only alphanumeric characters, '-', '_' and '{{machine_id}}'.",
+                    config.runners.name_prefix
+                ),
+            });
+        }
+
+        Self::validate_runner_name_template(&config.runners.runner_name_template)?;
+
+        if let Some(work_dir) = &config.runners.work_dir {
+            if !work_dir.starts_with('/') || work_dir.contains('\0') {
+                return Err(ConfigError::ValidationFailure {
+                    message: format!(
+                        "An invalid value '{}' in 'github.runners.work_dir'. It must be an absolute path.",
+                        work_dir
+                    ),
+                });
+            }
+        }
+
+        if config.max_runs == Some(0) {
+            return Err(ConfigError::ValidationFailure {
+                message: "'github.max_runs' must be greater than 0.".to_string(),
+            });
+        }
+
+        static API_VERSION_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap());
+        if !API_VERSION_RE.is_match(&config.api_version) {
+            return Err(ConfigError::ValidationFailure {
+                message: format!(
+                    "An invalid value '{}' in 'github.api_version'. It must be a date such as '2022-11-28'.",
+                    config.api_version
+                ),
+            });
+        }
+
+        if config.timeout_secs == 0 || config.connect_timeout_secs == 0 {
+            return Err(ConfigError::ValidationFailure {
+                message: "'github.timeout_secs' and 'github.connect_timeout_secs' must be greater than 0.".to_string(),
+            });
+        }
+
+        if config.retry.max_delay_ms < config.retry.base_delay_ms {
+            return Err(ConfigError::ValidationFailure {
+                message: format!(
+                    "'github.retry.max_delay_ms' ({}) must not be less than 'github.retry.base_delay_ms' ({}).",
+                    config.retry.max_delay_ms, config.retry.base_delay_ms
+                ),
+            });
+        }
+
+        // Repository-level runners can't belong to a runner group.
+        if config.runners.runner_group.is_some()
+            && config.runners.scope != "org"
+            && config.runners.scope != "enterprise"
+        {
+            return Err(ConfigError::ValidationFailure {
+                message: format!("'github.runners.runner_group' requires 'github.runners.scope' to be 'org' or 'enterprise', but it is '{}'.", config.runners.scope),
+            });
+        }
+
+        if config.runners.scope != "repo" && config.runners.scope != "org" {
+            return Err(ConfigError::ValidationFailure {
+                message: format!("An unsupported value '{}' in 'github.runners.scope'. 'repo' and 'org' are the only supported values at the moment.", config.runners.scope)
             });
         }
 
         Ok(config)
     }
 
+    fn validate_runner_name_template(template: &str) -> Result<(), ConfigError> {
+        static PLACEHOLDER_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{([^}]*)}").unwrap());
+        const PLACEHOLDERS: [&str; 3] = ["prefix", "machine_id", "uuid"];
+        const UNIQUE_PLACEHOLDERS: [&str; 1] = ["uuid"];
+
+        let mut has_unique_placeholder = false;
+        for captures in PLACEHOLDER_RE.captures_iter(template) {
+            let name = captures.get(1).unwrap().as_str();
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(ConfigError::ValidationFailure {
+                    message: format!(
+                        "An unknown placeholder '{{{}}}' in 'github.runners.runner_name_template'. Supported placeholders: {}",
+                        name,
+                        PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(", ")
+                    ),
+                });
+            }
+            if UNIQUE_PLACEHOLDERS.contains(&name) {
+                has_unique_placeholder = true;
+            }
+        }
+
+        if !has_unique_placeholder {
+            return Err(ConfigError::ValidationFailure {
+                message: format!(
+                    "'github.runners.runner_name_template' must contain at least one of: {}",
+                    UNIQUE_PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(", ")
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
     fn resolve_machine_defaults_config(
         c: &MachineDefaultsConfig,
         r: &ConfigResolver,
     ) -> Result<MachineDefaultsConfig, ConfigError> {
         Ok(MachineDefaultsConfig {
             ssh: Self::resolve_default_ssh_config(&c.ssh, r)?,
-            runners: RunnersConfig { max: c.runners.max },
+            runners: RunnersConfig {
+                min: c.runners.min,
+                max: c.runners.max,
+                idle_timeout_secs: c.runners.idle_timeout_secs,
+                ephemeral: c.runners.ephemeral,
+                unset_config_vars: c.runners.unset_config_vars,
+            },
+            docker: DockerConfig {
+                network: Self::resolve_opt(&c.docker.network, r)?,
+                image: Self::resolve_opt(&c.docker.image, r)?,
+                container_name_prefix: Self::resolve_opt(&c.docker.container_name_prefix, r)?,
+                prune_older_than_hours: c.docker.prune_older_than_hours,
+                auto_prune: c.docker.auto_prune,
+                auto_prune_on_start: c.docker.auto_prune_on_start,
+                prune_keep_days: c.docker.prune_keep_days,
+                pull_policy: c.docker.pull_policy,
+                extra_env: Self::resolve_values(&c.docker.extra_env, r)?,
+                extra_labels: Self::resolve_values(&c.docker.extra_labels, r)?,
+                volumes: Self::resolve_volumes(&c.docker.volumes, r)?,
+                restart_policy: c.docker.restart_policy,
+                memory_limit_bytes: c.docker.memory_limit_bytes,
+                min_free_disk_bytes: c.docker.min_free_disk_bytes,
+                critical_free_disk_bytes: c.docker.critical_free_disk_bytes,
+            },
         })
     }
 
@@ -175,31 +544,142 @@ impl Config {
             password: r.resolve(&c.password)?,
             private_key: r.resolve(&c.private_key)?,
             private_key_passphrase: r.resolve(&c.private_key_passphrase)?,
+            certificate: Self::resolve_opt(&c.certificate, r)?,
+            connect_timeout_secs: c.connect_timeout_secs,
+            command_timeout_secs: c.command_timeout_secs,
+            max_retries: c.max_retries,
+            retry_base_delay_ms: c.retry_base_delay_ms,
+            agent_socket: Self::resolve_opt(&c.agent_socket, r)?,
+            host_key_algorithm: Self::resolve_opt(&c.host_key_algorithm, r)?,
+            known_hosts_file: Self::resolve_opt(&c.known_hosts_file, r)?,
+            bind_address: Self::resolve_opt(&c.bind_address, r)?,
+            host_prefix: r.resolve(&c.host_prefix)?,
+            host_suffix: r.resolve(&c.host_suffix)?,
+            compression: c.compression,
+            use_setenv: c.use_setenv,
+            server_alive_interval_secs: c.server_alive_interval_secs,
         })
     }
 
     fn resolve_machine_configs(
+        github_runners: &GithubRunnerConfig,
         defaults: &MachineDefaultsConfig,
-        cfgs: &Vec<MachineConfig>,
+        cfgs: &Vec<Arc<MachineConfig>>,
+        max_machines: Option<usize>,
         r: &ConfigResolver,
-    ) -> Result<Vec<MachineConfig>, ConfigError> {
-        let mut out: Vec<MachineConfig> = vec![];
-        let mut id_generator = MachineIdGenerator::new(cfgs)?;
+        failures: &mut ValidationFailureCollector,
+    ) -> Result<Vec<Arc<MachineConfig>>, ConfigError> {
+        if cfgs.is_empty() {
+            failures.push("There must be at least one machine in the configuration.".to_string());
+        } else if cfgs.iter().all(|c| c.enabled == Some(false)) {
+            failures.push("At least one machine must be enabled in the configuration.".to_string());
+        }
+
+        if let Some(max_machines) = max_machines {
+            if cfgs.len() > max_machines {
+                failures.push(format!(
+                    "There are {} machines in the configuration, exceeding 'max_machines' ({}).",
+                    cfgs.len(),
+                    max_machines
+                ));
+            }
+        }
+
+        // The IDs of the machines are ambiguous if there's a duplicate one.
+        let Some(mut id_generator) = failures.collect(MachineIdGenerator::new(cfgs, r))? else {
+            return Ok(vec![]);
+        };
+
+        // Share the resolved configurations, so that a `Machine` doesn't have to copy
+        // its private key, etc.
+        let mut out: Vec<Arc<MachineConfig>> = vec![];
         for c in cfgs {
             let id = id_generator.generate(c, r)?;
-            let ssh = Self::resolve_ssh_config(&id, &defaults.ssh, &c.ssh, r)?;
-            let runners = Self::resolve_runners_config(&defaults.runners, &c.runners)?;
-            out.push(MachineConfig { id, ssh, runners })
+            let ssh = failures.collect(Self::resolve_ssh_config(&id, &defaults.ssh, &c.ssh, r))?;
+            let runners = failures.collect(Self::resolve_runners_config(
+                &id,
+                github_runners,
+                &defaults.runners,
+                &c.runners,
+            ))?;
+            let docker = failures.collect(Self::resolve_docker_config(
+                &id,
+                &defaults.docker,
+                &c.docker,
+                r,
+            ))?;
+            let labels = failures.collect(Self::resolve_labels(&id, &c.labels, r))?;
+            let tags = failures.collect(Self::resolve_tags(&id, &c.tags, r))?;
+            if let (Some(ssh), Some(runners), Some(docker), Some(labels), Some(tags)) =
+                (ssh, runners, docker, labels, tags)
+            {
+                if docker.restart_policy == Some(RestartPolicy::UnlessStopped)
+                    && runners.ephemeral == Some(true)
+                {
+                    warn!(
+                        "Machine '{}' uses the 'unless-stopped' restart policy, but a restarted runner container won't be registered again because the runners are ephemeral.",
+                        id
+                    );
+                }
+                out.push(Arc::new(MachineConfig {
+                    id,
+                    ssh,
+                    runners,
+                    docker,
+                    labels,
+                    weight: if c.weight != 0 { c.weight } else { 1 },
+                    enabled: Some(c.enabled.unwrap_or(true)),
+                    tags,
+                }))
+            }
         }
 
-        if out.is_empty() {
-            Err(ConfigError::ValidationFailure {
-                message: "There must be at least one machine in the configuration.".to_string(),
-            })
-        } else {
-            out.sort_by(|a, b| a.id.cmp(&b.id));
-            Ok(out)
+        out.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(out)
+    }
+
+    /// Returns the specified API endpoint URL, or infers it from the repo URL if unspecified,
+    /// i.e. `https://api.github.com` for GitHub.com and `https://<host>/api/v3` for GHE.
+    fn resolve_api_endpoint_url(
+        api_endpoint_url: &str,
+        repo_url: &str,
+    ) -> Result<String, ConfigError> {
+        if !api_endpoint_url.is_empty() {
+            if Url::parse(api_endpoint_url).is_err() {
+                return Err(ConfigError::ValidationFailure {
+                    message: format!(
+                        "An invalid URL '{}' in 'github.runners.api_endpoint_url'.",
+                        api_endpoint_url
+                    ),
+                });
+            }
+            return Ok(api_endpoint_url.trim_end_matches('/').to_string());
+        }
+
+        // Assume GitHub.com if there's no repo URL to infer from, which is allowed for an 'org' scope.
+        if repo_url.is_empty() {
+            return Ok(DEFAULT_GITHUB_API_ENDPOINT_URL.to_string());
         }
+
+        let parsed_repo_url =
+            Url::parse(repo_url).map_err(|err| ConfigError::ValidationFailure {
+                message: format!(
+                    "An invalid GitHub or GHE URL '{}' in 'github.runners.repo_url': {}",
+                    repo_url, err
+                ),
+            })?;
+        let inferred = match parsed_repo_url.host_str() {
+            Some("github.com") | Some("www.github.com") => {
+                DEFAULT_GITHUB_API_ENDPOINT_URL.to_string()
+            }
+            _ => format!("{}/api/v3", parsed_repo_url.origin().ascii_serialization()),
+        };
+
+        warn!(
+            "'github.runners.api_endpoint_url' is not specified; using '{}' inferred from 'github.runners.repo_url'.",
+            inferred
+        );
+        Ok(inferred)
     }
 
     fn resolve_ssh_config(
@@ -213,7 +693,7 @@ impl Config {
         // 2) A per-machine password
         // 3) The default private key
         // 4) The default password
-        let password_or_private_key: (&str, &str, &str) = {
+        let password_or_private_key: (&str, &str, &str, Option<&String>) = {
             if !c.private_key.is_empty() {
                 if !c.password.is_empty() {
                     warn!(
@@ -225,31 +705,52 @@ impl Config {
                     "",
                     c.private_key.as_str(),
                     c.private_key_passphrase.as_str(),
+                    // Don't use the default certificate, which was issued for another key.
+                    c.certificate.as_ref(),
                 )
             } else if !c.password.is_empty() {
-                (c.password.as_str(), "", "")
+                (c.password.as_str(), "", "", None)
             } else if !defaults.private_key.is_empty() {
                 (
                     "",
                     defaults.private_key.as_str(),
                     defaults.private_key_passphrase.as_str(),
+                    c.certificate.as_ref().or(defaults.certificate.as_ref()),
                 )
             } else {
-                (defaults.password.as_str(), "", "")
+                (defaults.password.as_str(), "", "", None)
             }
         };
 
+        if c.certificate.is_some() && password_or_private_key.1.is_empty() {
+            return Err(ConfigError::ValidationFailure {
+                message: format!(
+                    "'certificate' requires 'private_key' for machine '{}'.",
+                    machine_id
+                ),
+            });
+        }
+
+        if !c.host_prefix.is_empty() || !c.host_suffix.is_empty() {
+            warn!(
+                "'host_prefix' and 'host_suffix' will be ignored for machine '{}'; specify them in 'machine_defaults'.",
+                machine_id
+            );
+        }
+
+        let host = r.resolve_or_else(&c.host, || {
+            let fallback = defaults.host.clone();
+            if fallback.is_empty() {
+                Err(ConfigError::ValidationFailure {
+                    message: format!("'host' must be specified for machine '{}'.", machine_id),
+                })
+            } else {
+                Ok(fallback)
+            }
+        })?;
+
         let resolved = SshConfig {
-            host: r.resolve_or_else(&c.host, || {
-                let fallback = defaults.host.clone();
-                if fallback.is_empty() {
-                    Err(ConfigError::ValidationFailure {
-                        message: format!("'host' must be specified for machine '{}'.", machine_id),
-                    })
-                } else {
-                    Ok(fallback)
-                }
-            })?,
+            host: Self::complete_host(machine_id, host, defaults),
             port: if c.port != 0 {
                 c.port
             } else if defaults.port != 0 {
@@ -275,13 +776,110 @@ impl Config {
             password: r.resolve(password_or_private_key.0)?,
             private_key: r.resolve(password_or_private_key.1)?,
             private_key_passphrase: r.resolve(password_or_private_key.2)?,
+            certificate: match password_or_private_key.3 {
+                Some(certificate) => Some(r.resolve(certificate)?),
+                None => None,
+            },
+            connect_timeout_secs: if c.connect_timeout_secs != 0 {
+                c.connect_timeout_secs
+            } else if defaults.connect_timeout_secs != 0 {
+                defaults.connect_timeout_secs
+            } else {
+                30
+            },
+            command_timeout_secs: if c.command_timeout_secs != 0 {
+                c.command_timeout_secs
+            } else if defaults.command_timeout_secs != 0 {
+                defaults.command_timeout_secs
+            } else {
+                60
+            },
+            // Unlike other numeric fields, 0 is a valid value that disables retries.
+            max_retries: Some(c.max_retries.or(defaults.max_retries).unwrap_or(3)),
+            retry_base_delay_ms: if c.retry_base_delay_ms != 0 {
+                c.retry_base_delay_ms
+            } else if defaults.retry_base_delay_ms != 0 {
+                defaults.retry_base_delay_ms
+            } else {
+                1000
+            },
+            agent_socket: match Self::resolve_opt(&c.agent_socket, r)? {
+                Some(agent_socket) => Some(agent_socket),
+                None => defaults.agent_socket.clone(),
+            },
+            host_key_algorithm: match Self::resolve_opt(&c.host_key_algorithm, r)? {
+                Some(host_key_algorithm) => Some(host_key_algorithm),
+                None => defaults.host_key_algorithm.clone(),
+            },
+            known_hosts_file: match Self::resolve_opt(&c.known_hosts_file, r)? {
+                Some(known_hosts_file) => Some(known_hosts_file),
+                None => defaults.known_hosts_file.clone(),
+            },
+            bind_address: match Self::resolve_opt(&c.bind_address, r)? {
+                Some(bind_address) => Some(bind_address),
+                None => defaults.bind_address.clone(),
+            },
+            host_prefix: "".to_string(),
+            host_suffix: "".to_string(),
+            compression: Some(c.compression.or(defaults.compression).unwrap_or(false)),
+            use_setenv: Some(c.use_setenv.or(defaults.use_setenv).unwrap_or(false)),
+            // 0 is a valid value that disables keepalive messages.
+            server_alive_interval_secs: Some(
+                c.server_alive_interval_secs
+                    .or(defaults.server_alive_interval_secs)
+                    .unwrap_or(30),
+            ),
         };
 
-        // Ensure password or private key is specified.
-        if resolved.password.is_empty() && resolved.private_key.is_empty() {
+        Self::validate_private_key(machine_id, &resolved.private_key)?;
+
+        // Ensure only the algorithms known to libssh2 are specified.
+        if let Some(host_key_algorithm) = &resolved.host_key_algorithm {
+            const HOST_KEY_ALGORITHMS: [&str; 10] = [
+                "ssh-ed25519",
+                "ecdsa-sha2-nistp256",
+                "ecdsa-sha2-nistp384",
+                "ecdsa-sha2-nistp521",
+                "rsa-sha2-512",
+                "rsa-sha2-256",
+                "ssh-rsa",
+                "ssh-dss",
+                "ssh-ed25519-cert-v01@openssh.com",
+                "ssh-rsa-cert-v01@openssh.com",
+            ];
+            for algorithm in host_key_algorithm.split(',') {
+                if !HOST_KEY_ALGORITHMS.contains(&algorithm.trim()) {
+                    return Err(ConfigError::ValidationFailure {
+                        message: format!(
+                            "An unknown host key algorithm '{}' in 'host_key_algorithm' for machine '{}'.",
+                            algorithm, machine_id
+                        ),
+                    });
+                }
+            }
+        }
+
+        // Accept only an IP address because a host name may resolve to an address
+        // of another interface.
+        if let Some(bind_address) = &resolved.bind_address {
+            if bind_address.parse::<IpAddr>().is_err() {
+                return Err(ConfigError::ValidationFailure {
+                    message: format!(
+                        "An invalid IP address '{}' in 'bind_address' for machine '{}'.",
+                        bind_address, machine_id
+                    ),
+                });
+            }
+        }
+
+        // Ensure password, private key or agent socket is specified.
+        if resolved.password.is_empty()
+            && resolved.private_key.is_empty()
+            && resolved.agent_socket.is_none()
+        {
             return Err(ConfigError::ValidationFailure {
                 message: format!(
-                    "'password' or 'private_key' must be specified for machine '{}'.",
+                    "'password', 'private_key' or 'agent_socket' must be specified for machine '{}'.",
                     machine_id
                 ),
             });
@@ -290,12 +888,338 @@ impl Config {
         Ok(resolved)
     }
 
+    /// Completes a short host name such as `runner-01` with the default `host_prefix` and
+    /// `host_suffix`. Full host names and IP addresses are left as they are.
+    fn complete_host(machine_id: &str, host: String, defaults: &SshConfig) -> String {
+        if defaults.host_prefix.is_empty() && defaults.host_suffix.is_empty() {
+            return host;
+        }
+
+        if host.parse::<IpAddr>().is_ok() {
+            host
+        } else if host.contains('.') {
+            warn!(
+                "'host_prefix' and 'host_suffix' will be ignored for machine '{}' because its 'host' is a full host name: {}",
+                machine_id, host
+            );
+            host
+        } else {
+            format!("{}{}{}", defaults.host_prefix, host, defaults.host_suffix)
+        }
+    }
+
+    /// Ensures the private key is a PEM block rather than a path to a private key file.
+    fn validate_private_key(machine_id: &str, private_key: &str) -> Result<(), ConfigError> {
+        let private_key = private_key.trim();
+        if ["/", "./", "~/"]
+            .iter()
+            .any(|prefix| private_key.starts_with(prefix))
+        {
+            return Err(ConfigError::ValidationFailure {
+                message: format!(
+                    "'private_key' for machine '{}' looks like a file path. Use '${{file:{}}}' to read the private key from a file.",
+                    machine_id, private_key
+                ),
+            });
+        }
+
+        static PEM_FOOTER_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"-----END [A-Z0-9 ]+-----$").unwrap());
+        if private_key.starts_with("-----BEGIN") && !PEM_FOOTER_RE.is_match(private_key) {
+            return Err(ConfigError::ValidationFailure {
+                message: format!(
+                    "'private_key' for machine '{}' is not a complete PEM block; its '-----END ...-----' footer is missing.",
+                    machine_id
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn resolve_docker_config(
+        machine_id: &str,
+        defaults: &DockerConfig,
+        c: &DockerConfig,
+        r: &ConfigResolver,
+    ) -> Result<DockerConfig, ConfigError> {
+        let network = match Self::resolve_opt(&c.network, r)? {
+            Some(network) => Some(network),
+            None => defaults.network.clone(),
+        };
+
+        if network.as_deref() == Some("host") {
+            warn!(
+                "Machine '{}' uses the 'host' network, which grants the runner containers access to the host network stack.",
+                machine_id
+            );
+        }
+
+        // Validate the image reference, e.g. 'ghcr.io/foo/bar:tag' or 'foo/bar@sha256:...'.
+        static DOCKER_IMAGE_RE: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"^(?:[a-zA-Z0-9.-]+(?::[0-9]+)?/)?[a-z0-9]+(?:[._-][a-z0-9]+)*(?:/[a-z0-9]+(?:[._-][a-z0-9]+)*)*(?::\w[\w.-]{0,127})?(?:@sha256:[0-9a-f]{64})?$").unwrap()
+        });
+        let image = match Self::resolve_opt(&c.image, r)? {
+            Some(image) => Some(image),
+            None => defaults.image.clone(),
+        };
+        if let Some(image) = &image {
+            if !DOCKER_IMAGE_RE.is_match(image) {
+                return Err(ConfigError::ValidationFailure {
+                    message: format!(
+                        "An invalid Docker image '{}' in 'docker.image' for machine '{}'.",
+                        image, machine_id
+                    ),
+                });
+            }
+        }
+
+        // Docker allows only '[a-zA-Z0-9][a-zA-Z0-9_.-]*' for a container name, but we allow
+        // only hyphens to keep the prefix apart from the '-' and the UUID that follow it.
+        static CONTAINER_NAME_PREFIX_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"^[A-Za-z0-9][A-Za-z0-9-]*$").unwrap());
+        let container_name_prefix = match Self::resolve_opt(&c.container_name_prefix, r)? {
+            Some(prefix) => Some(prefix),
+            None => defaults.container_name_prefix.clone(),
+        };
+        if let Some(prefix) = &container_name_prefix {
+            if !CONTAINER_NAME_PREFIX_RE.is_match(prefix) {
+                return Err(ConfigError::ValidationFailure {
+                    message: format!(
+                        "An invalid value '{}' in 'docker.container_name_prefix' for machine '{}'. It must contain only alphanumeric characters and '-', and must not start with '-'.",
+                        prefix, machine_id
+                    ),
+                });
+            }
+        }
+
+        // The machine-level variables override the default ones with the same names.
+        let mut extra_env = defaults.extra_env.clone();
+        extra_env.extend(Self::resolve_values(&c.extra_env, r)?);
+        static ENV_NAME_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+        if let Some(name) = extra_env.keys().find(|name| !ENV_NAME_RE.is_match(name)) {
+            return Err(ConfigError::ValidationFailure {
+                message: format!(
+                    "An invalid environment variable name '{}' in 'docker.extra_env' for machine '{}'.",
+                    name, machine_id
+                ),
+            });
+        }
+
+        // The machine-level labels override the default ones with the same keys.
+        let mut extra_labels = defaults.extra_labels.clone();
+        extra_labels.extend(Self::resolve_values(&c.extra_labels, r)?);
+        // Lowercase alphanumerics separated by a single '.' or '-', as recommended by Docker.
+        static LABEL_KEY_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"^[a-z0-9]+(?:[.-][a-z0-9]+)*$").unwrap());
+        let mut label_keys: Vec<&String> = extra_labels.keys().collect();
+        label_keys.sort();
+        for key in label_keys {
+            if !LABEL_KEY_RE.is_match(key) {
+                return Err(ConfigError::ValidationFailure {
+                    message: format!(
+                        "An invalid label key '{}' in 'docker.extra_labels' for machine '{}'.",
+                        key, machine_id
+                    ),
+                });
+            }
+            if key.starts_with("github-") {
+                return Err(ConfigError::ValidationFailure {
+                    message: format!(
+                        "A reserved label key '{}' in 'docker.extra_labels' for machine '{}'. A key must not start with 'github-'.",
+                        key, machine_id
+                    ),
+                });
+            }
+        }
+
+        // Use the default volumes only if no volumes are specified for the machine.
+        let volumes = if c.volumes.is_empty() {
+            defaults.volumes.clone()
+        } else {
+            Self::resolve_volumes(&c.volumes, r)?
+        };
+        for volume in &volumes {
+            for (field, path) in [
+                ("host_path", &volume.host_path),
+                ("container_path", &volume.container_path),
+            ] {
+                if !path.starts_with('/') {
+                    return Err(ConfigError::ValidationFailure {
+                        message: format!(
+                            "A non-absolute path '{}' in 'docker.volumes[].{}' for machine '{}'.",
+                            path, field, machine_id
+                        ),
+                    });
+                }
+            }
+        }
+
+        let restart_policy = c
+            .restart_policy
+            .or(defaults.restart_policy)
+            .unwrap_or_default();
+        let memory_limit_bytes = c.memory_limit_bytes.or(defaults.memory_limit_bytes);
+        if let Some(memory_limit_bytes) = memory_limit_bytes {
+            if memory_limit_bytes < MIN_MEMORY_LIMIT_BYTES {
+                return Err(ConfigError::ValidationFailure {
+                    message: format!(
+                        "'docker.memory_limit_bytes' ({}) must be at least {} for machine '{}'.",
+                        memory_limit_bytes, MIN_MEMORY_LIMIT_BYTES, machine_id
+                    ),
+                });
+            }
+        }
+
+        let min_free_disk_bytes = c
+            .min_free_disk_bytes
+            .or(defaults.min_free_disk_bytes)
+            .unwrap_or(DEFAULT_MIN_FREE_DISK_BYTES);
+        let critical_free_disk_bytes = c
+            .critical_free_disk_bytes
+            .or(defaults.critical_free_disk_bytes)
+            .unwrap_or(DEFAULT_CRITICAL_FREE_DISK_BYTES);
+        if critical_free_disk_bytes > min_free_disk_bytes {
+            return Err(ConfigError::ValidationFailure {
+                message: format!(
+                    "'docker.critical_free_disk_bytes' ({}) must not be greater than 'docker.min_free_disk_bytes' ({}) for machine '{}'.",
+                    critical_free_disk_bytes, min_free_disk_bytes, machine_id
+                ),
+            });
+        }
+
+        Ok(DockerConfig {
+            network,
+            image,
+            container_name_prefix,
+            prune_older_than_hours: if c.prune_older_than_hours != 0 {
+                c.prune_older_than_hours
+            } else if defaults.prune_older_than_hours != 0 {
+                defaults.prune_older_than_hours
+            } else {
+                48
+            },
+            auto_prune: Some(c.auto_prune.or(defaults.auto_prune).unwrap_or(false)),
+            auto_prune_on_start: Some(
+                c.auto_prune_on_start
+                    .or(defaults.auto_prune_on_start)
+                    .unwrap_or(false),
+            ),
+            prune_keep_days: if c.prune_keep_days != 0 {
+                c.prune_keep_days
+            } else if defaults.prune_keep_days != 0 {
+                defaults.prune_keep_days
+            } else {
+                7
+            },
+            pull_policy: Some(c.pull_policy.or(defaults.pull_policy).unwrap_or_default()),
+            extra_env,
+            extra_labels,
+            volumes,
+            restart_policy: Some(restart_policy),
+            memory_limit_bytes,
+            min_free_disk_bytes: Some(min_free_disk_bytes),
+            critical_free_disk_bytes: Some(critical_free_disk_bytes),
+        })
+    }
+
+    fn resolve_values(
+        values: &HashMap<String, String>,
+        r: &ConfigResolver,
+    ) -> Result<HashMap<String, String>, ConfigError> {
+        let mut out = HashMap::with_capacity(values.len());
+        for (name, value) in values {
+            out.insert(name.clone(), r.resolve(value)?);
+        }
+        Ok(out)
+    }
+
+    fn resolve_volumes(
+        volumes: &[VolumeMount],
+        r: &ConfigResolver,
+    ) -> Result<Vec<VolumeMount>, ConfigError> {
+        let mut out = Vec::with_capacity(volumes.len());
+        for volume in volumes {
+            out.push(VolumeMount {
+                host_path: r.resolve(&volume.host_path)?,
+                container_path: r.resolve(&volume.container_path)?,
+                read_only: volume.read_only,
+            });
+        }
+        Ok(out)
+    }
+
+    /// Resolves an optional value, treating an empty value as unspecified.
+    fn resolve_opt(
+        value: &Option<String>,
+        r: &ConfigResolver,
+    ) -> Result<Option<String>, ConfigError> {
+        match value {
+            Some(value) => {
+                let resolved = r.resolve(value)?;
+                Ok(if resolved.is_empty() {
+                    None
+                } else {
+                    Some(resolved)
+                })
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn resolve_labels(
+        machine_id: &str,
+        labels: &Vec<String>,
+        r: &ConfigResolver,
+    ) -> Result<Vec<String>, ConfigError> {
+        let mut out: Vec<String> = vec![];
+        for label in labels {
+            let label = r.resolve(label)?;
+            if label.is_empty() || label.contains(',') {
+                return Err(ConfigError::ValidationFailure {
+                    message: format!(
+                        "An empty or invalid label '{}' for machine '{}'. A label must not be empty or contain a comma.",
+                        label, machine_id
+                    ),
+                });
+            }
+            out.push(label);
+        }
+        Ok(out)
+    }
+
+    fn resolve_tags(
+        machine_id: &str,
+        tags: &Vec<String>,
+        r: &ConfigResolver,
+    ) -> Result<Vec<String>, ConfigError> {
+        let mut out: Vec<String> = vec![];
+        for tag in tags {
+            let tag = r.resolve(tag)?;
+            if !TAG_RE.is_match(&tag) {
+                return Err(ConfigError::ValidationFailure {
+                    message: format!(
+                        "An invalid tag '{}' for machine '{}'. A tag must contain only alphanumeric characters and '-'.",
+                        tag, machine_id
+                    ),
+                });
+            }
+            out.push(tag);
+        }
+        Ok(out)
+    }
+
     fn resolve_runners_config(
+        machine_id: &str,
+        github_runners: &GithubRunnerConfig,
         defaults: &RunnersConfig,
         c: &RunnersConfig,
     ) -> Result<RunnersConfig, ConfigError> {
         let default_max_runners = 16;
-        Ok(RunnersConfig {
+        let resolved = RunnersConfig {
+            // Unlike 'max', 0 is a valid value that keeps no idle runners.
+            min: Some(c.min.or(defaults.min).unwrap_or(0)),
             max: if c.max != 0 {
                 c.max
             } else if defaults.max != 0 {
@@ -303,13 +1227,49 @@ impl Config {
             } else {
                 default_max_runners
             },
-        })
+            idle_timeout_secs: if c.idle_timeout_secs != 0 {
+                c.idle_timeout_secs
+            } else if defaults.idle_timeout_secs != 0 {
+                defaults.idle_timeout_secs
+            } else {
+                DEFAULT_IDLE_TIMEOUT_SECS
+            },
+            ephemeral: Some(
+                c.ephemeral
+                    .or(defaults.ephemeral)
+                    .unwrap_or(github_runners.ephemeral),
+            ),
+            unset_config_vars: Some(
+                c.unset_config_vars
+                    .or(defaults.unset_config_vars)
+                    .unwrap_or(github_runners.unset_config_vars),
+            ),
+        };
+
+        if resolved.min.unwrap_or(0) > resolved.max {
+            return Err(ConfigError::ValidationFailure {
+                message: format!(
+                    "'runners.min' must not be greater than 'runners.max' for machine '{}'.",
+                    machine_id
+                ),
+            });
+        }
+
+        if resolved.ephemeral == Some(false) && resolved.min == Some(0) {
+            warn!(
+                "'runners.ephemeral' is false but 'runners.min' is 0 for machine '{}'; \
+                 the persistent runners will be stopped as soon as they become idle.",
+                machine_id
+            );
+        }
+
+        Ok(resolved)
     }
 }
 
-#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 #[serde(deny_unknown_fields)]
-#[derive(Default)]
+#[derive(Default, JsonSchema)]
 pub enum LogLevel {
     #[serde(rename = "trace")]
     Trace,
@@ -334,12 +1294,57 @@ impl LogLevel {
     }
 }
 
-#[derive(Clone, Deserialize, PartialEq)]
+/// How the queued workflow runs are distributed across the machines.
+#[derive(Copy, Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SelectionStrategy {
+    /// Assigns the runs to the machines in turn, in proportion to their weights.
+    #[default]
+    RoundRobin,
+    /// Assigns a run to a random machine, with a probability proportional to its weight.
+    WeightedRandom,
+    /// Assigns a run to the machine with the fewest running containers.
+    LeastLoaded,
+}
+
+#[derive(Clone, Deserialize, JsonSchema, Serialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct GithubConfig {
-    #[serde(default)]
+    /// The GitHub personal access token, e.g. `ghp_...`, `github_pat_...`, `ghs_...` or `gho_...`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub personal_access_token: String,
+    /// The settings of the self-hosted runners.
+    #[serde(default)]
     pub runners: GithubRunnerConfig,
+    /// The maximum number of queued workflow runs to handle at once. Default: unlimited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_runs: Option<usize>,
+    /// The retries of the API requests that failed with a connection error, a timeout or a 5xx response.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// The version of the GitHub REST API to request, e.g. `2022-11-28`. Default: `2022-11-28`
+    #[serde(default = "default_github_api_version")]
+    pub api_version: String,
+    /// The timeout of an API request in seconds, including connecting and reading the response. Default: 10
+    #[serde(default = "default_github_timeout_secs")]
+    pub timeout_secs: u64,
+    /// The timeout of connecting to the API server in seconds. Default: 5
+    #[serde(default = "default_github_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+}
+
+impl Default for GithubConfig {
+    fn default() -> Self {
+        GithubConfig {
+            personal_access_token: "".to_string(),
+            runners: GithubRunnerConfig::default(),
+            max_runs: None,
+            retry: RetryConfig::default(),
+            api_version: default_github_api_version(),
+            timeout_secs: default_github_timeout_secs(),
+            connect_timeout_secs: default_github_connect_timeout_secs(),
+        }
+    }
 }
 
 impl Debug for GithubConfig {
@@ -350,65 +1355,233 @@ impl Debug for GithubConfig {
                 mask_credential(&self.personal_access_token),
             )
             .field("runners", &self.runners)
+            .field("max_runs", &self.max_runs)
+            .field("retry", &self.retry)
+            .field("api_version", &self.api_version)
+            .field("timeout_secs", &self.timeout_secs)
+            .field("connect_timeout_secs", &self.connect_timeout_secs)
             .finish()
     }
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    /// The maximum number of retries. Default: 3
+    #[serde(default = "default_retry_max_retries")]
+    pub max_retries: u32,
+    /// The delay before the first retry in milliseconds, doubled on every retry with jitter. Default: 1000
+    #[serde(default = "default_retry_base_delay_ms")]
+    pub base_delay_ms: u64,
+    /// The maximum delay between retries in milliseconds. Default: 30000
+    #[serde(default = "default_retry_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_retries: default_retry_max_retries(),
+            base_delay_ms: default_retry_base_delay_ms(),
+            max_delay_ms: default_retry_max_delay_ms(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct GithubRunnerConfig {
-    #[serde(default = "default_github_runner_name_prefix")]
+    /// The prefix of the runner names, i.e. `{prefix}` in `runner_name_template`. Default: `runner`
+    #[serde(
+        default = "default_github_runner_name_prefix",
+        skip_serializing_if = "String::is_empty"
+    )]
     pub name_prefix: String,
-    #[serde(default = "default_github_runner_scope")]
+    /// The template of the runner names, which must contain `{uuid}`. Default: `{prefix}-{uuid}`
+    #[serde(
+        default = "default_github_runner_name_template",
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub runner_name_template: String,
+    /// The scope of the runners, i.e. `repo` or `org`. Default: `repo`
+    #[serde(
+        default = "default_github_runner_scope",
+        skip_serializing_if = "String::is_empty"
+    )]
     pub scope: String,
-    #[serde(default)]
+    /// The name of the organization, e.g. `foo`. Required if `scope` is `org`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub org_name: String,
+    /// The URL of the repository, e.g. `https://github.com/foo/bar`. Required if `scope` is `repo`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub repo_url: String,
-    #[serde(skip_deserializing)]
+    /// The URL of the GitHub API endpoint. Default: inferred from `repo_url`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub api_endpoint_url: String,
-    #[serde(skip_deserializing)]
+    #[serde(skip)]
     pub repo_user: String,
-    #[serde(skip_deserializing)]
+    #[serde(skip)]
     pub repo_name: String,
+    /// The runner group. Requires `scope` of `org` or `enterprise`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runner_group: Option<String>,
+    /// Whether to pass a short-lived registration token instead of the personal access token to the runners. Default: `false`
+    #[serde(default)]
+    pub use_registration_token: bool,
+    /// Whether to deregister the offline runners of a machine once per scaling pass before starting the runners on it, so that their names are never taken. Default: `false`
+    #[serde(default)]
+    pub deregister_offline: bool,
+    /// The absolute path of the runners' work directory. Default: the image's default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub work_dir: Option<String>,
+    /// Whether a runner exits after running a job. Default: `true`
+    #[serde(default = "default_true")]
+    pub ephemeral: bool,
+    /// Whether to unset the configuration variables such as the token in the runners. Default: `true`
+    #[serde(default = "default_true")]
+    pub unset_config_vars: bool,
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+impl Default for GithubRunnerConfig {
+    fn default() -> Self {
+        GithubRunnerConfig {
+            name_prefix: default_github_runner_name_prefix(),
+            runner_name_template: default_github_runner_name_template(),
+            scope: default_github_runner_scope(),
+            org_name: "".to_string(),
+            repo_url: "".to_string(),
+            api_endpoint_url: "".to_string(),
+            repo_user: "".to_string(),
+            repo_name: "".to_string(),
+            runner_group: None,
+            use_registration_token: false,
+            deregister_offline: false,
+            work_dir: None,
+            ephemeral: true,
+            unset_config_vars: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 #[derive(Default)]
 pub struct MachineDefaultsConfig {
+    /// The default SSH settings.
     #[serde(default)]
     pub ssh: SshConfig,
+    /// The default runner settings.
     #[serde(default)]
     pub runners: RunnersConfig,
+    /// The default Docker settings.
+    #[serde(default)]
+    pub docker: DockerConfig,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct MachineConfig {
-    #[serde(default)]
+    /// The unique ID of the machine. Default: generated from `ssh.host`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub id: String,
+    /// The SSH settings.
     #[serde(default)]
     pub ssh: SshConfig,
+    /// The runner settings.
     #[serde(default)]
     pub runners: RunnersConfig,
+    /// The Docker settings.
+    #[serde(default)]
+    pub docker: DockerConfig,
+    /// The labels of the machine, which must include all the labels in a job's `runs-on` except `self-hosted`, `linux` and `x64`.
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// The relative share of the runners this machine receives. Default: 1
+    #[serde(default)]
+    pub weight: u32,
+    /// Whether to start new runners on this machine. Default: `true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// The tags that group the machines by capability, e.g. `gpu` or `arm64`, unlike `labels` not registered with GitHub.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl MachineConfig {
+    /// Returns whether this machine is tagged with the specified tag.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
 }
 
-#[derive(Clone, Deserialize, PartialEq)]
+#[derive(Clone, Deserialize, JsonSchema, Serialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct SshConfig {
-    #[serde(default)]
+    /// The IP address or host name.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub host: String,
+    /// The port number. Default: 22
     #[serde(default)]
     pub port: u16,
-    #[serde(default)]
+    /// The MD5 (`12:34:...`) or SHA-256 (`SHA256:...`) host key fingerprint to verify.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub fingerprint: String,
-    #[serde(default)]
+    /// The user name.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub username: String,
-    #[serde(default)]
+    /// The password.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub password: String,
-    #[serde(default)]
+    /// The private key.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub private_key: String,
-    #[serde(default)]
+    /// The passphrase of `private_key`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub private_key_passphrase: String,
+    /// The OpenSSH certificate signed by a certificate authority for `private_key`,
+    /// e.g. the content of `id_ed25519-cert.pub`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<String>,
+    /// The connection timeout in seconds. Default: 30
+    #[serde(default)]
+    pub connect_timeout_secs: u64,
+    /// The time limit of every remote command in seconds. Default: 60
+    #[serde(default)]
+    pub command_timeout_secs: u64,
+    /// The maximum number of connection retries. Default: 3
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>,
+    /// The delay before the first connection retry in milliseconds, doubled on every retry. Default: 1000
+    #[serde(default)]
+    pub retry_base_delay_ms: u64,
+    /// The ssh-agent socket to authenticate with, which takes precedence over `password` and `private_key`.
+    /// Must be the same as `SSH_AUTH_SOCK` of this process.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_socket: Option<String>,
+    /// The preferred host key algorithms in order, e.g. `ssh-ed25519,ecdsa-sha2-nistp256`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_key_algorithm: Option<String>,
+    /// The OpenSSH `known_hosts` file to verify the host key with, e.g. `${env:HOME}/.ssh/known_hosts`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub known_hosts_file: Option<String>,
+    /// The local IP address to connect from, e.g. `192.168.0.10`. Default: chosen by the OS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind_address: Option<String>,
+    /// The prefix of a `host` without `.`. Allowed only in `machine_defaults`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub host_prefix: String,
+    /// The suffix of a `host` without `.`. Allowed only in `machine_defaults`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub host_suffix: String,
+    /// Whether to compress the SSH traffic. Default: `false`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<bool>,
+    /// Whether to pass the environment variables such as the GitHub token with the SSH `env` request rather than a temporary script. Requires `AcceptEnv` on the SSH server. Default: `false`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_setenv: Option<bool>,
+    /// The interval of the keepalive messages in seconds, or 0 to disable them. Default: 30
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_alive_interval_secs: Option<u64>,
 }
 
 impl Default for SshConfig {
@@ -421,6 +1594,20 @@ impl Default for SshConfig {
             password: "".to_string(),
             private_key: "".to_string(),
             private_key_passphrase: "".to_string(),
+            certificate: None,
+            connect_timeout_secs: 0,
+            command_timeout_secs: 0,
+            max_retries: None,
+            retry_base_delay_ms: 0,
+            agent_socket: None,
+            host_key_algorithm: None,
+            known_hosts_file: None,
+            bind_address: None,
+            host_prefix: "".to_string(),
+            host_suffix: "".to_string(),
+            compression: None,
+            use_setenv: None,
+            server_alive_interval_secs: None,
         }
     }
 }
@@ -438,16 +1625,205 @@ impl Debug for SshConfig {
                 "private_key_passphrase",
                 mask_credential(&self.private_key_passphrase),
             )
+            .field("certificate", &self.certificate)
+            .field("connect_timeout_secs", &self.connect_timeout_secs)
+            .field("command_timeout_secs", &self.command_timeout_secs)
+            .field("max_retries", &self.max_retries)
+            .field("retry_base_delay_ms", &self.retry_base_delay_ms)
+            .field("agent_socket", &self.agent_socket)
+            .field("host_key_algorithm", &self.host_key_algorithm)
+            .field("known_hosts_file", &self.known_hosts_file)
+            .field("bind_address", &self.bind_address)
+            .field("host_prefix", &self.host_prefix)
+            .field("host_suffix", &self.host_suffix)
+            .field("compression", &self.compression)
+            .field("use_setenv", &self.use_setenv)
+            .field(
+                "server_alive_interval_secs",
+                &self.server_alive_interval_secs,
+            )
             .finish()
     }
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 #[derive(Default)]
 pub struct RunnersConfig {
+    /// The number of idle runners to keep in addition to the queued runs. Default: 0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<u32>,
+    /// The maximum number of runners. Default: 16
     #[serde(default)]
     pub max: u32,
+    /// The number of seconds a runner can stay idle before it's stopped, except the ones kept
+    /// for `min`. Default: 300
+    #[serde(default)]
+    pub idle_timeout_secs: u64,
+    /// Overrides `github.runners.ephemeral`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ephemeral: Option<bool>,
+    /// Overrides `github.runners.unset_config_vars`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unset_config_vars: Option<bool>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+#[derive(Default)]
+pub struct DockerConfig {
+    /// The network of the runner containers. Default: Docker's default bridge network
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    /// The image of the runner containers. Default: `ghcr.io/myoung34/docker-github-actions-runner:ubuntu-focal`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// The prefix of the runner container names, followed by `-` and a UUID. Default: `github-self-hosted-runner`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_name_prefix: Option<String>,
+    /// The minimum age of the unused images to prune. Default: 48
+    #[serde(default)]
+    pub prune_older_than_hours: u64,
+    /// Whether to prune the unused images after every scaling pass. Default: `false`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_prune: Option<bool>,
+    /// Whether to prune the unused images older than `prune_keep_days` before pulling the image
+    /// to start a runner, at most once per scaling pass. Default: `false`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_prune_on_start: Option<bool>,
+    /// The minimum age of the unused images to prune with `auto_prune_on_start` in days. Default: 7
+    #[serde(default)]
+    pub prune_keep_days: u32,
+    /// When to pull the image. Default: `always`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_policy: Option<PullPolicy>,
+    /// The additional environment variables of the runner containers, merged with the defaults.
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "serialize_sorted"
+    )]
+    pub extra_env: HashMap<String, String>,
+    /// The additional labels of the runner containers, merged with the defaults. A key must not start with `github-`.
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "serialize_sorted"
+    )]
+    pub extra_labels: HashMap<String, String>,
+    /// The bind mounts of the runner containers, replacing the defaults.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<VolumeMount>,
+    /// The restart policy of the runner containers, i.e. `no`, `on-failure`, `on-failure:<max_retries>` or `unless-stopped`. Default: `no`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart_policy: Option<RestartPolicy>,
+    /// The memory limit of each runner container, i.e. the `--memory` option of `docker container run`. Default: unlimited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_bytes: Option<u64>,
+    /// The available disk space of `/var/lib/docker` below which the preflight check warns. Default: 5 GiB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_free_disk_bytes: Option<u64>,
+    /// The available disk space of `/var/lib/docker` below which the preflight check fails. Default: 1 GiB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub critical_free_disk_bytes: Option<u64>,
+}
+
+/// A bind mount from the host into a runner container.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct VolumeMount {
+    /// The absolute path on the host.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub host_path: String,
+    /// The absolute path in the container.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub container_path: String,
+    /// Whether the mount is read-only. Default: `false`
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PullPolicy {
+    /// Pulls the image before starting every runner.
+    #[default]
+    Always,
+    /// Pulls the image only if it does not exist on the machine.
+    IfNotPresent,
+    /// Never pulls the image, failing if it does not exist on the machine.
+    Never,
+}
+
+impl Display for PullPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PullPolicy::Always => f.write_str("always"),
+            PullPolicy::IfNotPresent => f.write_str("if-not-present"),
+            PullPolicy::Never => f.write_str("never"),
+        }
+    }
+}
+
+/// The restart policy of the runner containers, i.e. the `--restart` option of `docker container run`.
+#[derive(Copy, Clone, Debug, Deserialize, JsonSchema, Serialize, PartialEq, Eq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum RestartPolicy {
+    /// Never restarts a container.
+    #[default]
+    No,
+    /// Restarts a container that exited with a non-zero code up to the specified number of
+    /// times, or indefinitely if 0.
+    OnFailure(u32),
+    /// Restarts a container unless it has been stopped explicitly.
+    UnlessStopped,
+}
+
+impl Display for RestartPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RestartPolicy::No => f.write_str("no"),
+            RestartPolicy::OnFailure(0) => f.write_str("on-failure"),
+            RestartPolicy::OnFailure(max_retries) => write!(f, "on-failure:{}", max_retries),
+            RestartPolicy::UnlessStopped => f.write_str("unless-stopped"),
+        }
+    }
+}
+
+impl FromStr for RestartPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "no" => Ok(RestartPolicy::No),
+            "on-failure" => Ok(RestartPolicy::OnFailure(0)),
+            "unless-stopped" => Ok(RestartPolicy::UnlessStopped),
+            _ => value
+                .strip_prefix("on-failure:")
+                .and_then(|max_retries| max_retries.parse().ok())
+                .map(RestartPolicy::OnFailure)
+                .ok_or_else(|| {
+                    format!(
+                        "unknown restart policy '{}', expected 'no', 'on-failure', 'on-failure:<N>' or 'unless-stopped'",
+                        value
+                    )
+                }),
+        }
+    }
+}
+
+impl TryFrom<String> for RestartPolicy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<RestartPolicy> for String {
+    fn from(value: RestartPolicy) -> Self {
+        value.to_string()
+    }
 }
 
 struct MachineIdGenerator {
@@ -456,18 +1832,28 @@ struct MachineIdGenerator {
 }
 
 impl MachineIdGenerator {
-    fn new<'a, T>(cfgs: &'a T) -> Result<MachineIdGenerator, ConfigError>
+    fn new<'a, T>(cfgs: &'a T, resolver: &ConfigResolver) -> Result<MachineIdGenerator, ConfigError>
     where
-        &'a T: IntoIterator<Item = &'a MachineConfig>,
+        &'a T: IntoIterator<Item = &'a Arc<MachineConfig>>,
     {
+        // Resolve the specified IDs first, so that an ID specified with a variable,
+        // e.g. '${MACHINE_ID}', is neither generated for another machine nor duplicate.
         let mut id_set = HashSet::<String>::new();
         for c in cfgs {
-            if c.id.is_empty() {
+            let id = resolver.resolve(&c.id)?;
+            if id.is_empty() {
                 continue;
             }
-            if !id_set.insert(c.id.clone()) {
+            if !id_set.insert(id.clone()) {
                 return Err(ConfigError::ValidationFailure {
-                    message: format!("A duplicate machine ID '{}' was found.", c.id),
+                    message: if id == c.id {
+                        format!("A duplicate machine ID '{}' was found.", id)
+                    } else {
+                        format!(
+                            "A duplicate machine ID '{}' (resolved from '{}') was found.",
+                            id, c.id
+                        )
+                    },
                 });
             }
         }
@@ -498,6 +1884,49 @@ impl MachineIdGenerator {
     }
 }
 
+/// Collects the validation failures found while resolving a configuration.
+#[derive(Default)]
+struct ValidationFailureCollector {
+    messages: Vec<String>,
+}
+
+impl ValidationFailureCollector {
+    /// Returns the value of `result`, or `None` after collecting its validation failures.
+    /// Other errors are returned as they are because they prevent further validation.
+    fn collect<T>(&mut self, result: Result<T, ConfigError>) -> Result<Option<T>, ConfigError> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(ConfigError::ValidationFailure { message }) => {
+                self.messages.push(message);
+                Ok(None)
+            }
+            Err(ConfigError::ValidationFailures { messages }) => {
+                self.messages.extend(messages);
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn push(&mut self, message: String) {
+        self.messages.push(message);
+    }
+
+    /// Returns the collected failures, if any, as a `ValidationFailure` if there's only one
+    /// or as a `ValidationFailures` otherwise.
+    fn finish(mut self) -> Result<(), ConfigError> {
+        match self.messages.len() {
+            0 => Ok(()),
+            1 => Err(ConfigError::ValidationFailure {
+                message: self.messages.pop().unwrap(),
+            }),
+            _ => Err(ConfigError::ValidationFailures {
+                messages: self.messages,
+            }),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ConfigError {
     ReadFailure {
@@ -516,9 +1945,105 @@ pub enum ConfigError {
         path: String,
         cause: io::Error,
     },
+    UnresolvedBase64Variable {
+        cause: Box<dyn Error + Send + Sync>,
+    },
+    UnresolvedVaultVariable {
+        path: String,
+        cause: Box<dyn Error + Send + Sync>,
+    },
+    DownloadFailure {
+        url: String,
+        cause: Box<dyn Error + Send + Sync>,
+    },
     ValidationFailure {
         message: String,
     },
+    ValidationFailures {
+        messages: Vec<String>,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ConfigError::ReadFailure { path, cause } => {
+                write!(
+                    f,
+                    "Failed to read the configuration file: {} ({})",
+                    path, cause
+                )
+            }
+            ConfigError::ParseFailure { path, cause } => {
+                write!(
+                    f,
+                    "Failed to parse the configuration file: {} ({})",
+                    path, cause
+                )
+            }
+            ConfigError::UnresolvedEnvironmentVariable { name, cause } => {
+                write!(
+                    f,
+                    "Failed to resolve an environment variable: {} ({})",
+                    name, cause
+                )
+            }
+            ConfigError::UnresolvedFileVariable { path, cause } => {
+                write!(
+                    f,
+                    "Failed to resolve an external file: {} ({})",
+                    path, cause
+                )
+            }
+            ConfigError::UnresolvedBase64Variable { cause } => {
+                write!(f, "Failed to decode a base64-encoded value ({})", cause)
+            }
+            ConfigError::UnresolvedVaultVariable { path, cause } => {
+                write!(f, "Failed to read a Vault secret: {} ({})", path, cause)
+            }
+            ConfigError::DownloadFailure { url, cause } => {
+                write!(
+                    f,
+                    "Failed to download the configuration file: {} ({})",
+                    url, cause
+                )
+            }
+            ConfigError::ValidationFailure { message } => {
+                write!(f, "Invalid configuration: {}", message)
+            }
+            ConfigError::ValidationFailures { messages } => {
+                write!(f, "Invalid configuration: {}", messages.join(" "))
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::ReadFailure { cause, .. } => Some(cause),
+            ConfigError::ParseFailure { cause, .. } => Some(cause),
+            ConfigError::UnresolvedEnvironmentVariable { cause, .. } => Some(cause),
+            ConfigError::UnresolvedFileVariable { cause, .. } => Some(cause),
+            ConfigError::UnresolvedBase64Variable { cause } => Some(cause.as_ref()),
+            ConfigError::UnresolvedVaultVariable { cause, .. } => Some(cause.as_ref()),
+            ConfigError::DownloadFailure { cause, .. } => Some(cause.as_ref()),
+            ConfigError::ValidationFailure { .. } => None,
+            ConfigError::ValidationFailures { .. } => None,
+        }
+    }
+}
+
+/// Returns the length of the runner names rendered for the specified machine. Every placeholder
+/// except `{uuid}` is replaced with its value, and `{uuid}` is counted as 36 characters.
+fn max_runner_name_len(config: &GithubRunnerConfig, machine_id: &str) -> usize {
+    const UUID_LEN: usize = 36;
+    let prefix = config.name_prefix.replace("{machine_id}", machine_id);
+    let template = config
+        .runner_name_template
+        .replace("{prefix}", &prefix)
+        .replace("{machine_id}", machine_id);
+    template.replace("{uuid}", "").len() + template.matches("{uuid}").count() * UUID_LEN
 }
 
 fn mask_credential(value: &str) -> &dyn Debug {
@@ -548,12 +2073,53 @@ fn write_masked_credential_with_preview(f: &mut Formatter, value: &str) -> fmt::
     }
 }
 
+/// Serializes a map with its keys sorted, so that the output is stable.
+fn serialize_sorted<S: Serializer>(
+    map: &HashMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let sorted: BTreeMap<&String, &String> = map.iter().collect();
+    sorted.serialize(serializer)
+}
+
 // Default value functions for serde
 
 fn default_github_runner_name_prefix() -> String {
     "runner".to_string()
 }
 
+fn default_github_runner_name_template() -> String {
+    "{prefix}-{uuid}".to_string()
+}
+
 fn default_github_runner_scope() -> String {
     "repo".to_string()
 }
+
+fn default_github_api_version() -> String {
+    "2022-11-28".to_string()
+}
+
+fn default_github_timeout_secs() -> u64 {
+    10
+}
+
+fn default_github_connect_timeout_secs() -> u64 {
+    5
+}
+
+fn default_retry_max_retries() -> u32 {
+    3
+}
+
+fn default_retry_base_delay_ms() -> u64 {
+    1000
+}
+
+fn default_retry_max_delay_ms() -> u64 {
+    30000
+}
+
+fn default_true() -> bool {
+    true
+}
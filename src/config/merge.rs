@@ -0,0 +1,258 @@
+use crate::config::{
+    Config, DockerConfig, GithubConfig, GithubRunnerConfig, LogLevel, MachineConfig,
+    MachineDefaultsConfig, RetryConfig, RunnersConfig, SelectionStrategy, SshConfig,
+};
+use std::sync::Arc;
+
+impl Config {
+    /// Overlays the non-default values in `overrides` onto `base`, i.e. non-empty strings,
+    /// non-zero numbers, non-empty lists and specified optional values in `overrides` win.
+    /// The machines with the same ID are merged field by field, and the machines with
+    /// a new or no ID are appended.
+    pub fn merge(base: Config, overrides: Config) -> Config {
+        Config {
+            log_level: merge_value(base.log_level, overrides.log_level, &LogLevel::default()),
+            selection_strategy: merge_value(
+                base.selection_strategy,
+                overrides.selection_strategy,
+                &SelectionStrategy::default(),
+            ),
+            max_machines: overrides.max_machines.or(base.max_machines),
+            audit_log: overrides.audit_log.or(base.audit_log),
+            tag_filter: overrides.tag_filter.or(base.tag_filter),
+            github: merge_github_config(base.github, overrides.github),
+            machine_defaults: merge_machine_defaults_config(
+                base.machine_defaults,
+                overrides.machine_defaults,
+            ),
+            machines: merge_machine_configs(base.machines, overrides.machines),
+        }
+    }
+}
+
+fn merge_github_config(base: GithubConfig, overrides: GithubConfig) -> GithubConfig {
+    let defaults = GithubRunnerConfig::default();
+    let (b, o) = (base.runners, overrides.runners);
+    GithubConfig {
+        personal_access_token: merge_string(
+            base.personal_access_token,
+            overrides.personal_access_token,
+        ),
+        runners: GithubRunnerConfig {
+            name_prefix: merge_value(b.name_prefix, o.name_prefix, &defaults.name_prefix),
+            runner_name_template: merge_value(
+                b.runner_name_template,
+                o.runner_name_template,
+                &defaults.runner_name_template,
+            ),
+            scope: merge_value(b.scope, o.scope, &defaults.scope),
+            org_name: merge_string(b.org_name, o.org_name),
+            repo_url: merge_string(b.repo_url, o.repo_url),
+            api_endpoint_url: merge_string(b.api_endpoint_url, o.api_endpoint_url),
+            repo_user: merge_string(b.repo_user, o.repo_user),
+            repo_name: merge_string(b.repo_name, o.repo_name),
+            runner_group: o.runner_group.or(b.runner_group),
+            use_registration_token: merge_value(
+                b.use_registration_token,
+                o.use_registration_token,
+                &false,
+            ),
+            deregister_offline: merge_value(b.deregister_offline, o.deregister_offline, &false),
+            work_dir: o.work_dir.or(b.work_dir),
+            ephemeral: merge_value(b.ephemeral, o.ephemeral, &defaults.ephemeral),
+            unset_config_vars: merge_value(
+                b.unset_config_vars,
+                o.unset_config_vars,
+                &defaults.unset_config_vars,
+            ),
+        },
+        max_runs: overrides.max_runs.or(base.max_runs),
+        retry: merge_retry_config(base.retry, overrides.retry),
+        api_version: merge_value(
+            base.api_version,
+            overrides.api_version,
+            &GithubConfig::default().api_version,
+        ),
+        timeout_secs: merge_value(
+            base.timeout_secs,
+            overrides.timeout_secs,
+            &GithubConfig::default().timeout_secs,
+        ),
+        connect_timeout_secs: merge_value(
+            base.connect_timeout_secs,
+            overrides.connect_timeout_secs,
+            &GithubConfig::default().connect_timeout_secs,
+        ),
+    }
+}
+
+fn merge_retry_config(base: RetryConfig, overrides: RetryConfig) -> RetryConfig {
+    let defaults = RetryConfig::default();
+    RetryConfig {
+        max_retries: merge_value(
+            base.max_retries,
+            overrides.max_retries,
+            &defaults.max_retries,
+        ),
+        base_delay_ms: merge_value(
+            base.base_delay_ms,
+            overrides.base_delay_ms,
+            &defaults.base_delay_ms,
+        ),
+        max_delay_ms: merge_value(
+            base.max_delay_ms,
+            overrides.max_delay_ms,
+            &defaults.max_delay_ms,
+        ),
+    }
+}
+
+fn merge_machine_defaults_config(
+    base: MachineDefaultsConfig,
+    overrides: MachineDefaultsConfig,
+) -> MachineDefaultsConfig {
+    MachineDefaultsConfig {
+        ssh: merge_ssh_config(base.ssh, overrides.ssh),
+        runners: merge_runners_config(base.runners, overrides.runners),
+        docker: merge_docker_config(base.docker, overrides.docker),
+    }
+}
+
+fn merge_machine_configs(
+    base: Vec<Arc<MachineConfig>>,
+    overrides: Vec<Arc<MachineConfig>>,
+) -> Vec<Arc<MachineConfig>> {
+    let mut out = base;
+    for o in overrides {
+        let existing = if o.id.is_empty() {
+            None
+        } else {
+            out.iter().position(|b| b.id == o.id)
+        };
+
+        match existing {
+            Some(index) => {
+                out[index] = Arc::new(merge_machine_config(
+                    Arc::unwrap_or_clone(out[index].clone()),
+                    Arc::unwrap_or_clone(o),
+                ))
+            }
+            None => out.push(o),
+        }
+    }
+    out
+}
+
+fn merge_machine_config(base: MachineConfig, overrides: MachineConfig) -> MachineConfig {
+    MachineConfig {
+        id: base.id,
+        ssh: merge_ssh_config(base.ssh, overrides.ssh),
+        runners: merge_runners_config(base.runners, overrides.runners),
+        docker: merge_docker_config(base.docker, overrides.docker),
+        labels: merge_value(base.labels, overrides.labels, &vec![]),
+        weight: merge_value(base.weight, overrides.weight, &0),
+        enabled: overrides.enabled.or(base.enabled),
+        tags: merge_value(base.tags, overrides.tags, &vec![]),
+    }
+}
+
+fn merge_ssh_config(base: SshConfig, overrides: SshConfig) -> SshConfig {
+    SshConfig {
+        host: merge_string(base.host, overrides.host),
+        port: merge_value(base.port, overrides.port, &0),
+        fingerprint: merge_string(base.fingerprint, overrides.fingerprint),
+        username: merge_string(base.username, overrides.username),
+        password: merge_string(base.password, overrides.password),
+        private_key: merge_string(base.private_key, overrides.private_key),
+        private_key_passphrase: merge_string(
+            base.private_key_passphrase,
+            overrides.private_key_passphrase,
+        ),
+        certificate: overrides.certificate.or(base.certificate),
+        connect_timeout_secs: merge_value(
+            base.connect_timeout_secs,
+            overrides.connect_timeout_secs,
+            &0,
+        ),
+        command_timeout_secs: merge_value(
+            base.command_timeout_secs,
+            overrides.command_timeout_secs,
+            &0,
+        ),
+        max_retries: overrides.max_retries.or(base.max_retries),
+        retry_base_delay_ms: merge_value(
+            base.retry_base_delay_ms,
+            overrides.retry_base_delay_ms,
+            &0,
+        ),
+        agent_socket: overrides.agent_socket.or(base.agent_socket),
+        host_key_algorithm: overrides.host_key_algorithm.or(base.host_key_algorithm),
+        known_hosts_file: overrides.known_hosts_file.or(base.known_hosts_file),
+        bind_address: overrides.bind_address.or(base.bind_address),
+        host_prefix: merge_string(base.host_prefix, overrides.host_prefix),
+        host_suffix: merge_string(base.host_suffix, overrides.host_suffix),
+        compression: overrides.compression.or(base.compression),
+        use_setenv: overrides.use_setenv.or(base.use_setenv),
+        server_alive_interval_secs: overrides
+            .server_alive_interval_secs
+            .or(base.server_alive_interval_secs),
+    }
+}
+
+fn merge_runners_config(base: RunnersConfig, overrides: RunnersConfig) -> RunnersConfig {
+    RunnersConfig {
+        min: overrides.min.or(base.min),
+        max: merge_value(base.max, overrides.max, &0),
+        idle_timeout_secs: merge_value(base.idle_timeout_secs, overrides.idle_timeout_secs, &0),
+        ephemeral: overrides.ephemeral.or(base.ephemeral),
+        unset_config_vars: overrides.unset_config_vars.or(base.unset_config_vars),
+    }
+}
+
+fn merge_docker_config(base: DockerConfig, overrides: DockerConfig) -> DockerConfig {
+    DockerConfig {
+        network: overrides.network.or(base.network),
+        image: overrides.image.or(base.image),
+        container_name_prefix: overrides
+            .container_name_prefix
+            .or(base.container_name_prefix),
+        prune_older_than_hours: merge_value(
+            base.prune_older_than_hours,
+            overrides.prune_older_than_hours,
+            &0,
+        ),
+        auto_prune: overrides.auto_prune.or(base.auto_prune),
+        auto_prune_on_start: overrides.auto_prune_on_start.or(base.auto_prune_on_start),
+        prune_keep_days: merge_value(base.prune_keep_days, overrides.prune_keep_days, &0),
+        pull_policy: overrides.pull_policy.or(base.pull_policy),
+        extra_env: {
+            let mut extra_env = base.extra_env;
+            extra_env.extend(overrides.extra_env);
+            extra_env
+        },
+        extra_labels: {
+            let mut extra_labels = base.extra_labels;
+            extra_labels.extend(overrides.extra_labels);
+            extra_labels
+        },
+        volumes: merge_value(base.volumes, overrides.volumes, &vec![]),
+        restart_policy: overrides.restart_policy.or(base.restart_policy),
+        memory_limit_bytes: overrides.memory_limit_bytes.or(base.memory_limit_bytes),
+        min_free_disk_bytes: overrides.min_free_disk_bytes.or(base.min_free_disk_bytes),
+        critical_free_disk_bytes: overrides
+            .critical_free_disk_bytes
+            .or(base.critical_free_disk_bytes),
+    }
+}
+
+fn merge_string(base: String, overrides: String) -> String {
+    merge_value(base, overrides, &String::new())
+}
+
+fn merge_value<T: PartialEq>(base: T, overrides: T, default: &T) -> T {
+    if overrides != *default {
+        overrides
+    } else {
+        base
+    }
+}
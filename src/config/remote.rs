@@ -0,0 +1,165 @@
+use crate::config::resolver::ConfigResolver;
+use crate::config::{Config, ConfigError};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fs, io};
+use tracing::{debug, warn};
+use url::{Host, Url};
+
+/// The timeout of a request for a remote configuration file.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl Config {
+    /// Downloads the configuration file from the specified URL, resolving `${file:...}` relative
+    /// to the URL. See [`Config::from_url_with_cache_dir`] for the details.
+    pub fn from_url(url: &Url) -> Result<Self, ConfigError> {
+        Self::from_url_with_cache_dir(url, &default_cache_dir())
+    }
+
+    /// Downloads the configuration file from the specified URL, which must use HTTPS unless
+    /// it points to the loopback interface. The downloaded file is cached in `cache_dir` with
+    /// its `ETag`, so that it's downloaded again only when it changes. The cached file is used
+    /// if the server is unreachable.
+    pub fn from_url_with_cache_dir(url: &Url, cache_dir: &Path) -> Result<Self, ConfigError> {
+        let content = download(url, cache_dir)?;
+        Self::resolve_config(
+            &ConfigResolver::from_url(url),
+            &Self::parse_str(url.as_str(), &content)?,
+        )
+    }
+
+    /// Downloads the configuration file from the specified URL and overlays the override file
+    /// on top of it using [`Config::merge`]. Note that `${file:...}` is resolved relative to
+    /// the URL.
+    pub fn from_url_with_overrides(url: &Url, override_file: &Path) -> Result<Self, ConfigError> {
+        let content = download(url, &default_cache_dir())?;
+        let merged_config = Self::merge(
+            Self::parse_str(url.as_str(), &content)?,
+            Self::parse(override_file)?,
+        );
+        Self::resolve_config(&ConfigResolver::from_url(url), &merged_config)
+    }
+}
+
+/// Returns the URL of a remote configuration file, or `None` if `location` is not an HTTP(S) URL.
+pub fn parse_config_url(location: &str) -> Option<Url> {
+    let url = Url::parse(location).ok()?;
+    matches!(url.scheme(), "http" | "https").then_some(url)
+}
+
+/// Returns whether the specified URL uses HTTPS. Plain HTTP is allowed only for a local server,
+/// e.g. a test server or an SSH tunnel.
+pub(crate) fn is_secure_url(url: &Url) -> bool {
+    let is_loopback = match url.host() {
+        Some(Host::Domain(domain)) => domain == "localhost",
+        Some(Host::Ipv4(addr)) => addr.is_loopback(),
+        Some(Host::Ipv6(addr)) => addr.is_loopback(),
+        None => false,
+    };
+    url.scheme() == "https" || is_loopback
+}
+
+fn default_cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join("gh-actions-scaler")
+}
+
+fn download(url: &Url, cache_dir: &Path) -> Result<String, ConfigError> {
+    if !is_secure_url(url) {
+        return Err(ConfigError::ValidationFailure {
+            message: format!(
+                "The configuration URL '{}' must use HTTPS, because it may contain credentials.",
+                url
+            ),
+        });
+    }
+
+    // Name the cached file after the URL, e.g. 'https___example_com_config_yaml.yaml'.
+    let cache_name: String = url
+        .as_str()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let content_path = cache_dir.join(format!("{}.yaml", cache_name));
+    let etag_path = cache_dir.join(format!("{}.etag", cache_name));
+    let cached = fs::read_to_string(&content_path).ok();
+    let etag = cached
+        .as_ref()
+        .and_then(|_| fs::read_to_string(&etag_path).ok());
+
+    let mut request = ureq::get(url.as_str()).timeout(DOWNLOAD_TIMEOUT);
+    if let Some(etag) = &etag {
+        request = request.set("If-None-Match", etag.trim());
+    }
+    let failure = |cause: Box<dyn std::error::Error + Send + Sync>| ConfigError::DownloadFailure {
+        url: url.to_string(),
+        cause,
+    };
+    match request.call() {
+        Ok(response) if response.status() == 304 => {
+            debug!(
+                "The configuration at {} has not changed; using the cached one",
+                url
+            );
+            // Never fails because the ETag is sent only when the file is cached.
+            Ok(cached.unwrap())
+        }
+        Ok(response) => {
+            let new_etag = response.header("ETag").map(str::to_string);
+            let content = response
+                .into_string()
+                .map_err(|cause| failure(cause.into()))?;
+            if let Err(err) = write_cache(&content_path, &content, &etag_path, new_etag.as_deref())
+            {
+                warn!(
+                    "Failed to cache the configuration at {}: {}",
+                    content_path.display(),
+                    err
+                );
+            }
+            Ok(content)
+        }
+        Err(ureq::Error::Transport(transport)) if cached.is_some() => {
+            warn!(
+                "Failed to download the configuration from {}; using the cached one: {}",
+                url, transport
+            );
+            Ok(cached.unwrap())
+        }
+        Err(err) => Err(failure(err.into())),
+    }
+}
+
+fn write_cache(
+    content_path: &Path,
+    content: &str,
+    etag_path: &Path,
+    etag: Option<&str>,
+) -> io::Result<()> {
+    if let Some(dir) = content_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    write_private(content_path, content)?;
+    match etag {
+        Some(etag) => write_private(etag_path, etag),
+        // Don't send a stale ETag for the new content.
+        None => match fs::remove_file(etag_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Writes a file readable only by the current user, because a configuration may contain
+/// credentials.
+fn write_private(path: &Path, content: &str) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    io::Write::write_all(&mut options.open(path)?, content.as_bytes())
+}
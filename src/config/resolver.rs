@@ -1,23 +1,62 @@
+use crate::config::remote::is_secure_url;
 use crate::config::ConfigError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use once_cell::sync::Lazy;
 use regex::{Captures, Regex, Replacer};
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
 use std::path::{Path, PathBuf};
-use std::{env, fs};
+use std::time::Duration;
+use std::{env, fs, io};
+use ureq::serde_json::Value;
+use url::Url;
+
+/// The timeout of a request to the Vault server.
+const VAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The timeout of a request for a file referenced by `${file:...}` in a remote configuration.
+const FILE_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct ConfigResolver {
     config_dir: PathBuf,
+    /// The URL that `${file:...}` is resolved against instead of `config_dir`, i.e. the URL of
+    /// the configuration file if it was downloaded.
+    config_url: Option<Url>,
+    /// The trimmed content of the files read by `${file:...}`, so that a file referenced
+    /// from many fields, e.g. the private key shared by all machines, is read only once.
+    file_cache: RefCell<HashMap<String, String>>,
+    /// The values of the environment variables read by `${...}` and `${env:...}`
+    env_cache: RefCell<HashMap<String, String>>,
+    /// The responses of the Vault server for the secret paths read by `${vault:...}`, so that
+    /// the keys of the same secret are read with a single request.
+    vault_cache: RefCell<HashMap<String, Value>>,
 }
 
 impl<P: AsRef<Path>> From<P> for ConfigResolver {
     fn from(config_dir: P) -> Self {
         ConfigResolver {
             config_dir: PathBuf::from(config_dir.as_ref()),
+            config_url: None,
+            file_cache: RefCell::new(HashMap::new()),
+            env_cache: RefCell::new(HashMap::new()),
+            vault_cache: RefCell::new(HashMap::new()),
         }
     }
 }
 
 impl ConfigResolver {
+    /// Creates a resolver that resolves `${file:...}` relative to the specified URL of the
+    /// configuration file, e.g. `${file:key}` in `https://example.com/ci/config.yaml` as
+    /// `https://example.com/ci/key`.
+    pub fn from_url(config_url: &Url) -> Self {
+        ConfigResolver {
+            config_url: Some(config_url.clone()),
+            ..ConfigResolver::from(".")
+        }
+    }
+
     pub fn resolve_or_else<STR, ELSE>(
         &self,
         input: STR,
@@ -35,13 +74,14 @@ impl ConfigResolver {
     }
 
     pub fn resolve<STR: AsRef<str>>(&self, input: STR) -> Result<String, ConfigError> {
-        static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(\$\$)|\$\{(file:)?([^}]+)}").unwrap());
+        static RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"(\$\$)|\$\{(env:|file:|base64:|vault:)?([^}]+)}").unwrap());
         let config_error_ref: RefCell<Option<ConfigError>> = RefCell::new(None);
         let resolved_value = RE
             .replace_all(
                 input.as_ref(),
                 ConfigVariableResolver {
-                    config_dir: self.config_dir.as_path(),
+                    resolver: self,
                     config_error_ref: &config_error_ref,
                 },
             )
@@ -56,7 +96,7 @@ impl ConfigResolver {
 }
 
 struct ConfigVariableResolver<'a> {
-    config_dir: &'a Path,
+    resolver: &'a ConfigResolver,
     config_error_ref: &'a RefCell<Option<ConfigError>>,
 }
 
@@ -68,20 +108,32 @@ impl Replacer for ConfigVariableResolver<'_> {
             return;
         }
 
-        // Replace ${...} with the environment variable value or the file content.
+        // Replace ${...} with the environment variable value, the file content, the decoded value
+        // or the Vault secret.
         let name = caps.get(3).unwrap().as_str();
-        match caps.get(2) {
-            Some(_) => self.append_file(name, dst),
-            None => self.append_env_var(name, dst),
+        match caps.get(2).map(|prefix| prefix.as_str()) {
+            Some("file:") => self.append_file(name, dst),
+            Some("base64:") => self.append_base64(name, dst),
+            Some("vault:") => self.append_vault_secret(name, dst),
+            _ => self.append_env_var(name, dst),
         }
     }
 }
 
 impl ConfigVariableResolver<'_> {
     fn append_env_var(&mut self, name: &str, dst: &mut String) {
+        if let Some(value) = self.resolver.env_cache.borrow().get(name) {
+            dst.push_str(value.as_str());
+            return;
+        }
+
         match env::var(name) {
             Ok(value) => {
                 dst.push_str(value.as_str());
+                self.resolver
+                    .env_cache
+                    .borrow_mut()
+                    .insert(String::from(name), value);
             }
             Err(cause) => {
                 self.set_config_error(ConfigError::UnresolvedEnvironmentVariable {
@@ -93,25 +145,143 @@ impl ConfigVariableResolver<'_> {
     }
 
     fn append_file(&mut self, path: &str, dst: &mut String) {
-        let path = {
-            let mut buf = PathBuf::from(self.config_dir);
-            buf.push(path);
-            buf
+        let location = match &self.resolver.config_url {
+            // Don't let an absolute URL send the request elsewhere, e.g. over plain HTTP.
+            Some(config_url) => match config_url.join(path) {
+                Ok(url) if url.origin() == config_url.origin() => url.to_string(),
+                Ok(url) => {
+                    self.set_config_error(ConfigError::UnresolvedFileVariable {
+                        path: url.to_string(),
+                        cause: io::Error::new(
+                            io::ErrorKind::PermissionDenied,
+                            format!("must be on the same origin as '{}'", config_url),
+                        ),
+                    });
+                    return;
+                }
+                Err(cause) => {
+                    self.set_config_error(ConfigError::UnresolvedFileVariable {
+                        path: path.to_string(),
+                        cause: io::Error::new(io::ErrorKind::InvalidInput, cause),
+                    });
+                    return;
+                }
+            },
+            None => {
+                let mut buf = PathBuf::from(&self.resolver.config_dir);
+                buf.push(path);
+                buf.to_string_lossy().into_owned()
+            }
         };
 
-        match fs::read_to_string(path.as_path()) {
+        if let Some(content) = self.resolver.file_cache.borrow().get(&location) {
+            dst.push_str(content.as_str());
+            return;
+        }
+
+        let content = if self.resolver.config_url.is_some() {
+            read_remote_file(&location)
+        } else {
+            fs::read_to_string(&location)
+        };
+        match content {
             Ok(content) => {
-                dst.push_str(content.trim_end());
+                let content = content.trim_end();
+                dst.push_str(content);
+                self.resolver
+                    .file_cache
+                    .borrow_mut()
+                    .insert(location, content.to_string());
             }
             Err(cause) => {
                 self.set_config_error(ConfigError::UnresolvedFileVariable {
-                    path: path.to_str().unwrap().to_string(),
+                    path: location,
                     cause,
                 });
             }
         }
     }
 
+    fn append_base64(&mut self, encoded: &str, dst: &mut String) {
+        let decoded = STANDARD
+            .decode(encoded.trim())
+            .map_err(|cause| cause.into())
+            .and_then(|bytes| String::from_utf8(bytes).map_err(|cause| cause.into()));
+
+        match decoded {
+            Ok(value) => {
+                dst.push_str(value.as_str());
+            }
+            Err(cause) => {
+                self.set_config_error(ConfigError::UnresolvedBase64Variable { cause });
+            }
+        }
+    }
+
+    /// Appends the value of `<key>` in the secret at `<mount>/<path>` specified as
+    /// `<mount>/<path>#<key>`, reading the secret from `$VAULT_ADDR` with `$VAULT_TOKEN`.
+    fn append_vault_secret(&mut self, spec: &str, dst: &mut String) {
+        let Some((path, key)) = spec.rsplit_once('#') else {
+            self.set_config_error(ConfigError::UnresolvedVaultVariable {
+                path: spec.to_string(),
+                cause: "The key must be specified as '<mount>/<path>#<key>'".into(),
+            });
+            return;
+        };
+
+        if !self.resolver.vault_cache.borrow().contains_key(path) {
+            match self.read_vault_secret(path) {
+                Ok(secret) => {
+                    self.resolver
+                        .vault_cache
+                        .borrow_mut()
+                        .insert(path.to_string(), secret);
+                }
+                Err(config_error) => {
+                    self.set_config_error(config_error);
+                    return;
+                }
+            }
+        }
+
+        let vault_cache = self.resolver.vault_cache.borrow();
+        let secret = &vault_cache[path];
+        // KV version 2 nests the key-value pairs in 'data' once more.
+        let value = secret["data"]["data"][key]
+            .as_str()
+            .or_else(|| secret["data"][key].as_str());
+        match value {
+            Some(value) => dst.push_str(value),
+            None => self.set_config_error(ConfigError::UnresolvedVaultVariable {
+                path: path.to_string(),
+                cause: format!("No such key: {}", key).into(),
+            }),
+        }
+    }
+
+    fn read_vault_secret(&self, path: &str) -> Result<Value, ConfigError> {
+        let env_var = |name: &str| {
+            env::var(name).map_err(|cause| ConfigError::UnresolvedEnvironmentVariable {
+                name: name.to_string(),
+                cause,
+            })
+        };
+        let addr = env_var("VAULT_ADDR")?;
+        let token = env_var("VAULT_TOKEN")?;
+
+        let url = format!("{}/v1/{}", addr.trim_end_matches('/'), path);
+        ureq::get(&url)
+            .timeout(VAULT_REQUEST_TIMEOUT)
+            .set("X-Vault-Token", &token)
+            .call()
+            .map_err(|cause| Box::new(cause) as Box<dyn Error + Send + Sync>)
+            .and_then(|response| response.into_json::<Value>().map_err(|cause| cause.into()))
+            .map_err(|cause| ConfigError::UnresolvedVaultVariable {
+                path: path.to_string(),
+                cause,
+            })
+    }
+
     fn set_config_error(&mut self, config_error: ConfigError) {
         let cell = self.config_error_ref;
         if cell.borrow().is_none() {
@@ -119,3 +289,20 @@ impl ConfigVariableResolver<'_> {
         }
     }
 }
+
+fn read_remote_file(url: &str) -> io::Result<String> {
+    let parsed_url =
+        Url::parse(url).map_err(|cause| io::Error::new(io::ErrorKind::InvalidInput, cause))?;
+    if !is_secure_url(&parsed_url) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "must use HTTPS, because it may contain credentials",
+        ));
+    }
+
+    ureq::get(url)
+        .timeout(FILE_REQUEST_TIMEOUT)
+        .call()
+        .map_err(io::Error::other)?
+        .into_string()
+}
@@ -0,0 +1,108 @@
+use crate::config::remote::parse_config_url;
+use crate::config::Config;
+use notify::{Event, RecursiveMode, Watcher};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// The delay that coalesces the bursts of file system events caused by a single save.
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(200);
+
+impl Config {
+    /// Reloads the configuration whenever the local configuration file or the override file is
+    /// modified or the process receives `SIGHUP`, and sends the reloaded configuration to
+    /// `sender`. A configuration that fails to load is logged and skipped. The watcher stops
+    /// when the receiving end of `sender` is dropped.
+    pub fn watch(
+        config_file: &Path,
+        override_file: Option<&Path>,
+        sender: Sender<Config>,
+    ) -> Result<(), Box<dyn Error>> {
+        let config_file = config_file.to_path_buf();
+        let override_file = override_file.map(Path::to_path_buf);
+        let (trigger_tx, trigger_rx) = mpsc::channel::<()>();
+
+        // Watch the parent directories rather than the files themselves, because many editors
+        // replace a file with a new one on save.
+        // A remote configuration file is reloaded only on `SIGHUP`.
+        let remote_config_file = config_file.to_str().and_then(parse_config_url).is_some();
+        let watched_files: Vec<PathBuf> = [
+            Some(&config_file).filter(|_| !remote_config_file),
+            override_file.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|file| file.canonicalize().unwrap_or_else(|_| file.clone()))
+        .collect();
+        let watcher_files = watched_files.clone();
+        let watcher_tx = trigger_tx.clone();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+            if let Ok(event) = res {
+                if (event.kind.is_create() || event.kind.is_modify())
+                    && event.paths.iter().any(|path| watcher_files.contains(path))
+                {
+                    let _ = watcher_tx.send(());
+                }
+            }
+        })?;
+        for file in &watched_files {
+            if let Some(dir) = file.parent() {
+                watcher.watch(dir, RecursiveMode::NonRecursive)?;
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGHUP])?;
+            thread::spawn(move || {
+                for _ in signals.forever() {
+                    info!("Received SIGHUP; reloading the configuration ..");
+                    if trigger_tx.send(()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        thread::spawn(move || {
+            // Keep the watcher alive as long as this thread runs.
+            let _watcher = watcher;
+            while wait_for_trigger(&trigger_rx) {
+                match Config::load(&config_file, override_file.as_deref()) {
+                    Ok(config) => {
+                        if sender.send(config).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        warn!(
+                            "Failed to reload the configuration; keeping the current one: {}",
+                            err
+                        );
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// Waits for a reload trigger and swallows the triggers that follow it shortly.
+/// Returns `false` if all triggering ends have been dropped.
+fn wait_for_trigger(trigger_rx: &Receiver<()>) -> bool {
+    if trigger_rx.recv().is_err() {
+        return false;
+    }
+
+    loop {
+        match trigger_rx.recv_timeout(DEBOUNCE_DELAY) {
+            Ok(()) => continue,
+            Err(_) => return true,
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::io::Write;
+use termcolor::{Buffer, Color, ColorSpec, WriteColor};
+
+/// A cell of a grid, i.e. its text and the optional color of the text.
+pub type Cell = (String, Option<Color>);
+
+/// Renders a grid with the box-drawing characters, whose columns are as wide as their widest
+/// cells. The cells are colored only if `color` is `true`. The rendered grid ends with a line
+/// break.
+pub fn render_grid(headers: &[&str], rows: &[Vec<Cell>], color: bool) -> String {
+    let mut widths: Vec<usize> = headers
+        .iter()
+        .map(|header| header.chars().count())
+        .collect();
+    for row in rows {
+        for (width, (value, _)) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+
+    let mut buf = if color {
+        Buffer::ansi()
+    } else {
+        Buffer::no_color()
+    };
+    let border = |buf: &mut Buffer, left: &str, middle: &str, right: &str| {
+        let line: Vec<String> = widths.iter().map(|width| "─".repeat(width + 2)).collect();
+        writeln!(buf, "{}{}{}", left, line.join(middle), right).unwrap();
+    };
+
+    border(&mut buf, "┌", "┬", "┐");
+    let header_row: Vec<Cell> = headers
+        .iter()
+        .map(|header| (header.to_string(), None))
+        .collect();
+    write_row(&mut buf, &widths, &header_row);
+    border(&mut buf, "├", "┼", "┤");
+    for row in rows {
+        write_row(&mut buf, &widths, row);
+    }
+    border(&mut buf, "└", "┴", "┘");
+
+    String::from_utf8(buf.into_inner()).unwrap()
+}
+
+fn write_row(buf: &mut Buffer, widths: &[usize], row: &[Cell]) {
+    // Writing to a buffer never fails.
+    for (width, (value, color)) in widths.iter().zip(row) {
+        buf.write_all("│ ".as_bytes()).unwrap();
+        if let Some(color) = color {
+            buf.set_color(ColorSpec::new().set_fg(Some(*color)))
+                .unwrap();
+            buf.write_all(value.as_bytes()).unwrap();
+            buf.reset().unwrap();
+        } else {
+            buf.write_all(value.as_bytes()).unwrap();
+        }
+        let padding = width - value.chars().count();
+        write!(buf, "{} ", " ".repeat(padding)).unwrap();
+    }
+    writeln!(buf, "│").unwrap();
+}
@@ -1,21 +1,142 @@
 use crate::config::GithubConfig;
+use chrono::{DateTime, Utc};
 use once_cell::sync::Lazy;
+use rand::Rng;
 use std::error::Error;
+use std::sync::Arc;
+use std::thread;
 use std::time::Duration;
-use ureq::{serde_json, Agent, AgentBuilder};
+use tracing::warn;
+use ureq::{serde_json, Agent, AgentBuilder, Request, Response, TlsConnector};
 
+/// A GitHub REST API client. A clone shares the connection pool with the original.
+#[derive(Clone)]
 pub struct GithubClient {
     config: GithubConfig,
     agent: Agent,
 }
 
-#[derive(Debug)]
+/// The default maximum number of the idle connections in the pool.
+const DEFAULT_MAX_IDLE_CONNECTIONS: usize = 100;
+
+/// Creates a [`GithubClient`] with custom connection settings.
+pub struct GithubClientBuilder {
+    config: GithubConfig,
+    agent_builder: AgentBuilder,
+}
+
+impl GithubClientBuilder {
+    /// Sets the maximum number of the idle connections kept in the pool.
+    pub fn max_idle_connections(mut self, max: usize) -> Self {
+        self.agent_builder = self
+            .agent_builder
+            .max_idle_connections(max)
+            .max_idle_connections_per_host(max);
+        self
+    }
+
+    /// Overrides `github.timeout_secs`, the timeout of a request, including connecting and
+    /// reading the response.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.agent_builder = self.agent_builder.timeout(timeout);
+        self
+    }
+
+    /// Sets the TLS implementation, e.g. to trust a custom CA certificate of a GHE server.
+    pub fn tls_connector<T: TlsConnector + 'static>(mut self, tls_connector: Arc<T>) -> Self {
+        self.agent_builder = self.agent_builder.tls_connector(tls_connector);
+        self
+    }
+
+    pub fn build(self) -> GithubClient {
+        GithubClient {
+            config: self.config,
+            agent: self.agent_builder.build(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub struct WorkflowRun {
+    pub run_id: u64,
     pub url: String,
+    pub head_sha: String,
+    pub event: String,
+    pub workflow_name: String,
+    /// The status of the run, e.g. `queued` or `in_progress`
+    pub status: String,
+    /// The conclusion of the run, e.g. `success` or `cancelled`, or `None` if not completed
+    pub conclusion: Option<String>,
+    /// The `runs-on` labels of all queued jobs
+    pub required_labels: Vec<String>,
+    pub queued_jobs: Vec<JobInfo>,
+}
+
+impl WorkflowRun {
+    /// Returns whether this run has completed already, which happens when it's cancelled or
+    /// completed right before it's retrieved as a queued run.
+    pub fn is_completed(&self) -> bool {
+        self.conclusion.is_some()
+    }
+
+    /// Returns the labels required by each runner to start for this run, i.e. one runner with
+    /// `required_labels` if all queued jobs require the same labels, or one runner per queued
+    /// job otherwise, so that each job can run on a different machine.
+    pub fn runner_labels(&self) -> Vec<&[String]> {
+        let mut jobs = self.queued_jobs.iter();
+        let Some(first) = jobs.next() else {
+            return vec![&self.required_labels];
+        };
+        if jobs.all(|job| same_labels(&job.labels, &first.labels)) {
+            vec![&self.required_labels]
+        } else {
+            self.queued_jobs
+                .iter()
+                .map(|job| job.labels.as_slice())
+                .collect()
+        }
+    }
+}
+
+/// Returns whether both have the same labels, ignoring the order, duplicates and the case
+/// as GitHub does.
+fn same_labels(a: &[String], b: &[String]) -> bool {
+    let normalize = |labels: &[String]| {
+        let mut labels: Vec<String> = labels.iter().map(|l| l.to_lowercase()).collect();
+        labels.sort();
+        labels.dedup();
+        labels
+    };
+    normalize(a) == normalize(b)
+}
+
+/// A job of a workflow run
+#[derive(Clone, Debug, PartialEq)]
+pub struct JobInfo {
+    pub job_id: u64,
+    pub name: String,
+    /// The `runs-on` labels
+    pub labels: Vec<String>,
+    /// `queued`, `in_progress`, `completed`, etc.
+    pub status: String,
+}
+
+#[derive(Debug)]
+pub struct SelfHostedRunner {
+    pub id: u64,
+    pub name: String,
+    pub status: String,
+    pub busy: bool,
 }
 
 impl GithubClient {
     pub fn new(config: &GithubConfig) -> GithubClient {
+        Self::builder(config)
+            .max_idle_connections(DEFAULT_MAX_IDLE_CONNECTIONS)
+            .build()
+    }
+
+    pub fn builder(config: &GithubConfig) -> GithubClientBuilder {
         static USER_AGENT: Lazy<String> = Lazy::new(|| {
             let mut buf = String::new();
             buf.push_str(env!("CARGO_PKG_NAME"));
@@ -24,63 +145,339 @@ impl GithubClient {
             buf
         });
 
-        GithubClient {
+        GithubClientBuilder {
             config: config.clone(),
-            agent: AgentBuilder::new()
-                .timeout(Duration::from_secs(10))
-                .user_agent(&USER_AGENT)
-                .build(),
+            agent_builder: AgentBuilder::new()
+                .timeout(Duration::from_secs(config.timeout_secs))
+                .timeout_connect(Duration::from_secs(config.connect_timeout_secs))
+                .user_agent(&USER_AGENT),
         }
     }
 
+    /// Retrieves the queued runs of the repository, or of all repositories of the organization
+    /// if `github.runners.scope` is `org`, because GitHub has no organization-level endpoint
+    /// for workflow runs.
     pub fn fetch_queued_workflow_runs(&self) -> Result<Vec<WorkflowRun>, Box<dyn Error>> {
-        let request_url = {
-            let mut buf = String::new();
-            buf.push_str(&self.config.runners.api_endpoint_url);
+        let repos = if self.config.runners.scope == "org" {
+            self.fetch_org_repos()?
+        } else {
+            vec![format!(
+                "{}/{}",
+                self.config.runners.repo_user, self.config.runners.repo_name
+            )]
+        };
+
+        let mut runs = vec![];
+        for repo in repos {
+            let limit = self.config.max_runs.map(|max| max - runs.len());
+            if limit == Some(0) {
+                break;
+            }
+
+            let request_url = format!(
+                "{}/repos/{}/actions/runs?status=queued&per_page=100",
+                self.config.runners.api_endpoint_url, repo
+            );
+            let array = self.fetch_all_pages(&request_url, Some("workflow_runs"), limit)?;
+            for run in array {
+                runs.push(self.parse_workflow_run(&repo, &run)?);
+            }
+        }
+        Ok(runs)
+    }
+
+    /// Retrieves the full names of the organization's repositories that are not archived.
+    fn fetch_org_repos(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let request_url = format!(
+            "{}/orgs/{}/repos?per_page=100",
+            self.config.runners.api_endpoint_url, self.config.runners.org_name
+        );
+        let array = self.fetch_all_pages(&request_url, None, None)?;
+
+        let mut repos = Vec::with_capacity(array.len());
+        for repo in array {
+            if repo["archived"].as_bool().unwrap_or(false) {
+                continue;
+            }
+            match repo["full_name"].as_str() {
+                Some(full_name) => repos.push(full_name.to_string()),
+                None => {
+                    return Err(
+                        "The response contains a repository without the 'full_name' field.".into(),
+                    )
+                }
+            }
+        }
+        Ok(repos)
+    }
+
+    fn parse_workflow_run(
+        &self,
+        repo: &str,
+        run: &serde_json::Value,
+    ) -> Result<WorkflowRun, Box<dyn Error>> {
+        let run_id = run["id"].as_u64();
+        let url = run["url"].as_str();
+        let (Some(run_id), Some(url)) = (run_id, url) else {
+            return Err("The response contains a run without the 'id' or 'url' field.".into());
+        };
+
+        let queued_jobs: Vec<JobInfo> = self
+            .fetch_workflow_run_jobs(repo, run_id)?
+            .into_iter()
+            .filter(|job| job.status == "queued")
+            .collect();
+        let mut required_labels: Vec<String> = vec![];
+        for label in queued_jobs.iter().flat_map(|job| &job.labels) {
+            if !required_labels
+                .iter()
+                .any(|l| l.eq_ignore_ascii_case(label))
+            {
+                required_labels.push(label.clone());
+            }
+        }
+
+        Ok(WorkflowRun {
+            run_id,
+            url: url.to_string(),
+            head_sha: run["head_sha"].as_str().unwrap_or_default().to_string(),
+            event: run["event"].as_str().unwrap_or_default().to_string(),
+            workflow_name: run["name"].as_str().unwrap_or_default().to_string(),
+            status: run["status"].as_str().unwrap_or_default().to_string(),
+            conclusion: run["conclusion"].as_str().map(str::to_string),
+            required_labels,
+            queued_jobs,
+        })
+    }
+
+    /// Retrieves the jobs of the latest attempt of the specified workflow run in `repo`,
+    /// e.g. `foo/bar`.
+    pub fn fetch_workflow_run_jobs(
+        &self,
+        repo: &str,
+        run_id: u64,
+    ) -> Result<Vec<JobInfo>, Box<dyn Error>> {
+        let request_url = format!(
+            "{}/repos/{}/actions/runs/{}/jobs?filter=latest&per_page=100",
+            self.config.runners.api_endpoint_url, repo, run_id
+        );
+        let array = self.fetch_all_pages(&request_url, Some("jobs"), None)?;
+
+        let mut jobs = Vec::with_capacity(array.len());
+        for job in array {
+            let job_id = job["id"].as_u64();
+            let status = job["status"].as_str();
+            if let (Some(job_id), Some(status)) = (job_id, status) {
+                jobs.push(JobInfo {
+                    job_id,
+                    name: job["name"].as_str().unwrap_or_default().to_string(),
+                    labels: job["labels"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|label| label.as_str())
+                        .map(|label| label.to_string())
+                        .collect(),
+                    status: status.to_string(),
+                });
+            } else {
+                return Err(
+                    "The response contains a job without the 'id' or 'status' field.".into(),
+                );
+            }
+        }
+        Ok(jobs)
+    }
+
+    pub fn fetch_self_hosted_runners(&self) -> Result<Vec<SelfHostedRunner>, Box<dyn Error>> {
+        let request_url = self.runners_api_url("/actions/runners?per_page=100");
+        let array = self.fetch_all_pages(&request_url, Some("runners"), None)?;
+
+        let mut runners = Vec::with_capacity(array.len());
+        for runner in array {
+            runners.push(parse_runner(&runner)?);
+        }
+        Ok(runners)
+    }
+
+    /// Retrieves the specified self-hosted runner, or `None` if it's not registered anymore.
+    pub fn fetch_self_hosted_runner(
+        &self,
+        runner_id: u64,
+    ) -> Result<Option<SelfHostedRunner>, Box<dyn Error>> {
+        let request_url = self.runners_api_url(&format!("/actions/runners/{}", runner_id));
+        match self.call("GET", &request_url) {
+            Ok(res) => Ok(Some(parse_runner(&res.into_json()?)?)),
+            Err(err) if matches!(*err, ureq::Error::Status(404, _)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Creates a short-lived token for registering a new runner,
+    /// and returns it with its expiration time.
+    pub fn get_runner_registration_token(&self) -> Result<(String, DateTime<Utc>), Box<dyn Error>> {
+        let request_url = self.runners_api_url("/actions/runners/registration-token");
+        let res: serde_json::Value = self.call("POST", &request_url)?.into_json()?;
+
+        let token = res["token"].as_str();
+        let expires_at = res["expires_at"].as_str();
+        if let (Some(token), Some(expires_at)) = (token, expires_at) {
+            Ok((
+                token.to_string(),
+                DateTime::parse_from_rfc3339(expires_at)?.to_utc(),
+            ))
+        } else {
+            Err("The response does not contain the 'token' or 'expires_at' field.".into())
+        }
+    }
+
+    pub fn delete_runner(&self, runner_id: u64) -> Result<(), Box<dyn Error>> {
+        let request_url = self.runners_api_url(&format!("/actions/runners/{}", runner_id));
+        self.call("DELETE", &request_url)?;
+        Ok(())
+    }
+
+    /// Retrieves the elements of the array field `field`, or of the response itself if `None`,
+    /// from all pages, following the `next` links in the `Link` response header.
+    /// Stops early once `limit` elements are retrieved.
+    fn fetch_all_pages(
+        &self,
+        url: &str,
+        field: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Vec<serde_json::Value>, Box<dyn Error>> {
+        let mut out: Vec<serde_json::Value> = vec![];
+        let mut next_url = Some(url.to_string());
+        while let Some(url) = next_url {
+            let res = self.call("GET", &url)?;
+            next_url = res.header("Link").and_then(parse_next_link);
+            let mut body: serde_json::Value = res.into_json()?;
+            let array = match field {
+                Some(field) => body[field].as_array_mut(),
+                None => body.as_array_mut(),
+            };
+            if let Some(array) = array {
+                out.append(array);
+            } else if let Some(field) = field {
+                return Err(
+                    format!("The response doesn't have an array field '{}'.", field).into(),
+                );
+            } else {
+                return Err("The response is not an array.".into());
+            }
+
+            if let Some(limit) = limit {
+                if out.len() >= limit {
+                    out.truncate(limit);
+                    break;
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Returns the URL of the runner API at `path`, under `/orgs/{org}` if `github.runners.scope`
+    /// is `org` or `/repos/{owner}/{repo}` otherwise.
+    fn runners_api_url(&self, path: &str) -> String {
+        let mut buf = String::new();
+        buf.push_str(&self.config.runners.api_endpoint_url);
+        if self.config.runners.scope == "org" {
+            buf.push_str("/orgs/");
+            buf.push_str(&self.config.runners.org_name);
+        } else {
             buf.push_str("/repos/");
             buf.push_str(&self.config.runners.repo_user);
             buf.push('/');
             buf.push_str(&self.config.runners.repo_name);
-            buf.push_str("/actions/runs?status=queued");
-            buf
-        };
+        }
+        buf.push_str(path);
+        buf
+    }
+
+    /// Sends a request, retrying up to `github.retry.max_retries` times if it failed transiently.
+    fn call(&self, method: &str, url: &str) -> Result<Response, Box<ureq::Error>> {
+        self.retry_with_backoff(method, url, || {
+            self.request(method, url).call().map_err(Box::new)
+        })
+    }
+
+    /// Invokes `f` until it succeeds, fails permanently or the retries are exhausted,
+    /// waiting `base_delay_ms * 2^attempt` milliseconds with jitter before every retry.
+    /// A connection error, a timeout and a 5xx response are considered transient.
+    fn retry_with_backoff<T>(
+        &self,
+        method: &str,
+        url: &str,
+        mut f: impl FnMut() -> Result<T, Box<ureq::Error>>,
+    ) -> Result<T, Box<ureq::Error>> {
+        let retry = &self.config.retry;
+        let mut attempt: u32 = 0;
+        loop {
+            let err = match f() {
+                Ok(value) => return Ok(value),
+                Err(err) => match *err {
+                    ureq::Error::Transport(_) | ureq::Error::Status(500..=599, _) => err,
+                    _ => return Err(err),
+                },
+            };
+            if attempt >= retry.max_retries {
+                return Err(err);
+            }
+
+            let delay_ms = retry
+                .base_delay_ms
+                .saturating_mul(1 << attempt.min(16))
+                .min(retry.max_delay_ms);
+            // Wait between 50% and 100% of the delay, so that the clients don't retry in lockstep.
+            let delay = Duration::from_millis(rand::rng().random_range(delay_ms / 2..=delay_ms));
+            attempt += 1;
+            warn!(
+                "{} {} failed; retrying in {:?} ({}/{}): {}",
+                method, url, delay, attempt, retry.max_retries, err
+            );
+            thread::sleep(delay);
+        }
+    }
 
-        let res: serde_json::Value = self
-            .agent
-            .get(&request_url)
+    fn request(&self, method: &str, url: &str) -> Request {
+        self.agent
+            .request(method, url)
             .set("Accept", "application/vnd.github+json")
             .set(
                 "Authorization",
                 &format!("Bearer {}", self.config.personal_access_token),
             )
-            .set("X-GitHub-Api-Version", "2022-11-28")
+            .set("X-GitHub-Api-Version", &self.config.api_version)
             .set("Accept-Encoding", "br, gzip")
-            .call()?
-            .into_json()?;
+    }
+}
 
-        if let Some(array) = res["workflow_runs"].as_array() {
-            let mut is_ok = true;
-            let runs = array
-                .iter()
-                .flat_map(|run| {
-                    if let Some(url) = run["url"].as_str() {
-                        Some(WorkflowRun {
-                            url: url.to_string(),
-                        })
-                    } else {
-                        is_ok = false;
-                        None
-                    }
-                })
-                .collect();
-
-            if is_ok {
-                Ok(runs)
-            } else {
-                Err("The response contains a run without the 'url' field.".into())
-            }
+fn parse_runner(runner: &serde_json::Value) -> Result<SelfHostedRunner, Box<dyn Error>> {
+    let id = runner["id"].as_u64();
+    let name = runner["name"].as_str();
+    let status = runner["status"].as_str();
+    if let (Some(id), Some(name), Some(status)) = (id, name, status) {
+        Ok(SelfHostedRunner {
+            id,
+            name: name.to_string(),
+            status: status.to_string(),
+            busy: runner["busy"].as_bool().unwrap_or(false),
+        })
+    } else {
+        Err("The response contains a runner without the 'id', 'name' or 'status' field.".into())
+    }
+}
+
+/// Extracts the URL of the next page from a `Link` header value, e.g.
+/// `<https://api.github.com/...?page=2>; rel="next", <https://api.github.com/...?page=5>; rel="last"`
+fn parse_next_link(link: &str) -> Option<String> {
+    link.split(',').find_map(|part| {
+        let mut segments = part.split(';').map(str::trim);
+        let url = segments.next()?.strip_prefix('<')?.strip_suffix('>')?;
+        if segments.any(|param| param == r#"rel="next""#) {
+            Some(url.to_string())
         } else {
-            Err("The response doesn't have an array field 'workflow_runs'.".into())
+            None
         }
-    }
+    })
 }
@@ -1,3 +1,8 @@
 pub mod config;
+pub mod display;
 pub mod github;
 pub mod machine;
+pub mod monitor;
+pub mod output;
+pub mod pool;
+pub mod preflight;
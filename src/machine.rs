@@ -1,58 +1,227 @@
-use crate::config::{Config, MachineConfig};
+use crate::config::{
+    Config, DockerConfig, MachineConfig, PullPolicy, VolumeMount, DEFAULT_CONTAINER_NAME_PREFIX,
+    DEFAULT_DOCKER_IMAGE,
+};
+use crate::github::{GithubClient, SelfHostedRunner, WorkflowRun};
+use crate::preflight::PreflightReport;
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
 use chrono::{DateTime, Datelike, ParseResult, Utc};
-use log::{debug, info};
 use maplit::hashmap;
-use ssh2::Session;
-use std::collections::HashMap;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize, Serializer};
+use socket2::{Domain, Protocol, Socket, Type};
+use ssh2::{Channel, CheckResult, ErrorCode, HashType, KnownHostFileKind, MethodType, Session};
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::error::Error;
-use std::fmt::Write;
-use std::io::Read;
-use std::net::{SocketAddr, TcpStream};
-use std::time::Duration;
+use std::fmt;
+use std::fmt::{Display, Formatter, Write};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write as _};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, trace, warn};
+use ureq::serde_json;
+use uuid::Uuid;
 
+/// A machine that hosts runner containers. The SSH session is opened on the first operation
+/// and reused by the later ones, including the ones of the clones.
+#[derive(Clone)]
 pub struct Machine {
-    config: MachineConfig,
+    config: Arc<MachineConfig>,
+    session: Arc<Mutex<Option<(SocketAddr, KeepaliveSession)>>>,
+    /// Whether the images have been pruned for `docker.auto_prune_on_start` in this scaling pass.
+    pruned_on_start: Arc<AtomicBool>,
 }
 
 impl Machine {
-    pub fn new(config: &MachineConfig) -> Self {
+    pub fn new(config: Arc<MachineConfig>) -> Self {
         Self {
-            config: config.clone(),
+            config,
+            session: Arc::new(Mutex::new(None)),
+            pruned_on_start: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Creates a machine and connects to it, so that an unreachable machine fails early rather
+    /// than on every operation. Returns the session as well, which the machine reuses.
+    pub fn new_with_session(config: Arc<MachineConfig>) -> Result<(Self, Session), Box<dyn Error>> {
+        let machine = Self::new(config);
+        let (_, session) = machine.session()?;
+        Ok((machine, session.session.clone()))
+    }
+
+    /// Connects to this machine unless connected already, so that an unreachable machine fails
+    /// early rather than on every operation.
+    pub fn connect_if_disconnected(&self) -> Result<(), Box<dyn Error>> {
+        self.session().map(|_| ())
+    }
+
+    pub fn id(&self) -> &str {
+        &self.config.id
+    }
+
+    pub fn config(&self) -> &MachineConfig {
+        &self.config
+    }
+
+    /// Returns the weight of this machine, which is at least 1.
+    pub fn weight(&self) -> u32 {
+        self.config.weight.max(1)
+    }
+
+    /// Returns whether new runners can be started on this machine, i.e. `enabled` is not `false`.
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled.unwrap_or(true)
+    }
+
+    /// Returns whether the runners on this machine can pick up a job that requires
+    /// the specified `runs-on` labels. The labels the runner registers by default,
+    /// i.e. `self-hosted`, `linux` and `x64`, are always satisfied.
+    pub fn satisfies(&self, required_labels: &[String]) -> bool {
+        const IMPLICIT_LABELS: [&str; 3] = ["self-hosted", "linux", "x64"];
+        required_labels.iter().all(|required| {
+            IMPLICIT_LABELS
+                .iter()
+                .any(|label| label.eq_ignore_ascii_case(required))
+                || self
+                    .config
+                    .labels
+                    .iter()
+                    .any(|label| label.eq_ignore_ascii_case(required))
+        })
+    }
+
+    /// Connects to this machine and runs `echo ok` without touching Docker, which is much cheaper
+    /// than [`Machine::fetch_runners`]. Returns the round-trip time of the command, failing if it
+    /// does not complete within `ssh.connect_timeout_secs`.
+    pub fn ping(&self) -> Result<Duration, Box<dyn Error>> {
+        let (socket_addr, mut sess) = self.session()?;
+        let timeout = Duration::from_secs(self.config.ssh.connect_timeout_secs);
+        let start_time = Instant::now();
+        let output = self.ssh_exec_with_timeout(
+            &socket_addr,
+            &mut sess,
+            &render_argv(&["echo", "ok"]),
+            timeout,
+        )?;
+        let latency = start_time.elapsed();
+        if output == "ok" {
+            debug!("[{}] Ping latency: {:?}", socket_addr, latency);
+            Ok(latency)
+        } else {
+            Err(format!("[{}] Unexpected output: {}", socket_addr, output).into())
         }
     }
 
     pub fn fetch_runners(&self) -> Result<Vec<RunnerInfo>, Box<dyn Error>> {
-        let (socket_addr, mut sess) = self.connect()?;
+        RunnerQuery::new().execute(self)
+    }
+
+    /// Retrieves the runners matching the specified query, failing if it does not complete
+    /// within `ssh.command_timeout_secs` in total. Every command is timed out as well, but
+    /// inspecting many containers one by one could take much longer than that.
+    fn fetch_matching_runners(
+        &self,
+        query: &RunnerQuery,
+    ) -> Result<Vec<RunnerInfo>, Box<dyn Error>> {
+        let (socket_addr, mut sess) = self.session()?;
 
         info!("[{}] Retrieving the list of runners ..", socket_addr);
 
-        let mut cmd = String::new();
-        cmd.push_str("docker container ls --all --no-trunc --filter ");
-        cmd.push_str_escaped("label=github-self-hosted-runner");
-        cmd.push_str(" --format {{.ID}} ");
-        cmd.push_str("| xargs --no-run-if-empty docker container inspect --format ");
-        cmd.push_str_escaped(
-            "{{.ID}}|{{.State.Status}}|{{.Created}}|{{.State.StartedAt}}|{{.State.FinishedAt}}",
-        );
+        let machine = self.clone();
+        let query = query.clone();
+        let timeout = Duration::from_secs(self.config.ssh.command_timeout_secs);
+        run_with_timeout("fetch_runners", timeout, move || {
+            machine
+                .fetch_matching_runners_with(&socket_addr, &mut sess, &query)
+                .map_err(|err| err.to_string())
+        })
+        .map_err(|_| {
+            // Don't reuse the session, which may be still busy with the timed out commands.
+            self.disconnect();
+            format!(
+                "[{}] fetch_runners timed out after {} second(s)",
+                socket_addr,
+                timeout.as_secs()
+            )
+        })?
+        .map_err(|err| err.into())
+    }
+
+    fn fetch_matching_runners_with(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+        query: &RunnerQuery,
+    ) -> Result<Vec<RunnerInfo>, Box<dyn Error>> {
+        let mut argv: Vec<String> = vec![
+            "docker".to_string(),
+            "container".to_string(),
+            "ls".to_string(),
+            "--all".to_string(),
+            "--no-trunc".to_string(),
+            "--filter".to_string(),
+            "label=github-self-hosted-runner".to_string(),
+        ];
+        // Let Docker filter by state so that we don't inspect the containers we don't need.
+        // Multiple 'status' filters match any of them.
+        if !query
+            .states
+            .iter()
+            .any(|state| matches!(state, ContainerState::Unknown(_)))
+        {
+            for state in &query.states {
+                argv.push("--filter".to_string());
+                argv.push(format!("status={}", state.as_str()));
+            }
+        }
+        argv.push("--format".to_string());
+        argv.push("{{.ID}}".to_string());
 
-        let output = Self::ssh_exec(&socket_addr, &mut sess, &cmd)?;
+        let output = self.ssh_exec_argv(socket_addr, session, &argv)?;
 
-        // Parse the output.
         let mut res: Vec<RunnerInfo> = vec![];
-        for line in output.lines() {
-            let fields: Vec<&str> = line.split(['|']).collect();
-            res.push(RunnerInfo {
-                container_id: fields[0].to_string(),
-                container_state: ContainerState::from(fields[1]),
-                created_at: Self::parse_timestamp(fields[2])?,
-                started_at: Self::parse_timestamp_opt(fields[3])?,
-                finished_at: Self::parse_timestamp_opt(fields[4])?,
-            });
+        for container_id in output.lines().filter(|line| !line.is_empty()) {
+            let inspected = self.inspect_container(socket_addr, session, container_id)?;
+            let runner = RunnerInfo::from_inspect(&inspected)?;
+            // Check again because the state may have changed since 'docker container ls'.
+            if query.matches(&runner) {
+                res.push(runner);
+            }
         }
 
         Ok(res)
     }
 
+    /// Runs `docker container inspect` for the specified container and returns its parsed output.
+    fn inspect_container(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+        container_id: &str,
+    ) -> Result<serde_json::Value, Box<dyn Error>> {
+        // Don't use '--format json', which is supported only since Docker 23.
+        // The default output is a JSON array on all versions.
+        let output = self.ssh_exec_argv(
+            socket_addr,
+            session,
+            &["docker", "container", "inspect", container_id],
+        )?;
+        parse_container_inspect(&output)
+            .map_err(|err| format!("[{}] {}: {}", socket_addr, err, container_id).into())
+    }
+
     fn parse_timestamp_opt(text: &str) -> ParseResult<Option<DateTime<Utc>>> {
         let timestamp = Self::parse_timestamp(text)?;
         if timestamp.year() > 1970 {
@@ -66,217 +235,2203 @@ impl Machine {
         Ok(DateTime::parse_from_rfc3339(text)?.to_utc())
     }
 
-    pub fn start_runner(&self, config: &Config) -> Result<(), Box<dyn Error>> {
-        let (socket_addr, mut sess) = self.connect()?;
+    /// Starts a runner dedicated to each of the specified runs, up to `limit` runners, and returns
+    /// what was done and why. This begins a new scaling pass, so call this once per pass before
+    /// starting any other runner. The offline runners of this machine are deregistered first if
+    /// `github.runners.deregister_offline` is true, and the images are pruned at most once until
+    /// the next pass if `docker.auto_prune_on_start` is true. A failure to start a runner is
+    /// returned as a [`ScalingAction::Error`] decision.
+    pub fn start_runners(
+        &self,
+        config: &Config,
+        github_client: &GithubClient,
+        runs: &[&WorkflowRun],
+        limit: u32,
+    ) -> Vec<ScalingDecision> {
+        self.pruned_on_start.store(false, Ordering::Relaxed);
+        if config.github.runners.deregister_offline {
+            if let Err(err) = self.deregister_offline_runners(github_client, config) {
+                return vec![self.decision(
+                    ScalingAction::Error,
+                    format!("Failed to deregister the offline runners: {}", err),
+                )];
+            }
+        }
+
+        let mut decisions: Vec<ScalingDecision> = vec![];
+        for (i, run) in runs.iter().enumerate().take(limit as usize) {
+            // The number of the runners assigned to the same run before this one.
+            let nth = runs[..i].iter().filter(|r| r.run_id == run.run_id).count();
+            info!(
+                machine = self.id(),
+                run_url = %run.url,
+                "[{}] Starting a new runner for: {}",
+                self.id(),
+                run.url
+            );
+            let decision = self
+                .start_runner(config, github_client, run, nth)
+                .unwrap_or_else(|err| ScalingDecision {
+                    run_url: Some(run.url.clone()),
+                    ..self.decision(
+                        ScalingAction::Error,
+                        format!("Failed to start a runner: {}", err),
+                    )
+                });
+            decisions.push(decision);
+        }
+        decisions
+    }
+
+    /// Starts a runner dedicated to the specified run, unless this machine has more than `nth`
+    /// live runners for the run already. `nth` is the number of the runners assigned to the same
+    /// run on this machine before this one, because a run gets a runner per queued job when its
+    /// jobs require different labels.
+    pub fn start_runner(
+        &self,
+        config: &Config,
+        github_client: &GithubClient,
+        run: &WorkflowRun,
+        nth: usize,
+    ) -> Result<ScalingDecision, Box<dyn Error>> {
+        // Don't start another container if there's already one for the same run,
+        // e.g. when the scaler was invoked twice in quick succession.
+        if let Some(runner) = RunnerQuery::alive()
+            .execute(self)?
+            .iter()
+            .filter(|runner| runner.run_id == Some(run.run_id))
+            .nth(nth)
+        {
+            return Ok(ScalingDecision {
+                run_url: Some(run.url.clone()),
+                container_id: Some(runner.container_id.clone()),
+                ..self.decision(
+                    ScalingAction::Skip,
+                    "A container has been started already for the run".to_string(),
+                )
+            });
+        }
+
+        let started = self.launch_runner(config, github_client, Some(run))?;
+        Ok(ScalingDecision {
+            run_url: Some(run.url.clone()),
+            container_id: Some(started.container_id),
+            container_name: Some(started.container_name),
+            ..self.decision(
+                ScalingAction::Start,
+                "Started a runner dedicated to the queued run".to_string(),
+            )
+        })
+    }
+
+    /// Starts or stops the runners on this machine, so that `target` runners are not running
+    /// a job, and returns what was done and why. The runners that GitHub reports as busy are not
+    /// counted, so that the idle runners are not stopped while jobs run. Only the idle runners
+    /// that are not dedicated to any of `queued_run_ids` are stopped, so that neither a job nor
+    /// a runner waiting for its queued run is interrupted. See [`plan_scaling`] for details.
+    /// A failure to start or stop a runner is returned as a [`ScalingAction::Error`] decision,
+    /// after which no more runners are started or stopped.
+    pub fn scale_to(
+        &self,
+        target: u32,
+        config: &Config,
+        github_client: &GithubClient,
+        queued_run_ids: &HashSet<u64>,
+    ) -> Result<Vec<ScalingDecision>, Box<dyn Error>> {
+        let live_runners = RunnerQuery::new()
+            .in_state(ContainerState::Running)
+            .in_state(ContainerState::Created)
+            .execute(self)?;
+        let github_runners = github_client.fetch_self_hosted_runners()?;
+        let max = self.config.runners.max;
+        let plan = plan_scaling(&live_runners, &github_runners, queued_run_ids, target, max);
+        let not_busy = plan.not_busy;
+
+        let mut decisions: Vec<ScalingDecision> = vec![];
+        if not_busy < target {
+            let reason = format!(
+                "{} runner(s) not running a job are fewer than {}",
+                not_busy, target
+            );
+            decisions = self.start_idle_runners(config, github_client, plan.start, &reason);
+            if plan.start < target - not_busy {
+                decisions.push(self.decision(
+                    ScalingAction::Skip,
+                    format!("{}, but 'runners.max' ({}) runners are alive", reason, max),
+                ));
+            }
+        } else if not_busy > target {
+            let reason = format!(
+                "{} runner(s) not running a job are more than {}",
+                not_busy, target
+            );
+            for (runner, runner_id) in &plan.stop {
+                match self.drain_runner(
+                    github_client,
+                    &runner.container_id,
+                    *runner_id,
+                    Duration::from_secs(u64::from(DEFAULT_STOP_TIMEOUT_SECS)),
+                ) {
+                    Ok(()) => decisions.push(ScalingDecision {
+                        container_id: Some(runner.container_id.clone()),
+                        ..self.decision(ScalingAction::Stop, reason.clone())
+                    }),
+                    Err(err) => {
+                        decisions.push(ScalingDecision {
+                            container_id: Some(runner.container_id.clone()),
+                            ..self.decision(
+                                ScalingAction::Error,
+                                format!("Failed to stop an idle runner: {}", err),
+                            )
+                        });
+                        break;
+                    }
+                }
+            }
+
+            if plan.kept > 0 {
+                decisions.push(self.decision(
+                    ScalingAction::Skip,
+                    format!(
+                        "{}, but {} of them are waiting for their queued runs or not idle yet",
+                        reason, plan.kept
+                    ),
+                ));
+            }
+        } else {
+            decisions.push(self.decision(
+                ScalingAction::Skip,
+                format!(
+                    "{} runner(s) not running a job are as many as needed",
+                    not_busy
+                ),
+            ));
+        }
+
+        Ok(decisions)
+    }
+
+    /// Starts the runners that are not dedicated to any run until `runners.min` runners are not
+    /// running a job, so that they wait for jobs as idle self-hosted runners. All live runners
+    /// are assumed to be idle if GitHub can't tell which runners are busy.
+    pub fn keep_warm(
+        &self,
+        config: &Config,
+        github_client: &GithubClient,
+    ) -> Result<Vec<ScalingDecision>, Box<dyn Error>> {
+        let live_runners = RunnerQuery::new()
+            .in_state(ContainerState::Running)
+            .in_state(ContainerState::Created)
+            .execute(self)?;
+        let busy_runner_names: HashSet<String> = match github_client.fetch_self_hosted_runners() {
+            Ok(runners) => runners
+                .into_iter()
+                .filter(|runner| runner.busy)
+                .map(|runner| runner.name)
+                .collect(),
+            Err(err) => {
+                warn!(
+                    machine = %self.config.id,
+                    "[{}] Failed to retrieve the busy runners; assuming none is busy: {}",
+                    self.config.id, err
+                );
+                HashSet::new()
+            }
+        };
+        let live = u32::try_from(live_runners.len()).unwrap_or(u32::MAX);
+        let not_busy = live_runners
+            .iter()
+            .filter(|runner| !busy_runner_names.contains(&runner.runner_name))
+            .count();
+        let not_busy = u32::try_from(not_busy).unwrap_or(u32::MAX);
+        let count = self.warm_up_count(not_busy, live);
+        if count == 0 {
+            return Ok(vec![]);
+        }
+
+        let reason = format!(
+            "{} runner(s) not running a job are fewer than 'runners.min' ({})",
+            not_busy,
+            self.config.runners.min.unwrap_or(0)
+        );
+        Ok(self.start_idle_runners(config, github_client, count, &reason))
+    }
+
+    /// Stops the runners that GitHub has reported as idle for longer than
+    /// `runners.idle_timeout_secs`, keeping `runners.min` of them warm, and returns what was
+    /// done and why. Stops at the first failure, which is returned as
+    /// a [`ScalingAction::Error`] decision.
+    pub fn stop_idle_runners(
+        &self,
+        github_client: &GithubClient,
+    ) -> Result<Vec<ScalingDecision>, Box<dyn Error>> {
+        let running_runners = RunnerQuery::new()
+            .in_state(ContainerState::Running)
+            .execute(self)?;
+        if running_runners.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let idle_runner_names: HashSet<String> = github_client
+            .fetch_self_hosted_runners()?
+            .into_iter()
+            .filter(|runner| runner.status == "online" && !runner.busy)
+            .map(|runner| runner.name)
+            .collect();
+        let timeout = Duration::from_secs(self.config.runners.idle_timeout_secs);
+        let keep = self.config.runners.min.unwrap_or(0) as usize;
+        let reason = format!(
+            "The runner has been idle for more than {} second(s)",
+            timeout.as_secs()
+        );
+
+        let mut decisions: Vec<ScalingDecision> = vec![];
+        for runner in find_idle_runners_to_stop(
+            &running_runners,
+            &idle_runner_names,
+            keep,
+            timeout,
+            Utc::now(),
+        ) {
+            match self.stop_runner(&runner.container_id, DEFAULT_STOP_TIMEOUT_SECS) {
+                Ok(()) => decisions.push(ScalingDecision {
+                    container_id: Some(runner.container_id.clone()),
+                    ..self.decision(ScalingAction::Stop, reason.clone())
+                }),
+                Err(err) => {
+                    decisions.push(ScalingDecision {
+                        container_id: Some(runner.container_id.clone()),
+                        ..self.decision(
+                            ScalingAction::Error,
+                            format!("Failed to stop an idle runner: {}", err),
+                        )
+                    });
+                    break;
+                }
+            }
+        }
+        Ok(decisions)
+    }
+
+    /// Returns the number of the runners to start for `runners.min` runners to be idle
+    /// when `live` runners are alive and `not_busy` of them are not running a job. The busy
+    /// runners count toward `runners.max` only.
+    pub fn warm_up_count(&self, not_busy: u32, live: u32) -> u32 {
+        let runners = &self.config.runners;
+        runners
+            .min
+            .unwrap_or(0)
+            .saturating_sub(not_busy)
+            .min(runners.max.saturating_sub(live))
+    }
+
+    /// Starts `count` runners that are not dedicated to any run. Stops at the first failure,
+    /// which is returned as a [`ScalingAction::Error`] decision.
+    fn start_idle_runners(
+        &self,
+        config: &Config,
+        github_client: &GithubClient,
+        count: u32,
+        reason: &str,
+    ) -> Vec<ScalingDecision> {
+        let mut decisions: Vec<ScalingDecision> = vec![];
+        for _ in 0..count {
+            match self.launch_runner(config, github_client, None) {
+                Ok(started) => decisions.push(ScalingDecision {
+                    container_id: Some(started.container_id),
+                    container_name: Some(started.container_name),
+                    ..self.decision(ScalingAction::Start, reason.to_string())
+                }),
+                Err(err) => {
+                    decisions.push(self.decision(
+                        ScalingAction::Error,
+                        format!("Failed to start a runner: {}", err),
+                    ));
+                    break;
+                }
+            }
+        }
+        decisions
+    }
+
+    fn decision(&self, action: ScalingAction, reason: String) -> ScalingDecision {
+        ScalingDecision {
+            machine_id: self.config.id.clone(),
+            action,
+            reason,
+            run_url: None,
+            container_id: None,
+            container_name: None,
+        }
+    }
+
+    /// Stops the specified runner container gracefully, and kills it if it does not stop
+    /// within `timeout_secs` seconds.
+    pub fn stop_runner(&self, container_id: &str, timeout_secs: u32) -> Result<(), Box<dyn Error>> {
+        let (socket_addr, mut sess) = self.session()?;
+
+        info!(
+            machine = %self.config.id,
+            container_id = %container_id,
+            "[{}] Stopping the container: {}",
+            socket_addr, container_id
+        );
+        let timeout_secs_str = timeout_secs.to_string();
+        let stop_argv = [
+            "docker",
+            "container",
+            "stop",
+            "--time",
+            &timeout_secs_str,
+            container_id,
+        ];
+        let stopped = match self.ssh_exec_argv(&socket_addr, &mut sess, &stop_argv) {
+            Ok(_) => {
+                let inspect_argv = [
+                    "docker",
+                    "container",
+                    "inspect",
+                    "--format",
+                    "{{.State.Running}}",
+                    container_id,
+                ];
+                self.ssh_exec_argv(&socket_addr, &mut sess, &inspect_argv)? != "true"
+            }
+            Err(err) => {
+                warn!(
+                    machine = %self.config.id,
+                    container_id = %container_id,
+                    "[{}] Failed to stop the container: {}",
+                    socket_addr, err
+                );
+                false
+            }
+        };
+
+        if stopped {
+            info!(
+                machine = %self.config.id,
+                container_id = %container_id,
+                "[{}] Stopped the container: {}",
+                socket_addr, container_id
+            );
+            Ok(())
+        } else {
+            warn!(
+                machine = %self.config.id,
+                container_id = %container_id,
+                "[{}] The container did not stop in {} second(s); killing it: {}",
+                socket_addr, timeout_secs, container_id
+            );
+            self.kill_container(&socket_addr, &mut sess, container_id)
+        }
+    }
+
+    /// Stops the specified runner container with `SIGTERM`, which lets the runner finish its
+    /// current job without accepting a new one, and waits until GitHub reports the runner with
+    /// `github_runner_id` as offline or deregistered. The container is killed if it does not stop
+    /// within `timeout`, and an error is returned if GitHub still reports the runner as online
+    /// after `timeout`.
+    pub fn drain_runner(
+        &self,
+        github_client: &GithubClient,
+        container_id: &str,
+        github_runner_id: u64,
+        timeout: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        let deadline = Instant::now() + timeout;
+        let timeout_secs = u32::try_from(timeout.as_secs()).unwrap_or(u32::MAX);
+        self.stop_runner(container_id, timeout_secs)?;
 
-        // TODO: Make the image URL configurable.
-        const IMAGE: &str = "ghcr.io/myoung34/docker-github-actions-runner:ubuntu-focal";
+        loop {
+            match github_client.fetch_self_hosted_runner(github_runner_id)? {
+                Some(runner) if runner.status != "offline" => {}
+                _ => {
+                    debug!(
+                        machine = %self.config.id,
+                        container_id = %container_id,
+                        "[{}] Drained the runner: {} (id: {})",
+                        self.config.id, container_id, github_runner_id
+                    );
+                    return Ok(());
+                }
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(format!(
+                    "[{}] The runner is still online {:?} after stopping the container: {} (id: {})",
+                    self.config.id, timeout, container_id, github_runner_id
+                )
+                .into());
+            }
+            thread::sleep(DRAIN_POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    /// Kills the specified runner container immediately.
+    pub fn force_stop_runner(&self, container_id: &str) -> Result<(), Box<dyn Error>> {
+        let (socket_addr, mut sess) = self.session()?;
+        self.kill_container(&socket_addr, &mut sess, container_id)
+    }
+
+    fn kill_container(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+        container_id: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        self.ssh_exec_argv(
+            socket_addr,
+            session,
+            &["docker", "container", "kill", container_id],
+        )?;
+
+        info!(
+            machine = %self.config.id,
+            container_id = %container_id,
+            "[{}] Killed the container: {}",
+            socket_addr, container_id
+        );
+        Ok(())
+    }
+
+    /// Starts a new runner container, optionally dedicated to the specified run,
+    /// and returns its container ID.
+    fn launch_runner(
+        &self,
+        config: &Config,
+        github_client: &GithubClient,
+        run: Option<&WorkflowRun>,
+    ) -> Result<StartedRunner, Box<dyn Error>> {
+        let (socket_addr, mut sess) = self.session()?;
+        self.preflight_check(&socket_addr, &mut sess)?;
+
+        let image = self.image();
+
+        let pull_policy = self.config.docker.pull_policy.unwrap_or_default();
+        let image_present = match pull_policy {
+            PullPolicy::Always => false,
+            PullPolicy::IfNotPresent => self.image_exists(&socket_addr, &mut sess, image)?,
+            PullPolicy::Never => {
+                self.ensure_runner_image_present(&socket_addr, &mut sess, image)?;
+                true
+            }
+        };
+        if should_pull_image(pull_policy, image_present)
+            .map_err(|err| format!("[{}] {}", socket_addr, err))?
+        {
+            for step in image_pull_steps(
+                &self.config.docker,
+                self.pruned_on_start.load(Ordering::Relaxed),
+            ) {
+                match step {
+                    ImagePullStep::Prune { hours } => {
+                        if self.pruned_on_start.swap(true, Ordering::Relaxed) {
+                            continue;
+                        }
+                        if let Err(err) =
+                            self.prune_images_older_than(&socket_addr, &mut sess, hours)
+                        {
+                            warn!(
+                                machine = %self.config.id,
+                                "[{}] Failed to prune the unused container images: {}",
+                                socket_addr, err
+                            );
+                        }
+                    }
+                    ImagePullStep::Pull => {
+                        info!(
+                            "[{}] Pulling the container image '{}' ..",
+                            socket_addr, image
+                        );
+                        let pull_cmd = render_argv(&["docker", "image", "pull", image]);
+                        let pull_timeout = Duration::from_millis(u64::from(sess.timeout()))
+                            .max(MIN_IMAGE_PULL_TIMEOUT);
+                        self.ssh_exec_with_timeout(
+                            &socket_addr,
+                            &mut sess,
+                            &pull_cmd,
+                            pull_timeout,
+                        )?;
+                    }
+                }
+            }
+
+            info!("[{}] Pulled the container image", socket_addr);
+        } else {
+            debug!(
+                "[{}] Using the local container image '{}'",
+                socket_addr, image
+            );
+        }
+
+        let uuid = Uuid::new_v4().to_string();
+        // Share the UUID with the runner name, so that we can tell which container runs which runner.
+        let container_name = format!("{}-{}", self.container_name_prefix(), uuid);
+        let runner_name_prefix = self.runner_name_prefix(config);
+        let runner_name = render_runner_name(
+            &config.github.runners.runner_name_template,
+            &hashmap! {
+                "prefix" => runner_name_prefix.as_str(),
+                "machine_id" => self.config.id.as_str(),
+                "uuid" => uuid.as_str(),
+            },
+        );
 
-        // FIXME(trustin): Pull only once a day.
-        //                 Keep the timestamp in ~/.cache/gh-actions-scaler (or $XDG_CACHE_HOME/...)
+        let run_url = run.map(|run| run.url.as_str());
         info!(
-            "[{}] Pulling the container image '{}' ..",
-            socket_addr, IMAGE
+            machine = %self.config.id,
+            run_url,
+            "[{}] Creating and starting a new container for runner '{}' ..",
+            socket_addr, runner_name
         );
-        let mut pull_cmd = String::new();
-        pull_cmd.push_str("docker image pull ");
-        pull_cmd.push_str_escaped(IMAGE);
-        Self::ssh_exec(&socket_addr, &mut sess, &pull_cmd)?;
-
-        info!("[{}] Pulled the container image", socket_addr);
-
-        // FIXME(trustin): Specify a unique yet identifiable container name.
-        //                 Use `docker container rename <container_id> github-self-hosted-runner-<container_id>
-        info!("[{}] Creating and starting a new container ..", socket_addr);
-        let mut run_cmd = String::new();
-        run_cmd.push_str("docker container run --detach --restart no --label ");
-        run_cmd.push_str_escaped("github-self-hosted-runner");
-        run_cmd.push_str(" --env ACCESS_TOKEN");
-        run_cmd.push_str(" --env REPO_URL=");
-        run_cmd.push_str_escaped(&config.github.runners.repo_url);
-        run_cmd.push_str(" --env RUNNER_NAME_PREFIX=");
-        run_cmd.push_str_escaped(&config.github.runners.name_prefix);
-        run_cmd.push_str(" --env RUNNER_SCOPE=");
-        run_cmd.push_str_escaped(&config.github.runners.scope);
-        run_cmd.push_str(" --env EPHEMERAL=true");
-        run_cmd.push_str(" --env UNSET_CONFIG_VARS=true ");
-        run_cmd.push_str_escaped(IMAGE);
-
-        let container_id = Self::ssh_exec_with_env(
+        let mut run_argv: Vec<String> = vec![
+            "docker".to_string(),
+            "container".to_string(),
+            "run".to_string(),
+            "--detach".to_string(),
+            "--name".to_string(),
+            container_name.clone(),
+            "--restart".to_string(),
+            self.config
+                .docker
+                .restart_policy
+                .unwrap_or_default()
+                .to_string(),
+        ];
+        // Add the user-specified labels first, so that they cannot override the ones below.
+        run_argv.extend(render_extra_label_args(&self.config.docker.extra_labels));
+        run_argv.extend([
+            "--label".to_string(),
+            "github-self-hosted-runner".to_string(),
+            // Keep the runner name in a label, so that we can tell which runner belongs to which container.
+            "--label".to_string(),
+            format!("github-runner-name={}", runner_name),
+        ]);
+        if let Some(run) = run {
+            run_argv.push("--label".to_string());
+            run_argv.push(format!("github-run-url={}", run.url));
+            run_argv.push("--label".to_string());
+            run_argv.push(format!("github-run-id={}", run.run_id));
+        }
+        if let Some(network) = &self.config.docker.network {
+            run_argv.push("--network".to_string());
+            run_argv.push(network.clone());
+        }
+        if let Some(memory_limit_bytes) = self.config.docker.memory_limit_bytes {
+            run_argv.push("--memory".to_string());
+            run_argv.push(memory_limit_bytes.to_string());
+        }
+        // Pass a short-lived registration token rather than the personal access token if possible.
+        let (token_env_name, token) = if config.github.runners.use_registration_token {
+            let (token, expires_at) = github_client.get_runner_registration_token()?;
+            debug!(
+                "[{}] Fetched a runner registration token that expires at {}",
+                socket_addr, expires_at
+            );
+            ("RUNNER_TOKEN", token)
+        } else {
+            ("ACCESS_TOKEN", config.github.personal_access_token.clone())
+        };
+        run_argv.extend(render_volume_args(&self.config.docker.volumes));
+        // Add the user-specified variables first, so that they cannot override the ones below.
+        run_argv.extend(render_extra_env_args(&self.config.docker.extra_env));
+        let mut env_vars: Vec<String> = vec![
+            token_env_name.to_string(),
+            if config.github.runners.scope == "org" {
+                format!("ORG_NAME={}", config.github.runners.org_name)
+            } else {
+                format!("REPO_URL={}", config.github.runners.repo_url)
+            },
+            format!("RUNNER_NAME={}", runner_name),
+            format!("RUNNER_SCOPE={}", config.github.runners.scope),
+        ];
+        if let Some(runner_group) = &config.github.runners.runner_group {
+            env_vars.push(format!("RUNNER_GROUP={}", runner_group));
+        }
+        if let Some(work_dir) = &config.github.runners.work_dir {
+            env_vars.push(format!("RUNNER_WORKDIR={}", work_dir));
+        }
+        if !self.config.labels.is_empty() {
+            env_vars.push(format!("LABELS={}", self.config.labels.join(",")));
+        }
+        env_vars.push(format!(
+            "EPHEMERAL={}",
+            self.config.runners.ephemeral.unwrap_or(true)
+        ));
+        env_vars.push(format!(
+            "UNSET_CONFIG_VARS={}",
+            self.config.runners.unset_config_vars.unwrap_or(true)
+        ));
+        for env_var in env_vars {
+            run_argv.push("--env".to_string());
+            run_argv.push(env_var);
+        }
+        run_argv.push(image.to_string());
+
+        let output = self.ssh_exec_with_env(
             &socket_addr,
             &mut sess,
             &hashmap! {
-                "ACCESS_TOKEN" => config.github.personal_access_token.as_str(),
+                token_env_name => token.as_str(),
             },
-            &run_cmd,
+            &render_argv(&run_argv),
         )?;
+        let container_id =
+            parse_container_id(&output).map_err(|err| format!("[{}] {}", socket_addr, err))?;
         info!(
-            "[{}] Started a new container: {}",
-            socket_addr, container_id
+            machine = %self.config.id,
+            run_url,
+            container_id = %container_id,
+            "[{}] Started a new container: {} ({})",
+            socket_addr, container_name, container_id
+        );
+
+        Ok(StartedRunner {
+            container_id,
+            container_name,
+        })
+    }
+
+    /// Streams the last `lines` lines of the specified container's output followed by the new
+    /// lines as they arrive. The iterator ends when the container exits.
+    pub fn tail_runner_logs(
+        &self,
+        container_id: &str,
+        lines: usize,
+    ) -> Result<impl Iterator<Item = String>, Box<dyn Error>> {
+        // Use a dedicated session, because the timeout below must not affect the other operations.
+        let (socket_addr, sess) = self.connect()?;
+        // Wait for the new lines indefinitely rather than failing after 'ssh.command_timeout_secs'.
+        sess.set_timeout(0);
+
+        let mut cmd = render_argv(&[
+            "docker",
+            "container",
+            "logs",
+            "--follow",
+            "--tail",
+            &lines.to_string(),
+            container_id,
+        ]);
+        // Merge the container's standard error into the standard output.
+        cmd.push_str(" 2>&1");
+
+        let mut ch = sess.channel_session()?;
+        ch.exec(&cmd)?;
+        ch.send_eof()?;
+
+        Ok(RunnerLogLines {
+            socket_addr,
+            reader: BufReader::new(ch),
+            _session: sess,
+        })
+    }
+
+    /// Downloads the output of the specified container into `local_path`. Unlike
+    /// [`Machine::tail_runner_logs`], the output is written into a temporary file on this machine
+    /// first and then streamed via SFTP, so that a large output is never held in memory.
+    /// The temporary file is deleted afterwards.
+    pub fn copy_logs_via_sftp(
+        &self,
+        container_id: &str,
+        local_path: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        let (socket_addr, mut sess) = self.session()?;
+        let remote_path = format!("/tmp/gh-actions-scaler-{}.log", Uuid::new_v4());
+
+        info!(
+            machine = %self.config.id,
+            container_id = %container_id,
+            "[{}] Writing the output of the container to: {}",
+            socket_addr, remote_path
+        );
+        // Keep the output private, because it may contain credentials.
+        let mut cmd = "umask 077 && ".to_string();
+        cmd.push_str(&render_argv(&["docker", "container", "logs", container_id]));
+        cmd.push_str(" > ");
+        cmd.push_str_escaped(&remote_path);
+        cmd.push_str(" 2>&1");
+        let result = self.ssh_exec(&socket_addr, &mut sess, &cmd).and_then(|_| {
+            let sftp = sess.sftp()?;
+            let mut remote_file = sftp.open(Path::new(&remote_path))?;
+            let mut local_file = File::create(local_path)?;
+            let bytes = io::copy(&mut remote_file, &mut local_file)?;
+            debug!(
+                "[{}] Downloaded {} byte(s) to: {}",
+                socket_addr,
+                bytes,
+                local_path.display()
+            );
+            Ok(())
+        });
+
+        // Delete the temporary file even if the download failed.
+        let rm_argv = ["rm", "-f", remote_path.as_str()];
+        if let Err(err) = self.ssh_exec_argv(&socket_addr, &mut sess, &rm_argv) {
+            warn!(
+                "[{}] Failed to delete the temporary file: {}: {}",
+                socket_addr, remote_path, err
+            );
+        }
+        result
+    }
+
+    /// Removes the unused container images older than `docker.prune_older_than_hours`,
+    /// and returns the number of the reclaimed bytes.
+    pub fn prune_docker_images(&self) -> Result<u64, Box<dyn Error>> {
+        let (socket_addr, mut sess) = self.session()?;
+        self.prune_images_older_than(
+            &socket_addr,
+            &mut sess,
+            self.config.docker.prune_older_than_hours,
+        )
+    }
+
+    fn prune_images_older_than(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+        hours: u64,
+    ) -> Result<u64, Box<dyn Error>> {
+        info!(
+            machine = %self.config.id,
+            "[{}] Pruning the unused container images older than {} hour(s) ..",
+            socket_addr, hours
+        );
+        let output = self.ssh_exec_argv(socket_addr, session, &render_image_prune_argv(hours))?;
+
+        let reclaimed = parse_reclaimed_space(&output).ok_or_else(|| {
+            format!(
+                "[{}] Failed to parse the reclaimed space: {}",
+                socket_addr, output
+            )
+        })?;
+        info!(
+            machine = %self.config.id,
+            "[{}] Pruned the unused container images; reclaimed {} bytes",
+            socket_addr, reclaimed
+        );
+        Ok(reclaimed)
+    }
+
+    /// Collects the state of this machine checked by [`MachinePreflight`], i.e. the Docker
+    /// version, the available disk space and memory, and whether the runner image exists.
+    /// The disk space and memory are `None` if they could not be determined.
+    pub fn fetch_preflight_report(&self) -> Result<PreflightReport, Box<dyn Error>> {
+        let (socket_addr, mut sess) = self.session()?;
+        let docker_version = self.fetch_docker_version(&socket_addr, &mut sess)?;
+
+        let disk_usage =
+            match self.fetch_host_disk_usage(&socket_addr, &mut sess, "/var/lib/docker") {
+                Ok(disk_usage) => Some(disk_usage),
+                Err(err) => {
+                    debug!(
+                        "[{}] Failed to retrieve the available disk space: {}",
+                        socket_addr, err
+                    );
+                    None
+                }
+            };
+        let memory = match self.fetch_host_memory(&socket_addr, &mut sess) {
+            Ok(memory) => Some(memory),
+            Err(err) => {
+                debug!(
+                    "[{}] Failed to retrieve the available memory: {}",
+                    socket_addr, err
+                );
+                None
+            }
+        };
+        let running_runners = match self.fetch_matching_runners_with(
+            &socket_addr,
+            &mut sess,
+            &RunnerQuery::new().in_state(ContainerState::Running),
+        ) {
+            Ok(runners) => Some(u32::try_from(runners.len()).unwrap_or(u32::MAX)),
+            Err(err) => {
+                debug!(
+                    "[{}] Failed to retrieve the list of runners: {}",
+                    socket_addr, err
+                );
+                None
+            }
+        };
+        // Fail fast, because the runners can't start at all without the image.
+        let image_cached = if self.config.docker.pull_policy == Some(PullPolicy::Never) {
+            self.ensure_runner_image_present(&socket_addr, &mut sess, self.image())?;
+            true
+        } else {
+            self.image_exists(&socket_addr, &mut sess, self.image())?
+        };
+
+        Ok(PreflightReport {
+            docker_version,
+            disk_usage,
+            memory,
+            running_runners,
+            image_cached,
+            warnings: vec![],
+        })
+    }
+
+    /// Retrieves the total and available disk space of the file system that contains `path`.
+    fn fetch_host_disk_usage(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+        path: &str,
+    ) -> Result<DiskUsage, Box<dyn Error>> {
+        let output = self.ssh_exec_argv(socket_addr, session, &["df", "-Pk", path])?;
+        parse_df_usage(&output)
+            .ok_or_else(|| format!("Unexpected output of 'df -Pk {}': {}", path, output).into())
+    }
+
+    /// Retrieves the total and available memory of this machine.
+    fn fetch_host_memory(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+    ) -> Result<MemoryInfo, Box<dyn Error>> {
+        let output = self.ssh_exec_argv(socket_addr, session, &["free", "-b"])?;
+        parse_free_memory(&output)
+            .ok_or_else(|| format!("Unexpected output of 'free -b': {}", output).into())
+    }
+
+    /// Lists the images cached on this machine, the most recently created first.
+    pub fn list_docker_images(&self) -> Result<Vec<DockerImage>, Box<dyn Error>> {
+        let (socket_addr, mut sess) = self.session()?;
+        // '{{json .}}' prints the same fields as '--format json', which old Docker versions lack.
+        let output = self.ssh_exec_argv(
+            &socket_addr,
+            &mut sess,
+            &[
+                "docker",
+                "image",
+                "ls",
+                "--no-trunc",
+                "--format",
+                "{{json .}}",
+            ],
+        )?;
+        parse_docker_images(&output).map_err(|err| format!("[{}] {}", socket_addr, err).into())
+    }
+
+    /// Returns whether the runner image exists on this machine.
+    pub fn is_runner_image_cached(&self) -> Result<bool, Box<dyn Error>> {
+        let (socket_addr, mut sess) = self.session()?;
+        self.image_exists(&socket_addr, &mut sess, self.image())
+    }
+
+    /// Updates the CPU and memory limits of the specified running container.
+    pub fn apply_resource_limits(
+        &self,
+        container_id: &str,
+        limits: &DockerLimits,
+    ) -> Result<(), Box<dyn Error>> {
+        if limits.cpus.is_none() && limits.memory_bytes.is_none() {
+            return Err("No resource limits to apply".into());
+        }
+        if let Some(cpus) = limits.cpus {
+            if !cpus.is_finite() || cpus <= 0.0 {
+                return Err(format!("Invalid number of CPUs: {}", cpus).into());
+            }
+        }
+
+        let (socket_addr, mut sess) = self.session()?;
+        self.ssh_exec_argv(
+            &socket_addr,
+            &mut sess,
+            &render_update_argv(container_id, limits),
+        )?;
+
+        info!(
+            machine = %self.config.id,
+            container_id = %container_id,
+            "[{}] Updated the resource limits of the container {}: {}",
+            socket_addr, container_id, limits
+        );
+        Ok(())
+    }
+
+    /// Samples the CPU and memory usage of the specified running container.
+    pub fn get_resource_usage(&self, container_id: &str) -> Result<ContainerStats, Box<dyn Error>> {
+        self.get_resource_usages(&[container_id])?
+            .pop()
+            .ok_or_else(|| format!("No resource usage of the container: {}", container_id).into())
+    }
+
+    /// Samples the CPU and memory usage of the specified running containers at once,
+    /// in the same order.
+    pub fn get_resource_usages(
+        &self,
+        container_ids: &[&str],
+    ) -> Result<Vec<ContainerStats>, Box<dyn Error>> {
+        if container_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let (socket_addr, mut sess) = self.session()?;
+        // Don't use '--format json', which is supported only since Docker 23.
+        let mut argv = vec![
+            "docker",
+            "container",
+            "stats",
+            "--no-stream",
+            "--format",
+            "{{json .}}",
+        ];
+        argv.extend_from_slice(container_ids);
+        let output = self.ssh_exec_argv(&socket_addr, &mut sess, &argv)?;
+
+        output
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                parse_container_stats(line)
+                    .map_err(|err| format!("[{}] {}", socket_addr, err).into())
+            })
+            .collect()
+    }
+
+    /// Deregisters all offline runners of this machine, even if their containers are still alive,
+    /// so that their names can never collide with a new runner. Returns the IDs of
+    /// the deregistered runners.
+    pub fn deregister_offline_runners(
+        &self,
+        github_client: &GithubClient,
+        config: &Config,
+    ) -> Result<Vec<u64>, Box<dyn Error>> {
+        let runner_name_re = self.runner_name_regex(config);
+        let mut deregistered = vec![];
+        for runner in github_client.fetch_self_hosted_runners()? {
+            if runner.status != "offline" || !runner_name_re.is_match(&runner.name) {
+                continue;
+            }
+
+            info!(
+                machine = %self.config.id,
+                "[{}] Deregistering an offline runner: {} (id: {})",
+                self.config.id, runner.name, runner.id
+            );
+            github_client.delete_runner(runner.id)?;
+            deregistered.push(runner.id);
+        }
+
+        Ok(deregistered)
+    }
+
+    /// Returns the regular expression that matches only the names of the runners rendered from
+    /// our template for this machine.
+    fn runner_name_regex(&self, config: &Config) -> Regex {
+        let runner_name_prefix = self.runner_name_prefix(config);
+        runner_name_regex(
+            &config.github.runners.runner_name_template,
+            &hashmap! {
+                "prefix" => runner_name_prefix.as_str(),
+                "machine_id" => self.config.id.as_str(),
+            },
+        )
+    }
+
+    /// Returns `github.runners.name_prefix` with its `{machine_id}` replaced with the ID of
+    /// this machine.
+    fn runner_name_prefix(&self, config: &Config) -> String {
+        render_runner_name(
+            &config.github.runners.name_prefix,
+            &hashmap! { "machine_id" => self.config.id.as_str() },
+        )
+    }
+
+    /// Returns the SSH session of this machine, connecting to it if not connected yet.
+    fn session(&self) -> Result<(SocketAddr, KeepaliveSession), Box<dyn Error>> {
+        let mut session = self.session.lock().unwrap();
+        if let Some(session) = session.as_ref() {
+            return Ok(session.clone());
+        }
+
+        let new_session = self.connect()?;
+        *session = Some(new_session.clone());
+        Ok(new_session)
+    }
+
+    /// Discards the SSH session of this machine, so that the next operation connects again.
+    fn disconnect(&self) {
+        *self.session.lock().unwrap() = None;
+    }
+
+    fn connect(&self) -> Result<(SocketAddr, KeepaliveSession), Box<dyn Error>> {
+        // Connect to the SSH server
+        let host = self.config.ssh.host.as_str();
+        let socket_addr = (host, self.config.ssh.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| format!("Failed to resolve the host: {}", host))?;
+        let max_retries = self.config.ssh.max_retries.unwrap_or(0);
+        let mut attempt: u32 = 0;
+        let sess = loop {
+            match self.handshake(&socket_addr) {
+                Ok(sess) => break sess,
+                Err(err) => {
+                    if attempt >= max_retries {
+                        return Err(err);
+                    }
+
+                    let delay = Duration::from_millis(
+                        self.config
+                            .ssh
+                            .retry_base_delay_ms
+                            .saturating_mul(1 << attempt.min(16)),
+                    );
+                    attempt += 1;
+                    warn!(
+                        "[{}] Connection attempt failed; retrying in {:?} ({}/{}): {}",
+                        socket_addr, delay, attempt, max_retries, err
+                    );
+                    thread::sleep(delay);
+                }
+            }
+        };
+
+        self.verify_fingerprint(&socket_addr, &sess)?;
+        self.verify_known_hosts(&socket_addr, &sess)?;
+
+        debug!(
+            "[{}] SSH session established; authenticating ..",
+            socket_addr
         );
+        if let Some(agent_socket) = &self.config.ssh.agent_socket {
+            debug!(
+                "[{}] Using SSH agent authentication via: {}",
+                socket_addr, agent_socket
+            );
+            self.userauth_agent(&socket_addr, &sess, agent_socket)?;
+        } else if self.config.ssh.password.is_empty() {
+            let certificate = self.config.ssh.certificate.as_deref();
+            if certificate.is_some() {
+                debug!("[{}] Using certificate authentication", socket_addr);
+            } else {
+                debug!("[{}] Using private key authentication", socket_addr);
+            }
+            // libssh2 sends the certificate in place of the public key.
+            sess.userauth_pubkey_memory(
+                &self.config.ssh.username,
+                certificate,
+                &self.config.ssh.private_key,
+                self.passphrase_opt(),
+            )?;
+        } else {
+            debug!("[{}] Using password authentication", socket_addr);
+            sess.userauth_password(&self.config.ssh.username, &self.config.ssh.password)?;
+        }
+
+        if !sess.authenticated() {
+            return Err("Authentication failed".into());
+        }
+
+        let interval = self.config.ssh.server_alive_interval_secs.unwrap_or(30);
+        Ok((socket_addr, KeepaliveSession::new(sess, interval)))
+    }
+
+    /// Tries every identity held by the SSH agent listening on `agent_socket`
+    /// until one of them is accepted.
+    fn userauth_agent(
+        &self,
+        socket_addr: &SocketAddr,
+        sess: &Session,
+        agent_socket: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        // libssh2 always connects to the socket at $SSH_AUTH_SOCK, and changing the environment
+        // while other threads may read it is not safe, so only that socket can be used.
+        if env::var_os("SSH_AUTH_SOCK").is_none_or(|socket| socket != agent_socket) {
+            return Err(format!(
+                "'ssh.agent_socket' ({}) must be the same as $SSH_AUTH_SOCK of gh-actions-scaler",
+                agent_socket
+            )
+            .into());
+        }
+
+        let mut agent = sess.agent()?;
+        agent.connect()?;
+        agent.list_identities()?;
+
+        let username = &self.config.ssh.username;
+        for identity in agent.identities()? {
+            match agent.userauth(username, &identity) {
+                Ok(()) => {
+                    debug!(
+                        "[{}] Authenticated with the SSH agent identity: {}",
+                        socket_addr,
+                        identity.comment()
+                    );
+                    let _ = agent.disconnect();
+                    return Ok(());
+                }
+                Err(err) => {
+                    debug!(
+                        "[{}] SSH agent identity rejected: {} ({})",
+                        socket_addr,
+                        identity.comment(),
+                        err
+                    );
+                }
+            }
+        }
+
+        let _ = agent.disconnect();
+        Err(format!(
+            "None of the identities in the SSH agent at '{}' was accepted for user '{}'",
+            agent_socket, username
+        )
+        .into())
+    }
+
+    /// Ensures the host key matches `ssh.fingerprint` if specified, in either the MD5
+    /// (`12:34:...`) or the SHA-256 (`SHA256:...`) format of `ssh-keygen -l`.
+    fn verify_fingerprint(
+        &self,
+        socket_addr: &SocketAddr,
+        sess: &Session,
+    ) -> Result<(), Box<dyn Error>> {
+        let expected = self.config.ssh.fingerprint.trim();
+        if expected.is_empty() {
+            return Ok(());
+        }
+
+        let (actual, matches) = if expected.starts_with("SHA256:") {
+            let actual = sess
+                .host_key_hash(HashType::Sha256)
+                .map(|hash| format!("SHA256:{}", STANDARD_NO_PAD.encode(hash)));
+            let matches = actual.as_deref() == Some(expected);
+            (actual, matches)
+        } else {
+            let actual = sess.host_key_hash(HashType::Md5).map(|hash| {
+                hash.iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<Vec<String>>()
+                    .join(":")
+            });
+            let matches = actual.as_deref().is_some_and(|actual| {
+                actual.eq_ignore_ascii_case(expected.trim_start_matches("MD5:"))
+            });
+            (actual, matches)
+        };
+
+        if matches {
+            debug!("[{}] Verified the host key fingerprint", socket_addr);
+            Ok(())
+        } else {
+            Err(format!(
+                "[{}] Host key fingerprint mismatch (expected: {}, actual: {})",
+                socket_addr,
+                expected,
+                actual.as_deref().unwrap_or("unknown")
+            )
+            .into())
+        }
+    }
+
+    /// Ensures the host key matches the entry in `ssh.known_hosts_file` if specified.
+    /// A host without an entry is accepted with a warning, like `StrictHostKeyChecking=no`.
+    fn verify_known_hosts(
+        &self,
+        socket_addr: &SocketAddr,
+        sess: &Session,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(known_hosts_file) = &self.config.ssh.known_hosts_file else {
+            return Ok(());
+        };
+
+        let mut known_hosts = sess.known_hosts()?;
+        known_hosts
+            .read_file(Path::new(known_hosts_file), KnownHostFileKind::OpenSSH)
+            .map_err(|err| {
+                format!(
+                    "[{}] Failed to read the known hosts file '{}': {}",
+                    socket_addr, known_hosts_file, err
+                )
+            })?;
+
+        let (key, _) = sess
+            .host_key()
+            .ok_or_else(|| format!("[{}] Failed to get the host key", socket_addr))?;
+        let host = self.config.ssh.host.as_str();
+        match known_hosts.check_port(host, self.config.ssh.port, key) {
+            CheckResult::Match => {
+                debug!(
+                    "[{}] Verified the host key with the known hosts",
+                    socket_addr
+                );
+                Ok(())
+            }
+            CheckResult::NotFound => {
+                warn!(
+                    "[{}] '{}' is not in the known hosts file '{}'; accepting its host key",
+                    socket_addr, host, known_hosts_file
+                );
+                Ok(())
+            }
+            CheckResult::Mismatch => Err(format!(
+                "[{}] Host key mismatch for '{}' in the known hosts file '{}'",
+                socket_addr, host, known_hosts_file
+            )
+            .into()),
+            CheckResult::Failure => Err(format!(
+                "[{}] Failed to check the host key of '{}' against the known hosts file '{}'",
+                socket_addr, host, known_hosts_file
+            )
+            .into()),
+        }
+    }
+
+    fn handshake(&self, socket_addr: &SocketAddr) -> Result<Session, Box<dyn Error>> {
+        let connect_timeout = Duration::from_secs(self.config.ssh.connect_timeout_secs);
+        let tcp = match &self.config.ssh.bind_address {
+            Some(bind_address) => {
+                debug!(
+                    "[{}] Making a connection attempt from {} ..",
+                    socket_addr, bind_address
+                );
+                connect_from(bind_address.parse()?, socket_addr, connect_timeout)?
+            }
+            None => {
+                debug!("[{}] Making a connection attempt ..", socket_addr);
+                TcpStream::connect_timeout(socket_addr, connect_timeout)?
+            }
+        };
+        debug!(
+            "[{}] Connection established; creating an SSH session ..",
+            socket_addr
+        );
+        let mut sess = Session::new()?;
+        // Applies to every blocking operation in the session, including the commands we execute.
+        sess.set_timeout(
+            u32::try_from(self.config.ssh.command_timeout_secs.saturating_mul(1000))
+                .unwrap_or(u32::MAX),
+        );
+        sess.set_compress(self.config.ssh.compression.unwrap_or(false));
+        sess.set_tcp_stream(tcp);
+        if let Some(host_key_algorithm) = &self.config.ssh.host_key_algorithm {
+            sess.method_pref(MethodType::HostKey, host_key_algorithm)?;
+        }
+        sess.handshake()?;
+        debug!(
+            "[{}] Negotiated the host key algorithm: {}",
+            socket_addr,
+            sess.methods(MethodType::HostKey).unwrap_or("unknown")
+        );
+        Ok(sess)
+    }
+
+    fn image(&self) -> &str {
+        self.config
+            .docker
+            .image
+            .as_deref()
+            .unwrap_or(DEFAULT_DOCKER_IMAGE)
+    }
+
+    fn container_name_prefix(&self) -> &str {
+        self.config
+            .docker
+            .container_name_prefix
+            .as_deref()
+            .unwrap_or(DEFAULT_CONTAINER_NAME_PREFIX)
+    }
+
+    fn passphrase_opt(&self) -> Option<&str> {
+        let passphrase = &self.config.ssh.private_key_passphrase;
+        if passphrase.is_empty() {
+            None
+        } else {
+            Some(passphrase)
+        }
+    }
+
+    fn image_exists(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+        image: &str,
+    ) -> Result<bool, Box<dyn Error>> {
+        // 'docker image inspect' fails if the image does not exist.
+        let mut cmd = render_argv(&["docker", "image", "inspect", "--format", "{{.ID}}", image]);
+        cmd.push_str(" >/dev/null 2>&1 && echo true || echo false");
+        Ok(self.ssh_exec(socket_addr, session, &cmd)? == "true")
+    }
+
+    /// Fails with the way to fix it if the specified image doesn't exist on this machine,
+    /// which `docker container run` would fail obscurely for when `docker.pull_policy` is `never`.
+    fn ensure_runner_image_present(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+        image: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.image_exists(socket_addr, session, image)? {
+            Ok(())
+        } else {
+            let pull_policy = self.config.docker.pull_policy.unwrap_or_default();
+            Err(format!(
+                "[{}] {}",
+                socket_addr,
+                missing_image_message(image, pull_policy)
+            )
+            .into())
+        }
+    }
+
+    /// Ensures the remote Docker daemon is recent enough to run the runner containers.
+    fn preflight_check(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+    ) -> Result<(), Box<dyn Error>> {
+        let version = self.fetch_docker_version(socket_addr, session)?;
+        if version < MIN_DOCKER_VERSION {
+            return Err(format!(
+                "[{}] Docker {}.{}.{} is too old; {}.{} or later is required",
+                socket_addr,
+                version.0,
+                version.1,
+                version.2,
+                MIN_DOCKER_VERSION.0,
+                MIN_DOCKER_VERSION.1
+            )
+            .into());
+        }
+
+        debug!(
+            "[{}] Detected Docker {}.{}.{}",
+            socket_addr, version.0, version.1, version.2
+        );
+        Ok(())
+    }
+
+    fn fetch_docker_version(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+    ) -> Result<(u32, u32, u32), Box<dyn Error>> {
+        let output = self.ssh_exec_argv(
+            socket_addr,
+            session,
+            &["docker", "version", "--format", "{{.Server.Version}}"],
+        )?;
+        parse_docker_version(&output).ok_or_else(|| {
+            format!(
+                "[{}] Failed to parse the Docker version: {}",
+                socket_addr, output
+            )
+            .into()
+        })
+    }
+
+    fn ssh_exec_with_env(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+        env: &HashMap<&str, &str>,
+        command: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        if self.config.ssh.use_setenv == Some(true) {
+            trace_command(socket_addr, command);
+            match Self::open_exec_channel_with_env(session, env, command)? {
+                Some(ch) => {
+                    let timeout = Duration::from_millis(u64::from(session.timeout()));
+                    return Self::read_exec_output(socket_addr, ch, command, timeout);
+                }
+                None => warn!(
+                    "[{}] The SSH server rejected the environment variables; falling back to a temporary script. Check 'AcceptEnv' in the server's sshd_config.",
+                    socket_addr
+                ),
+            }
+        }
+
+        let env_script_path = self.ssh_generate_env_script(socket_addr, session, env)?;
+
+        // Prepend the command that sources the environment variable script and removes it.
+        let mut cmd_with_env = String::new();
+        cmd_with_env.push_str(". ");
+        cmd_with_env.push_str_escaped(&env_script_path);
+        cmd_with_env.push_str(" && rm ");
+        cmd_with_env.push_str_escaped(&env_script_path);
+        cmd_with_env.push_str(" && ");
+        cmd_with_env.push_str(command);
+
+        self.ssh_exec(socket_addr, session, &cmd_with_env)
+    }
+
+    fn ssh_generate_env_script(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+        env: &HashMap<&str, &str>,
+    ) -> Result<String, Box<dyn Error>> {
+        let env_script_path = self.ssh_exec_argv(
+            socket_addr,
+            session,
+            &["mktemp", "-t", "github-self-hosted-runner-env.XXXXXXXXXX"],
+        )?;
+
+        // Use a quoted, unguessable delimiter, so that the shell neither expands nor
+        // terminates the here-document early. The script itself has no raw control characters.
+        let delimiter = format!("GHASCALER_DELIM_{}", Uuid::new_v4().simple());
+        let mut cmd = String::new();
+        cmd.push_str("cat <<'");
+        cmd.push_str(&delimiter);
+        cmd.push_str("' >");
+        cmd.push_str_escaped(&env_script_path);
+        cmd.push('\n');
+        cmd.push_str(&render_env_script(env));
+        cmd.push_str(&delimiter);
+        cmd.push('\n');
+
+        self.ssh_exec(socket_addr, session, &cmd)?;
+        Ok(env_script_path)
+    }
+
+    /// Executes the command with the specified arguments. Unlike [`Self::ssh_exec`], no
+    /// argument is interpreted by the remote shell, e.g. `$HOME` and `;` are passed as they are.
+    fn ssh_exec_argv<S: AsRef<str>>(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+        argv: &[S],
+    ) -> Result<String, Box<dyn Error>> {
+        self.ssh_exec(socket_addr, session, &render_argv(argv))
+    }
+
+    fn ssh_exec(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+        cmd: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        // The session timeout has been set to 'ssh.command_timeout_secs' in handshake().
+        let timeout = Duration::from_millis(u64::from(session.timeout()));
+        self.ssh_exec_with_timeout(socket_addr, session, cmd, timeout)
+    }
+
+    /// Executes the specified command, failing with [`TimedOutError`] if it does not complete
+    /// within `timeout`. The session timeout alone does not help when a command keeps
+    /// producing output, because it applies to every read rather than the whole command.
+    fn ssh_exec_with_timeout(
+        &self,
+        socket_addr: &SocketAddr,
+        session: &mut KeepaliveSession,
+        cmd: &str,
+        timeout: Duration,
+    ) -> Result<String, Box<dyn Error>> {
+        trace_command(socket_addr, cmd);
+        let mut ch = match session.channel_session() {
+            Ok(ch) => ch,
+            // Retry only when no channel could be opened, because the command might have been
+            // started already if 'exec' failed, and it must never run twice.
+            Err(err) if matches!(err.code(), ErrorCode::Session(_)) => {
+                warn!(
+                    "[{}] Failed to open a channel; reconnecting: {}",
+                    socket_addr, err
+                );
+                if let Err(err) = self.reconnect(session) {
+                    error!("[{}] Failed to reconnect: {}", socket_addr, err);
+                    return Err(err);
+                }
+                session.channel_session()?
+            }
+            Err(err) => return Err(err.into()),
+        };
+        ch.exec(cmd)?;
+
+        // libssh2 fails a read that blocks longer than the session timeout, e.g. while
+        // 'docker image pull' extracts a large layer silently, so raise it to `timeout`.
+        let session_timeout = session.timeout();
+        let raised_session_timeout = raise_session_timeout(session_timeout, timeout);
+        if let Some(raised_session_timeout) = raised_session_timeout {
+            session.set_timeout(raised_session_timeout);
+        }
+        let res = Self::read_exec_output(socket_addr, ch, cmd, timeout);
+        if raised_session_timeout.is_some() {
+            session.set_timeout(session_timeout);
+        }
+        res
+    }
+
+    /// Replaces the specified session and the one reused by this machine with a new one,
+    /// verifying it with `echo ok`.
+    fn reconnect(&self, session: &mut KeepaliveSession) -> Result<(), Box<dyn Error>> {
+        let (socket_addr, new_session) = self.connect()?;
+        let timeout = Duration::from_millis(u64::from(new_session.timeout()));
+        let ch = Self::open_exec_channel(&new_session, "echo ok")?;
+        let output = Self::read_exec_output(&socket_addr, ch, "echo ok", timeout)?;
+        if output != "ok" {
+            return Err(format!("[{}] Unexpected output: {}", socket_addr, output).into());
+        }
+
+        info!("[{}] Reconnected", socket_addr);
+        *self.session.lock().unwrap() = Some((socket_addr, new_session.clone()));
+        *session = new_session;
+        Ok(())
+    }
+
+    fn open_exec_channel(session: &Session, cmd: &str) -> Result<Channel, ssh2::Error> {
+        let mut ch = session.channel_session()?;
+        ch.exec(cmd)?;
+        Ok(ch)
+    }
+
+    /// Opens a channel that executes the command with the specified environment variables.
+    /// Returns `None` if the SSH server rejects any of them, e.g. due to its `AcceptEnv`.
+    fn open_exec_channel_with_env(
+        session: &Session,
+        env: &HashMap<&str, &str>,
+        cmd: &str,
+    ) -> Result<Option<Channel>, ssh2::Error> {
+        let mut ch = session.channel_session()?;
+        let mut names: Vec<&&str> = env.keys().collect();
+        names.sort();
+        for name in names {
+            if ch.setenv(name, env[name]).is_err() {
+                return Ok(None);
+            }
+        }
+        ch.exec(cmd)?;
+        Ok(Some(ch))
+    }
+
+    fn read_exec_output(
+        socket_addr: &SocketAddr,
+        mut ch: Channel,
+        cmd: &str,
+        timeout: Duration,
+    ) -> Result<String, Box<dyn Error>> {
+        // None of our commands reads the standard input.
+        ch.send_eof()?;
+
+        let (stdout, stderr, exit_code) = run_with_timeout(cmd, timeout, move || {
+            let mut stdout = String::new();
+            let mut stderr = String::new();
+            ch.read_to_string(&mut stdout)?;
+            ch.stderr().read_to_string(&mut stderr)?;
+            ch.wait_close()?;
+            Ok::<_, io::Error>((stdout, stderr, ch.exit_status()?))
+        })??;
+        trace!("[{}] Exit code: {}", socket_addr, exit_code);
+
+        if exit_code == 0 {
+            Ok(stdout.trim().to_string())
+        } else {
+            let mut indented_out: String =
+                String::with_capacity((stdout.len() + stderr.len()) * 3 / 2);
+            write!(
+                indented_out,
+                "[{}] Failed to execute the command:\n\n    {}\n\nExit code: {}",
+                socket_addr, cmd, exit_code
+            )?;
+
+            if !stdout.is_empty() {
+                write!(indented_out, "\nStandard output:\n\n")?;
+                for line in stdout.lines() {
+                    indented_out.push_str("    ");
+                    indented_out.push_str(line);
+                    indented_out.push('\n');
+                }
+            }
+
+            if !stderr.is_empty() {
+                write!(indented_out, "\nStandard error:\n\n")?;
+                for line in stderr.lines() {
+                    indented_out.push_str("    ");
+                    indented_out.push_str(line);
+                    indented_out.push('\n');
+                }
+            }
+
+            Err(indented_out.into())
+        }
+    }
+}
+
+/// An SSH session that sends a keepalive message every `interval_secs` seconds from a background
+/// thread, so that an idle connection is not dropped by a firewall or NAT. The thread terminates
+/// when this session and all its clones are dropped.
+#[derive(Clone)]
+struct KeepaliveSession {
+    session: Session,
+    // Disconnects the background thread when dropped.
+    _stop_tx: Option<mpsc::Sender<()>>,
+}
+
+impl KeepaliveSession {
+    fn new(session: Session, interval_secs: u64) -> Self {
+        if interval_secs == 0 {
+            return KeepaliveSession {
+                session,
+                _stop_tx: None,
+            };
+        }
+
+        session.set_keepalive(true, u32::try_from(interval_secs).unwrap_or(u32::MAX));
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        let keepalive_session = session.clone();
+        thread::spawn(move || {
+            let interval = Duration::from_secs(interval_secs);
+            while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                if let Err(err) = keepalive_session.keepalive_send() {
+                    debug!("Failed to send an SSH keepalive message: {}", err);
+                    break;
+                }
+            }
+        });
+
+        KeepaliveSession {
+            session,
+            _stop_tx: Some(stop_tx),
+        }
+    }
+}
+
+impl Deref for KeepaliveSession {
+    type Target = Session;
+
+    fn deref(&self) -> &Self::Target {
+        &self.session
+    }
+}
+
+impl DerefMut for KeepaliveSession {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.session
+    }
+}
+
+/// The lines of a container's output, read from an SSH channel until it is closed.
+struct RunnerLogLines {
+    socket_addr: SocketAddr,
+    reader: BufReader<Channel>,
+    // Keeps the session alive while reading.
+    _session: KeepaliveSession,
+}
+
+impl Iterator for RunnerLogLines {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            // The channel has been closed, e.g. because the container exited.
+            Ok(0) => None,
+            Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+            Err(err) => {
+                warn!(
+                    "[{}] Failed to read the container output: {}",
+                    self.socket_addr, err
+                );
+                None
+            }
+        }
+    }
+}
+
+/// The number of seconds to wait for a runner container to stop before killing it.
+pub const DEFAULT_STOP_TIMEOUT_SECS: u32 = 10;
+
+/// The interval of checking whether a drained runner went offline.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The CPU and memory usage of a container, sampled by [`Machine::get_resource_usage`].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ContainerStats {
+    /// The CPU usage, where 100 means a single CPU is fully used.
+    pub cpu_percent: f32,
+    pub memory_bytes: u64,
+    pub memory_limit_bytes: u64,
+}
+
+/// The resource limits of a running container, applied by [`Machine::apply_resource_limits`].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct DockerLimits {
+    /// The number of CPUs, e.g. `1.5`
+    pub cpus: Option<f32>,
+    /// The memory limit in bytes
+    pub memory_bytes: Option<u64>,
+}
+
+impl Display for DockerLimits {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.cpus {
+            Some(cpus) => write!(f, "cpus={}", cpus)?,
+            None => f.write_str("cpus=unchanged")?,
+        }
+        match self.memory_bytes {
+            Some(memory_bytes) => write!(f, ", memory={}", memory_bytes),
+            None => f.write_str(", memory=unchanged"),
+        }
+    }
+}
+
+/// What [`Machine::scale_to`] or [`Machine::start_runner`] did to a machine and why,
+/// which is logged and optionally appended to the audit log.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ScalingDecision {
+    pub machine_id: String,
+    pub action: ScalingAction,
+    pub reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_name: Option<String>,
+}
+
+/// A runner container started on a machine.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StartedRunner {
+    /// The full 64-character ID of the container
+    pub container_id: String,
+    pub container_name: String,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScalingAction {
+    /// A runner was started.
+    Start,
+    /// Nothing was done.
+    Skip,
+    /// An idle runner was stopped.
+    Stop,
+    /// A runner could not be started or stopped.
+    Error,
+}
+
+impl Display for ScalingAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ScalingAction::Start => "start",
+            ScalingAction::Skip => "skip",
+            ScalingAction::Stop => "stop",
+            ScalingAction::Error => "error",
+        })
+    }
+}
+
+impl Display for ScalingDecision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.action, self.reason)?;
+        if let Some(run_url) = &self.run_url {
+            write!(f, " (run: {})", run_url)?;
+        }
+        if let Some(container_id) = &self.container_id {
+            write!(f, " (container: {})", container_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl ScalingDecision {
+    /// Logs this decision at `info` level if a runner was started or stopped, `debug` level if
+    /// nothing was done, or `warn` level on a failure.
+    pub fn log(&self) {
+        let run_url = self.run_url.as_deref();
+        let container_id = self.container_id.as_deref();
+        match self.action {
+            ScalingAction::Start | ScalingAction::Stop => info!(
+                machine = %self.machine_id,
+                action = %self.action,
+                run_url,
+                container_id,
+                "[{}] {}",
+                self.machine_id,
+                self
+            ),
+            ScalingAction::Skip => debug!(
+                machine = %self.machine_id,
+                action = %self.action,
+                run_url,
+                container_id,
+                "[{}] {}",
+                self.machine_id,
+                self
+            ),
+            ScalingAction::Error => warn!(
+                machine = %self.machine_id,
+                action = %self.action,
+                run_url,
+                container_id,
+                "[{}] {}",
+                self.machine_id,
+                self
+            ),
+        }
+    }
+}
+
+/// Appends the specified decisions to the audit log file as JSON lines, creating the file
+/// if it does not exist.
+pub fn append_audit_log(path: &Path, decisions: &[ScalingDecision]) -> Result<(), Box<dyn Error>> {
+    #[derive(Serialize)]
+    struct AuditRecord<'a> {
+        timestamp: DateTime<Utc>,
+        #[serde(flatten)]
+        decision: &'a ScalingDecision,
+    }
+
+    let timestamp = Utc::now();
+    let mut lines = String::new();
+    for decision in decisions {
+        lines.push_str(&serde_json::to_string(&AuditRecord {
+            timestamp,
+            decision,
+        })?);
+        lines.push('\n');
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(lines.as_bytes())?;
+    Ok(())
+}
+
+/// Returns the running runners that have been idle for longer than `timeout` at `now`, the longest
+/// idle first. `idle_runner_names` are the names of the runners that GitHub reports as online and
+/// not busy. The `keep` most recently started idle runners are never returned, so that they stay
+/// warm. The time since a runner started is used as its idle time, because neither Docker nor
+/// GitHub tells when a runner became idle.
+pub fn find_idle_runners_to_stop<'a>(
+    runners: &'a [RunnerInfo],
+    idle_runner_names: &HashSet<String>,
+    keep: usize,
+    timeout: Duration,
+    now: DateTime<Utc>,
+) -> Vec<&'a RunnerInfo> {
+    let idle_since = |runner: &RunnerInfo| runner.started_at.unwrap_or(runner.created_at);
+    let mut idle_runners: Vec<&RunnerInfo> = runners
+        .iter()
+        .filter(|runner| {
+            runner.container_state == ContainerState::Running
+                && idle_runner_names.contains(&runner.runner_name)
+        })
+        .collect();
+    idle_runners.sort_by_key(|runner| idle_since(runner));
+
+    let stoppable = idle_runners.len().saturating_sub(keep);
+    idle_runners
+        .into_iter()
+        .take(stoppable)
+        .filter(|runner| {
+            (now - idle_since(runner))
+                .to_std()
+                .is_ok_and(|idle_time| idle_time > timeout)
+        })
+        .collect()
+}
+
+/// The runners to start and stop on a machine, as planned by [`plan_scaling`].
+#[derive(Debug)]
+pub struct ScalingPlan<'a> {
+    /// The number of the live runners that GitHub does not report as busy.
+    pub not_busy: u32,
+    /// The number of the runners to start.
+    pub start: u32,
+    /// The runners to stop, with their IDs on GitHub.
+    pub stop: Vec<(&'a RunnerInfo, u64)>,
+    /// The number of the runners that are more than needed but can't be stopped, because they are
+    /// waiting for their queued runs or GitHub does not report them as idle yet.
+    pub kept: u32,
+}
+
+/// Plans how many runners to start or which of `live_runners` to stop, so that `target` runners
+/// are not running a job without more than `max` runners being alive. `github_runners` are
+/// the runners registered on GitHub, which tell which runners are busy. A busy runner counts
+/// toward `max` only. Only the runners that GitHub reports as online and not busy, and that are
+/// not dedicated to any of `queued_run_ids`, are stopped.
+pub fn plan_scaling<'a>(
+    live_runners: &'a [RunnerInfo],
+    github_runners: &[SelfHostedRunner],
+    queued_run_ids: &HashSet<u64>,
+    target: u32,
+    max: u32,
+) -> ScalingPlan<'a> {
+    let busy_runner_names: HashSet<&str> = github_runners
+        .iter()
+        .filter(|runner| runner.busy)
+        .map(|runner| runner.name.as_str())
+        .collect();
+    let idle_runner_ids: HashMap<&str, u64> = github_runners
+        .iter()
+        .filter(|runner| runner.status == "online" && !runner.busy)
+        .map(|runner| (runner.name.as_str(), runner.id))
+        .collect();
+
+    let live = u32::try_from(live_runners.len()).unwrap_or(u32::MAX);
+    let not_busy = live_runners
+        .iter()
+        .filter(|runner| !busy_runner_names.contains(runner.runner_name.as_str()))
+        .count();
+    let mut plan = ScalingPlan {
+        not_busy: u32::try_from(not_busy).unwrap_or(u32::MAX),
+        start: 0,
+        stop: vec![],
+        kept: 0,
+    };
+
+    if plan.not_busy < target {
+        plan.start = (target - plan.not_busy).min(max.saturating_sub(live));
+    } else if plan.not_busy > target {
+        let excess = plan.not_busy - target;
+        plan.stop = live_runners
+            .iter()
+            .filter(|runner| !runner.run_id.is_some_and(|id| queued_run_ids.contains(&id)))
+            .filter_map(|runner| {
+                idle_runner_ids
+                    .get(runner.runner_name.as_str())
+                    .map(|id| (runner, *id))
+            })
+            .take(excess as usize)
+            .collect();
+        plan.kept = excess - plan.stop.len() as u32;
+    }
+    plan
+}
 
-        Ok(())
+/// Deregisters the offline runners whose containers are gone from every machine that could own
+/// them, i.e. every machine whose runner names match the runner's name. `machines` must contain
+/// all machines, including the disabled ones, because a runner may still be alive on any of them.
+/// A runner is never deregistered if a machine that could own it is unreachable.
+pub fn deregister_orphaned_runners(
+    machines: &[Machine],
+    github_client: &GithubClient,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let mut alive_runner_names = HashMap::new();
+    for machine in machines {
+        match RunnerQuery::alive().execute(machine) {
+            Ok(runners) => {
+                let names: HashSet<String> = runners
+                    .into_iter()
+                    .map(|runner| runner.runner_name)
+                    .collect();
+                alive_runner_names.insert(machine.id().to_string(), names);
+            }
+            Err(err) => warn!(
+                machine = %machine.id(),
+                "[{}] Failed to retrieve the runners; not deregistering the runners that may belong to it: {}",
+                machine.id(), err
+            ),
+        }
     }
 
-    fn connect(&self) -> Result<(SocketAddr, Session), Box<dyn Error>> {
-        // Connect to the SSH server
-        let socket_addr = SocketAddr::new(self.config.ssh.host.parse()?, self.config.ssh.port);
-        debug!("[{}] Making a connection attempt ..", socket_addr);
-        let tcp = TcpStream::connect_timeout(&socket_addr, Duration::from_secs(30))?;
-        debug!(
-            "[{}] Connection established; creating an SSH session ..",
-            socket_addr
-        );
-        let mut sess = Session::new()?;
-        sess.set_tcp_stream(tcp);
-        sess.handshake()?;
-        debug!(
-            "[{}] SSH session established; authenticating ..",
-            socket_addr
+    let runners = github_client.fetch_self_hosted_runners()?;
+    for (machine_id, runner) in
+        find_orphaned_runners(&runners, machines, config, &alive_runner_names)
+    {
+        info!(
+            machine = %machine_id,
+            "[{}] Deregistering an orphaned runner: {} (id: {})",
+            machine_id, runner.name, runner.id
         );
-        if self.config.ssh.password.is_empty() {
-            debug!("[{}] Using private key authentication", socket_addr);
-            sess.userauth_pubkey_memory(
-                &self.config.ssh.username,
-                None,
-                &self.config.ssh.private_key,
-                self.passphrase_opt(),
-            )?;
-        } else {
-            debug!("[{}] Using password authentication", socket_addr);
-            sess.userauth_password(&self.config.ssh.username, &self.config.ssh.password)?;
-        }
+        github_client.delete_runner(runner.id)?;
+    }
 
-        if !sess.authenticated() {
-            return Err("Authentication failed".into());
-        }
+    Ok(())
+}
+
+/// Returns the offline runners whose containers are gone from every machine that could own them,
+/// paired with the ID of the first such machine. `alive_runner_names` maps the ID of a machine
+/// to the names of its alive runners; a machine missing in it is considered unreachable, and
+/// the runners it could own are never returned. The online runners are never returned either,
+/// because they're obviously alive somewhere.
+pub fn find_orphaned_runners<'a>(
+    runners: &'a [SelfHostedRunner],
+    machines: &'a [Machine],
+    config: &Config,
+    alive_runner_names: &HashMap<String, HashSet<String>>,
+) -> Vec<(&'a str, &'a SelfHostedRunner)> {
+    let runner_name_res: Vec<(&str, Regex)> = machines
+        .iter()
+        .map(|machine| (machine.id(), machine.runner_name_regex(config)))
+        .collect();
+
+    runners
+        .iter()
+        .filter(|runner| runner.status == "offline")
+        .filter_map(|runner| {
+            let owners: Vec<&str> = runner_name_res
+                .iter()
+                .filter(|(_, runner_name_re)| runner_name_re.is_match(&runner.name))
+                .map(|(machine_id, _)| *machine_id)
+                .collect();
+            let orphaned = !owners.is_empty()
+                && owners.iter().all(|machine_id| {
+                    alive_runner_names
+                        .get(*machine_id)
+                        .is_some_and(|names| !names.contains(&runner.name))
+                });
+            orphaned.then(|| (owners[0], runner))
+        })
+        .collect()
+}
 
-        Ok((socket_addr, sess))
+#[derive(Debug, Serialize)]
+pub struct RunnerInfo {
+    pub(crate) container_id: String,
+    pub(crate) container_state: ContainerState,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) started_at: Option<DateTime<Utc>>,
+    pub(crate) finished_at: Option<DateTime<Utc>>,
+    pub(crate) runner_name: String,
+    pub(crate) run_url: Option<String>,
+    pub(crate) run_id: Option<u64>,
+}
+
+impl RunnerInfo {
+    /// Creates a new instance from a container object in the output of `docker container inspect`.
+    pub fn from_inspect(inspected: &serde_json::Value) -> Result<Self, Box<dyn Error>> {
+        let container_id = inspected["Id"]
+            .as_str()
+            .ok_or("Missing the container ID in 'docker container inspect' output")?;
+        let state = &inspected["State"];
+        let labels = &inspected["Config"]["Labels"];
+        let label = |name: &str| labels[name].as_str().filter(|value| !value.is_empty());
+
+        Ok(RunnerInfo {
+            container_id: container_id.to_string(),
+            container_state: ContainerState::from(state["Status"].as_str().unwrap_or("")),
+            created_at: Machine::parse_timestamp(inspected["Created"].as_str().unwrap_or(""))?,
+            started_at: Machine::parse_timestamp_opt(state["StartedAt"].as_str().unwrap_or(""))?,
+            finished_at: Machine::parse_timestamp_opt(state["FinishedAt"].as_str().unwrap_or(""))?,
+            runner_name: label("github-runner-name").unwrap_or("").to_string(),
+            run_url: label("github-run-url").map(str::to_string),
+            run_id: label("github-run-id").and_then(|run_id| run_id.parse().ok()),
+        })
     }
 
-    fn passphrase_opt(&self) -> Option<&str> {
-        let passphrase = &self.config.ssh.private_key_passphrase;
-        if passphrase.is_empty() {
-            None
-        } else {
-            Some(passphrase)
-        }
+    pub fn container_id(&self) -> &str {
+        &self.container_id
     }
 
-    fn ssh_exec_with_env(
-        socket_addr: &SocketAddr,
-        session: &mut Session,
-        env: &HashMap<&str, &str>,
-        command: &str,
-    ) -> Result<String, Box<dyn Error>> {
-        let env_script_path = Self::ssh_generate_env_script(socket_addr, session, env)?;
+    pub fn state(&self) -> &ContainerState {
+        &self.container_state
+    }
 
-        // Prepend the command that sources the environment variable script and removes it.
-        let mut cmd_with_env = String::new();
-        cmd_with_env.push_str(". ");
-        cmd_with_env.push_str_escaped(&env_script_path);
-        cmd_with_env.push_str(" && rm ");
-        cmd_with_env.push_str_escaped(&env_script_path);
-        cmd_with_env.push_str(" && ");
-        cmd_with_env.push_str(command);
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        &self.created_at
+    }
 
-        Self::ssh_exec(socket_addr, session, &cmd_with_env)
+    pub fn started_at(&self) -> Option<&DateTime<Utc>> {
+        self.started_at.as_ref()
     }
 
-    fn ssh_generate_env_script(
-        socket_addr: &SocketAddr,
-        session: &mut Session,
-        env: &HashMap<&str, &str>,
-    ) -> Result<String, Box<dyn Error>> {
-        let env_script_path = Self::ssh_exec(
-            socket_addr,
-            session,
-            "mktemp -t github-self-hosted-runner-env.XXXXXXXXXX",
-        )?;
+    pub fn finished_at(&self) -> Option<&DateTime<Utc>> {
+        self.finished_at.as_ref()
+    }
 
-        let mut cmd = String::new();
-        cmd.push_str("cat <<======== >");
-        cmd.push_str_escaped(&env_script_path);
-        cmd.push('\n');
+    pub fn runner_name(&self) -> &str {
+        &self.runner_name
+    }
+
+    /// Returns the URL of the run this runner was started for, i.e. the `github-run-url` label.
+    pub fn run_url(&self) -> Option<&str> {
+        self.run_url.as_deref()
+    }
 
-        for kv in env {
-            // KEY=VALUE
-            cmd.push_str_escaped(kv.0);
-            cmd.push('=');
-            cmd.push_str_escaped(kv.1);
-            cmd.push('\n');
+    /// Returns the ID of the run this runner was started for, i.e. the `github-run-id` label.
+    pub fn run_id(&self) -> Option<u64> {
+        self.run_id
+    }
+}
 
-            // export KEY
-            cmd.push_str("export ");
-            cmd.push_str_escaped(kv.0);
-            cmd.push('\n');
+impl Display for RunnerInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, created at {}",
+            &self.container_id[..self.container_id.len().min(12)],
+            self.container_state,
+            self.created_at.to_rfc3339()
+        )?;
+        if !self.runner_name.is_empty() {
+            write!(f, ", runner: {}", self.runner_name)?;
+        }
+        if let Some(run_url) = &self.run_url {
+            write!(f, ", run: {}", run_url)?;
         }
+        f.write_str(")")
+    }
+}
 
-        cmd.push_str("========\n");
+/// Filters the runner containers of a machine by their state and creation time, e.g.
+///
+/// ```ignore
+/// let runners = RunnerQuery::new()
+///     .in_state(ContainerState::Running)
+///     .created_before(Utc::now() - TimeDelta::hours(1))
+///     .execute(&machine)?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RunnerQuery {
+    states: Vec<ContainerState>,
+    created_after: Option<DateTime<Utc>>,
+    created_before: Option<DateTime<Utc>>,
+}
 
-        Self::ssh_exec(socket_addr, session, &cmd)?;
-        Ok(env_script_path)
+impl RunnerQuery {
+    /// Creates a new query that matches all runners.
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    fn ssh_exec(
-        socket_addr: &SocketAddr,
-        session: &mut Session,
-        cmd: &str,
-    ) -> Result<String, Box<dyn Error>> {
-        let mut ch = session.channel_session()?;
-        ch.exec(cmd)?;
-
-        let mut stdout = String::new();
-        let mut stderr = String::new();
-        ch.read_to_string(&mut stdout)?;
-        ch.stderr().read_to_string(&mut stderr)?;
-        ch.wait_close()?;
+    /// Creates a new query that matches the runners whose containers are alive.
+    /// See [`ContainerState::is_alive`].
+    pub fn alive() -> Self {
+        Self::new()
+            .in_state(ContainerState::Created)
+            .in_state(ContainerState::Restarting)
+            .in_state(ContainerState::Running)
+            .in_state(ContainerState::Paused)
+    }
 
-        let exit_code = ch.exit_status()?;
-        if exit_code == 0 {
-            Ok(stdout.trim().to_string())
-        } else {
-            let mut indented_out: String =
-                String::with_capacity((stdout.len() + stderr.len()) * 3 / 2);
-            write!(
-                indented_out,
-                "[{}] Failed to execute the command:\n\n    {}\n\nExit code: {}",
-                socket_addr, cmd, exit_code
-            )?;
+    /// Adds the specified state to the states to match. A runner matches if its container
+    /// is in any of the added states.
+    pub fn in_state(mut self, state: ContainerState) -> Self {
+        if !self.states.contains(&state) {
+            self.states.push(state);
+        }
+        self
+    }
 
-            if !stdout.is_empty() {
-                write!(indented_out, "\nStandard output:\n\n")?;
-                for line in stdout.lines() {
-                    indented_out.push_str("    ");
-                    indented_out.push_str(line);
-                    indented_out.push('\n');
-                }
-            }
+    /// Matches only the runners created after the specified time.
+    pub fn created_after(mut self, time: DateTime<Utc>) -> Self {
+        self.created_after = Some(time);
+        self
+    }
 
-            if !stderr.is_empty() {
-                write!(indented_out, "\nStandard error:\n\n")?;
-                for line in stderr.lines() {
-                    indented_out.push_str("    ");
-                    indented_out.push_str(line);
-                    indented_out.push('\n');
-                }
-            }
+    /// Matches only the runners created before the specified time.
+    pub fn created_before(mut self, time: DateTime<Utc>) -> Self {
+        self.created_before = Some(time);
+        self
+    }
 
-            Err(indented_out.into())
-        }
+    /// Returns whether the specified runner matches this query.
+    pub fn matches(&self, runner: &RunnerInfo) -> bool {
+        (self.states.is_empty() || self.states.contains(&runner.container_state))
+            && self
+                .created_after
+                .is_none_or(|time| runner.created_at > time)
+            && self
+                .created_before
+                .is_none_or(|time| runner.created_at < time)
     }
-}
 
-#[derive(Debug)]
-pub struct RunnerInfo {
-    container_id: String,
-    container_state: ContainerState,
-    created_at: DateTime<Utc>,
-    started_at: Option<DateTime<Utc>>,
-    finished_at: Option<DateTime<Utc>>,
+    /// Retrieves the runners matching this query from the specified machine.
+    pub fn execute(&self, machine: &Machine) -> Result<Vec<RunnerInfo>, Box<dyn Error>> {
+        machine.fetch_matching_runners(self)
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ContainerState {
     Created,
     Restarting,
@@ -288,6 +2443,46 @@ pub enum ContainerState {
     Unknown(String),
 }
 
+impl ContainerState {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ContainerState::Created => "created",
+            ContainerState::Restarting => "restarting",
+            ContainerState::Running => "running",
+            ContainerState::Removing => "removing",
+            ContainerState::Paused => "paused",
+            ContainerState::Exited => "exited",
+            ContainerState::Dead => "dead",
+            ContainerState::Unknown(value) => value.as_str(),
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        matches!(
+            self,
+            ContainerState::Created
+                | ContainerState::Restarting
+                | ContainerState::Running
+                | ContainerState::Paused
+        )
+    }
+}
+
+impl Display for ContainerState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ContainerState::Unknown(value) => write!(f, "unknown({})", value),
+            _ => f.write_str(self.as_str()),
+        }
+    }
+}
+
+impl Serialize for ContainerState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
 impl From<&str> for ContainerState {
     fn from(value: &str) -> Self {
         match value {
@@ -303,15 +2498,574 @@ impl From<&str> for ContainerState {
     }
 }
 
+/// Returns whether the runner container image has to be pulled under the specified policy,
+/// or an error if the image is missing but the policy forbids pulling it.
+pub fn should_pull_image(policy: PullPolicy, image_present: bool) -> Result<bool, String> {
+    match policy {
+        PullPolicy::Always => Ok(true),
+        PullPolicy::IfNotPresent => Ok(!image_present),
+        PullPolicy::Never => {
+            if image_present {
+                Ok(false)
+            } else {
+                Err("The container image is missing, but 'docker.pull_policy' is 'never'.".into())
+            }
+        }
+    }
+}
+
+/// A step of pulling the runner image, in the order returned by [`image_pull_steps`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImagePullStep {
+    /// Prunes the unused images older than `hours` hours.
+    Prune { hours: u64 },
+    /// Pulls the image.
+    Pull,
+}
+
+/// Returns the steps of pulling the runner image with the specified Docker configuration.
+/// The unused images older than `prune_keep_days` are pruned first to make room for the image
+/// rather than failing in the middle if `auto_prune_on_start` is true, unless they have been
+/// pruned already in this scaling pass, because the image is pulled for every runner with
+/// the `always` pull policy.
+pub fn image_pull_steps(docker: &DockerConfig, pruned_on_start: bool) -> Vec<ImagePullStep> {
+    let mut steps: Vec<ImagePullStep> = vec![];
+    if docker.auto_prune_on_start == Some(true) && !pruned_on_start {
+        steps.push(ImagePullStep::Prune {
+            hours: u64::from(docker.prune_keep_days) * 24,
+        });
+    }
+    steps.push(ImagePullStep::Pull);
+    steps
+}
+
+/// Returns the message of the error raised when the runner image is missing, suggesting how to fix it.
+pub fn missing_image_message(image: &str, pull_policy: PullPolicy) -> String {
+    format!(
+        "The container image '{}' is missing, but 'docker.pull_policy' is '{}'. \
+         Pull it manually with 'docker image pull {}' or change 'docker.pull_policy' to 'if-not-present'.",
+        image, pull_policy, image
+    )
+}
+
+/// The minimum time to wait for `docker image pull`, which usually takes longer than other commands.
+const MIN_IMAGE_PULL_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Connects to `socket_addr` from the local `bind_address`, which `TcpStream` can't do because
+/// it can't bind a socket before connecting it.
+pub fn connect_from(
+    bind_address: IpAddr,
+    socket_addr: &SocketAddr,
+    timeout: Duration,
+) -> io::Result<TcpStream> {
+    let local_addr = SocketAddr::new(bind_address, 0);
+    if local_addr.is_ipv4() != socket_addr.is_ipv4() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Can't connect to {} from {} of a different IP version",
+                socket_addr, bind_address
+            ),
+        ));
+    }
+
+    let socket = Socket::new(
+        Domain::for_address(local_addr),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    socket.bind(&local_addr.into())?;
+    socket.connect_timeout(&(*socket_addr).into(), timeout)?;
+    Ok(socket.into())
+}
+
+/// Logs the specified command at `trace` level. Only the first line of a command with
+/// a here-document is logged, because the here-document may contain secrets, e.g. the script
+/// written by `ssh_generate_env_script()`.
+pub fn trace_command(socket_addr: &SocketAddr, cmd: &str) {
+    match cmd.split_once('\n') {
+        Some((first_line, _)) if first_line.contains("<<") => {
+            trace!(
+                "[{}] Executing: {} (here-document omitted)",
+                socket_addr,
+                first_line
+            );
+        }
+        _ => trace!("[{}] Executing: {}", socket_addr, cmd),
+    }
+}
+
+/// Runs `f` in a separate thread and waits for its result up to `timeout`, which is unlimited if
+/// zero. The thread keeps running in the background after the timeout; `f` must not block forever.
+pub fn run_with_timeout<T, F>(command: &str, timeout: Duration, f: F) -> Result<T, TimedOutError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        // The receiver is gone if timed out already.
+        let _ = tx.send(f());
+    });
+
+    let res = if timeout.is_zero() {
+        rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+    } else {
+        rx.recv_timeout(timeout)
+    };
+    res.map_err(|_| TimedOutError {
+        command: command.to_string(),
+        timeout,
+    })
+}
+
+/// Returns the session timeout in milliseconds that lets a command run up to `timeout`, or `None`
+/// if `session_timeout` is long enough already. Zero means no timeout for both.
+pub fn raise_session_timeout(session_timeout: u32, timeout: Duration) -> Option<u32> {
+    if session_timeout == 0
+        || (!timeout.is_zero() && timeout.as_millis() <= u128::from(session_timeout))
+    {
+        None
+    } else {
+        Some(u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX))
+    }
+}
+
+/// An error raised when a remote command does not complete in time.
+#[derive(Debug)]
+pub struct TimedOutError {
+    pub command: String,
+    pub timeout: Duration,
+}
+
+impl Display for TimedOutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The command did not complete in {:?}:\n\n    {}",
+            self.timeout, self.command
+        )
+    }
+}
+
+impl Error for TimedOutError {}
+
+/// The minimum version of the Docker daemon on the machines.
+pub const MIN_DOCKER_VERSION: (u32, u32, u32) = (20, 10, 0);
+
+/// Parses the `major.minor.patch` prefix of a Docker version string, e.g. `20.10.17`,
+/// `24.0.7-1` or `25.0.0-rc.1`. A missing patch version is treated as 0.
+pub fn parse_docker_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.trim().splitn(3, '.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = match parts.next() {
+        Some(patch) => {
+            let digits: &str = &patch[..patch
+                .find(|ch: char| !ch.is_ascii_digit())
+                .unwrap_or(patch.len())];
+            digits.parse().ok()?
+        }
+        None => 0,
+    };
+    Some((major, minor, patch))
+}
+
+/// Renders a shell script that exports the specified environment variables.
+/// The values are ANSI-C-quoted, so that any character in them, including a trailing line feed,
+/// is preserved as it is and the script has one line per variable.
+pub fn render_env_script(env: &HashMap<&str, &str>) -> String {
+    let mut script = String::new();
+    for (key, value) in env {
+        // export KEY=$'VALUE'
+        script.push_str("export ");
+        script.push_str_escaped(key);
+        script.push('=');
+        script.push_str_ansi_c_quoted(value);
+        script.push('\n');
+    }
+    script
+}
+
+/// Parses the output of `docker container inspect` for a single container. Accepts both the JSON
+/// array printed by default and the single object printed with `--format json` since Docker 23.
+pub fn parse_container_inspect(output: &str) -> Result<serde_json::Value, String> {
+    let parsed: serde_json::Value = serde_json::from_str(output)
+        .map_err(|err| format!("Failed to parse 'docker container inspect' output: {}", err))?;
+    match parsed {
+        serde_json::Value::Array(mut containers) if !containers.is_empty() => {
+            Ok(containers.swap_remove(0))
+        }
+        serde_json::Value::Object(_) => Ok(parsed),
+        _ => Err("No container in 'docker container inspect' output".to_string()),
+    }
+}
+
+/// Renders the `docker image prune` command that removes the unused images created more than
+/// `hours` hours ago.
+pub fn render_image_prune_argv(hours: u64) -> Vec<String> {
+    vec![
+        "docker".to_string(),
+        "image".to_string(),
+        "prune".to_string(),
+        "--force".to_string(),
+        "--filter".to_string(),
+        format!("until={}h", hours),
+    ]
+}
+
+/// Renders the specified arguments into a command line that a POSIX shell splits back into
+/// the same arguments without expanding any of them.
+pub fn render_argv<S: AsRef<str>>(argv: &[S]) -> String {
+    let mut cmd = String::new();
+    for (i, arg) in argv.iter().enumerate() {
+        if i > 0 {
+            cmd.push(' ');
+        }
+        let arg = arg.as_ref();
+        if arg.is_empty() {
+            // Don't let an empty argument disappear.
+            cmd.push_str("''");
+        } else {
+            cmd.push_str_escaped(arg);
+        }
+    }
+    cmd
+}
+
+/// Returns the container ID printed by `docker container run --detach`, ensuring it's
+/// a full 64-character hexadecimal ID.
+pub fn parse_container_id(output: &str) -> Result<String, String> {
+    let container_id = output.lines().last().unwrap_or("").trim();
+    if container_id.len() == 64 && container_id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Ok(container_id.to_ascii_lowercase())
+    } else {
+        Err(format!(
+            "Unexpected output of 'docker container run': {}",
+            output
+        ))
+    }
+}
+
+/// Renders the `--env NAME=VALUE` options of `docker container run` for the specified variables,
+/// sorted by their names.
+pub fn render_extra_env_args(extra_env: &HashMap<String, String>) -> Vec<String> {
+    let mut names: Vec<&String> = extra_env.keys().collect();
+    names.sort();
+
+    let mut args: Vec<String> = vec![];
+    for name in names {
+        args.push("--env".to_string());
+        args.push(format!("{}={}", name, extra_env[name]));
+    }
+    args
+}
+
+/// Renders the `--label KEY=VALUE` options of `docker container run` for the specified labels,
+/// sorted by their keys.
+pub fn render_extra_label_args(extra_labels: &HashMap<String, String>) -> Vec<String> {
+    let mut keys: Vec<&String> = extra_labels.keys().collect();
+    keys.sort();
+
+    let mut args: Vec<String> = vec![];
+    for key in keys {
+        args.push("--label".to_string());
+        args.push(format!("{}={}", key, extra_labels[key]));
+    }
+    args
+}
+
+/// Renders the `--volume <host_path>:<container_path>[:ro]` options of `docker container run`
+/// for the specified bind mounts.
+pub fn render_volume_args(volumes: &[VolumeMount]) -> Vec<String> {
+    let mut args: Vec<String> = vec![];
+    for volume in volumes {
+        args.push("--volume".to_string());
+        let mut spec = format!("{}:{}", volume.host_path, volume.container_path);
+        if volume.read_only {
+            spec.push_str(":ro");
+        }
+        args.push(spec);
+    }
+    args
+}
+
+/// Renders the arguments of the `docker container update` command that applies the specified
+/// resource limits to a container. The unspecified limits are left unchanged.
+pub fn render_update_argv(container_id: &str, limits: &DockerLimits) -> Vec<String> {
+    let mut argv: Vec<String> = vec![
+        "docker".to_string(),
+        "container".to_string(),
+        "update".to_string(),
+    ];
+    if let Some(cpus) = limits.cpus {
+        argv.push("--cpus".to_string());
+        argv.push(cpus.to_string());
+    }
+    if let Some(memory_bytes) = limits.memory_bytes {
+        argv.push("--memory".to_string());
+        argv.push(memory_bytes.to_string());
+    }
+    argv.push(container_id.to_string());
+    argv
+}
+
+/// A container image cached on a machine, retrieved by `docker image ls`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DockerImage {
+    /// The full image ID, e.g. `sha256:0123...`
+    pub id: String,
+    /// The repository, e.g. `ghcr.io/myoung34/docker-github-actions-runner`, or `<none>`
+    pub repository: String,
+    /// The tag, e.g. `ubuntu-focal`, or `<none>`
+    pub tag: String,
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl DockerImage {
+    /// Returns whether this image is the specified image, e.g. `ubuntu` or `ghcr.io/foo/bar:1.0`.
+    /// An image without a tag means the `latest` tag.
+    pub fn is(&self, image: &str) -> bool {
+        let (repository, tag) = match image.rsplit_once(':') {
+            // A colon followed by '/' separates a registry host and its port rather than a tag.
+            Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+            _ => (image, "latest"),
+        };
+        self.repository == repository && self.tag == tag
+    }
+}
+
+/// The disk space of a file system, retrieved by `df`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+}
+
+/// Parses the total and available bytes from the output of `df -Pk <path>`.
+pub fn parse_df_usage(output: &str) -> Option<DiskUsage> {
+    // Filesystem 1024-blocks Used Available Capacity Mounted on
+    let fields: Vec<&str> = output.lines().nth(1)?.split_whitespace().collect();
+    let total_kib: u64 = fields.get(1)?.parse().ok()?;
+    let available_kib: u64 = fields.get(3)?.parse().ok()?;
+    Some(DiskUsage {
+        total_bytes: total_kib.checked_mul(1024)?,
+        available_bytes: available_kib.checked_mul(1024)?,
+    })
+}
+
+/// The memory of a machine, retrieved by `free`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryInfo {
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+}
+
+/// Parses the total and available bytes of memory from the output of `free -b`.
+pub fn parse_free_memory(output: &str) -> Option<MemoryInfo> {
+    let mut lines = output.lines();
+    // The header has no column for the row names, e.g. 'Mem:'.
+    let columns: Vec<&str> = lines.next()?.split_whitespace().collect();
+    let fields: Vec<&str> = lines
+        .find(|line| line.starts_with("Mem:"))?
+        .split_whitespace()
+        .skip(1)
+        .collect();
+    let field = |name: &str| -> Option<u64> {
+        let column = columns.iter().position(|column| *column == name)?;
+        fields.get(column)?.parse().ok()
+    };
+    Some(MemoryInfo {
+        total_bytes: field("total")?,
+        available_bytes: field("available")?,
+    })
+}
+
+/// Parses the number of bytes from the `Total reclaimed space: 1.5GB` line
+/// in the output of `docker image prune`.
+pub fn parse_reclaimed_space(output: &str) -> Option<u64> {
+    let size = output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Total reclaimed space:"))?;
+    parse_size(size)
+}
+
+/// Parses a line of `docker container stats --format '{{json .}}'` output, e.g.
+/// `{"CPUPerc":"1.50%","MemUsage":"1.5MiB / 7.6GiB",...}`.
+pub fn parse_container_stats(line: &str) -> Result<ContainerStats, String> {
+    #[derive(Deserialize)]
+    struct RawStats {
+        #[serde(rename = "CPUPerc")]
+        cpu_percent: String,
+        #[serde(rename = "MemUsage")]
+        mem_usage: String,
+    }
+
+    let raw: RawStats = serde_json::from_str(line)
+        .map_err(|err| format!("Failed to parse 'docker container stats' output: {}", err))?;
+    let invalid = |name: &str, value: &str| {
+        format!(
+            "Invalid '{}' in 'docker container stats' output: {}",
+            name, value
+        )
+    };
+
+    let cpu_percent = raw
+        .cpu_percent
+        .trim()
+        .trim_end_matches('%')
+        .parse::<f32>()
+        .map_err(|_| invalid("CPUPerc", &raw.cpu_percent))?;
+    let (memory, memory_limit) = raw
+        .mem_usage
+        .split_once('/')
+        .ok_or_else(|| invalid("MemUsage", &raw.mem_usage))?;
+    Ok(ContainerStats {
+        cpu_percent,
+        memory_bytes: parse_size(memory).ok_or_else(|| invalid("MemUsage", &raw.mem_usage))?,
+        memory_limit_bytes: parse_size(memory_limit)
+            .ok_or_else(|| invalid("MemUsage", &raw.mem_usage))?,
+    })
+}
+
+/// Parses the output of `docker image ls --format '{{json .}}'`, which has a JSON object per line,
+/// e.g. `{"ID":"sha256:...","Repository":"ubuntu","Tag":"24.04","Size":"78.1MB",...}`.
+pub fn parse_docker_images(output: &str) -> Result<Vec<DockerImage>, String> {
+    #[derive(Deserialize)]
+    struct RawImage {
+        #[serde(rename = "ID")]
+        id: String,
+        #[serde(rename = "Repository")]
+        repository: String,
+        #[serde(rename = "Tag")]
+        tag: String,
+        #[serde(rename = "Size")]
+        size: String,
+        /// e.g. `2024-05-01 12:34:56 +0900 KST`
+        #[serde(rename = "CreatedAt")]
+        created_at: String,
+    }
+
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let raw: RawImage = serde_json::from_str(line)
+                .map_err(|err| format!("Failed to parse 'docker image ls' output: {}", err))?;
+            let invalid = |name: &str, value: &str| {
+                format!("Invalid '{}' in 'docker image ls' output: {}", name, value)
+            };
+            // Ignore the trailing time zone name, which chrono can't parse.
+            let created_at = raw
+                .created_at
+                .split_whitespace()
+                .take(3)
+                .collect::<Vec<_>>()
+                .join(" ");
+            Ok(DockerImage {
+                size_bytes: parse_size(&raw.size).ok_or_else(|| invalid("Size", &raw.size))?,
+                created_at: DateTime::parse_from_str(&created_at, "%Y-%m-%d %H:%M:%S %z")
+                    .map_err(|_| invalid("CreatedAt", &raw.created_at))?
+                    .to_utc(),
+                id: raw.id,
+                repository: raw.repository,
+                tag: raw.tag,
+            })
+        })
+        .collect()
+}
+
+/// Parses a size reported by Docker, e.g. `1.5GB` or `1.5GiB`.
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let unit_start = size
+        .find(|ch: char| !ch.is_ascii_digit() && ch != '.')
+        .unwrap_or(size.len());
+    let value: f64 = size[..unit_start].parse().ok()?;
+    // 'docker image prune' reports the sizes in decimal units,
+    // while 'docker container stats' reports the memory usage in binary units.
+    let multiplier: u64 = match &size[unit_start..] {
+        "B" | "" => 1,
+        "kB" | "KB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        "TB" => 1_000_000_000_000,
+        "PB" => 1_000_000_000_000_000,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        "TiB" => 1 << 40,
+        "PiB" => 1 << 50,
+        _ => return None,
+    };
+    Some((value * multiplier as f64).round() as u64)
+}
+
+/// Substitutes the `{key}` placeholders in the specified runner name template.
+/// The placeholders without a matching variable are left as they are.
+pub fn render_runner_name(template: &str, vars: &HashMap<&str, &str>) -> String {
+    RUNNER_NAME_PLACEHOLDER_RE
+        .replace_all(template, |caps: &Captures| {
+            let name = caps.get(1).unwrap().as_str();
+            match vars.get(name) {
+                Some(value) => value.to_string(),
+                None => caps.get(0).unwrap().as_str().to_string(),
+            }
+        })
+        .to_string()
+}
+
+/// Returns the regular expression that matches the runner names rendered from the specified
+/// template. The placeholders without a matching variable match any non-empty string.
+fn runner_name_regex(template: &str, vars: &HashMap<&str, &str>) -> Regex {
+    let mut pattern = String::from("^");
+    let mut last_end = 0;
+    for caps in RUNNER_NAME_PLACEHOLDER_RE.captures_iter(template) {
+        let placeholder = caps.get(0).unwrap();
+        pattern.push_str(&regex::escape(&template[last_end..placeholder.start()]));
+        match vars.get(caps.get(1).unwrap().as_str()) {
+            Some(value) => pattern.push_str(&regex::escape(value)),
+            None => pattern.push_str(".+"),
+        }
+        last_end = placeholder.end();
+    }
+    pattern.push_str(&regex::escape(&template[last_end..]));
+    pattern.push('$');
+    Regex::new(&pattern).unwrap()
+}
+
+static RUNNER_NAME_PLACEHOLDER_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{([^}]*)}").unwrap());
+
 pub trait StringExt {
+    /// Appends the specified string, quoting it so that a POSIX shell treats it as a single word:
+    ///
+    /// - A string without any special characters is appended as it is.
+    /// - A string with control characters, e.g. `\n`, `\r`, `\0` and DEL, is ANSI-C-quoted,
+    ///   e.g. `$'foo\nbar'`, with each control character replaced by its escape sequence.
+    ///   Note that the shell truncates the word at `\x00` because an argument can't contain
+    ///   a null byte, but the rest of the command remains intact.
+    /// - A string with other special characters is double-quoted, with `"`, `\`, `$` and
+    ///   `` ` `` escaped with a backslash.
     fn push_str_escaped(&mut self, s: &str);
+
+    /// Appends the specified string in ANSI-C quotes, e.g. `$'foo\nbar'`, which bash, zsh and
+    /// ksh expand back into the same string. `'` and `\` are escaped with a backslash, and
+    /// control characters are replaced with their escape sequences, e.g. `\n`, `\x00` and
+    /// `\u0085`, so that the quoted string never spans multiple lines.
+    fn push_str_ansi_c_quoted(&mut self, s: &str);
 }
 
 impl StringExt for String {
     fn push_str_escaped(&mut self, s: &str) {
+        if s.contains(char::is_control) {
+            self.push_str_ansi_c_quoted(s);
+            return;
+        }
+
         if !s.contains([
             '\'', '"', ' ', '\\', '|', '&', '!', ';', '$', '(', ')', '[', ']', '{', '}', '<', '>',
-            '#', '`',
+            '#', '`', '*', '?', '~',
         ]) {
             // No need to escape
             self.push_str(s);
@@ -323,9 +3077,31 @@ impl StringExt for String {
             match ch {
                 '"' => self.push_str("\\\""),
                 '\\' => self.push_str("\\\\"),
+                // Prevent parameter expansion and command substitution in double quotes.
+                '$' => self.push_str("\\$"),
+                '`' => self.push_str("\\`"),
                 _ => self.push(ch),
             }
         }
         self.push('"');
     }
+
+    fn push_str_ansi_c_quoted(&mut self, s: &str) {
+        self.push_str("$'");
+        for ch in s.chars() {
+            match ch {
+                '\'' => self.push_str("\\'"),
+                '\\' => self.push_str("\\\\"),
+                '\n' => self.push_str("\\n"),
+                '\r' => self.push_str("\\r"),
+                '\t' => self.push_str("\\t"),
+                // C0 control characters and DEL
+                _ if ch.is_ascii_control() => self.push_str(&format!("\\x{:02x}", ch as u32)),
+                // C1 control characters
+                _ if ch.is_control() => self.push_str(&format!("\\u{:04x}", ch as u32)),
+                _ => self.push(ch),
+            }
+        }
+        self.push('\'');
+    }
 }
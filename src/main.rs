@@ -1,32 +1,173 @@
-mod config;
-mod github;
-mod machine;
+mod commands;
 
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::path::PathBuf;
 use std::process::exit;
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-use crate::config::{Config, ConfigError, LogLevel};
-use crate::github::GithubClient;
-use crate::machine::Machine;
-use clap::Parser;
-use log::{debug, error, info, LevelFilter};
+use clap::{Parser, Subcommand, ValueEnum};
+use gh_actions_scaler::config::{Config, ConfigError, LogLevel};
+use gh_actions_scaler::github::{GithubClient, WorkflowRun};
+use gh_actions_scaler::machine::{
+    append_audit_log, deregister_orphaned_runners, Machine, ScalingAction, ScalingDecision,
+    DEFAULT_STOP_TIMEOUT_SECS,
+};
+use gh_actions_scaler::monitor::ResourceMonitor;
+use gh_actions_scaler::output::OutputFormat;
+use gh_actions_scaler::pool::MachinePool;
+use std::str::FromStr;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::reload;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Registry;
+use ureq::serde_json;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    /// Sets a custom config file.
-    #[arg(short, long, value_name = "FILE")]
+    /// Sets a custom config file, or an HTTPS URL to download it from.
+    #[arg(short, long, value_name = "FILE|URL")]
     config: Option<PathBuf>,
 
+    /// Sets a config file to merge on top of the config file.
+    #[arg(long, value_name = "FILE")]
+    override_config: Option<PathBuf>,
+
     /// Sets the log level.
     #[arg(short, long, value_name = "LEVEL")]
     log_level: Option<LogLevel>,
+
+    /// Sets the log format.
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Prints the resolved configuration with the credentials redacted and exits.
+    #[arg(long)]
+    print_config: bool,
+
+    /// Prints the JSON Schema of the config file and exits.
+    #[arg(long)]
+    print_schema: bool,
+
+    /// Prints the output of the specified runner container as it arrives and exits
+    /// when the container exits.
+    #[arg(long, value_name = "CONTAINER_ID")]
+    tail_logs: Option<String>,
+
+    /// Sets the number of the existing lines to print with '--tail-logs'.
+    #[arg(long, value_name = "LINES", default_value_t = 100)]
+    tail_lines: usize,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    /// Human-readable log lines
+    Text,
+    /// One JSON object per log event
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Lists the configured machines with the number of their live runners.
+    ListMachines {
+        /// Sets the output format.
+        #[arg(
+            short,
+            long,
+            value_enum,
+            default_value_t = OutputFormat::Table,
+            alias = "format",
+            short_alias = 'f'
+        )]
+        output: OutputFormat,
+    },
+    /// Lists the runner containers of all machines.
+    ListRunners {
+        /// Sets the output format.
+        #[arg(
+            short,
+            long,
+            value_enum,
+            default_value_t = OutputFormat::Table,
+            alias = "format",
+            short_alias = 'f'
+        )]
+        output: OutputFormat,
+        /// Limits to the machines tagged with the specified tag.
+        #[arg(long, value_name = "TAG")]
+        machines_with_tag: Option<String>,
+    },
+    /// Lists the container images cached on all machines, marking the runner images.
+    ListImages {
+        /// Limits to the machines tagged with the specified tag.
+        #[arg(long, value_name = "TAG")]
+        machines_with_tag: Option<String>,
+    },
+    /// Scales the runners periodically, reloading the configuration when it changes
+    /// or SIGHUP is received.
+    Daemon {
+        /// Sets the number of seconds between scaling passes.
+        #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+        interval: u64,
+    },
+    /// Prints a summary of every machine, i.e. its SSH connectivity, the number of its running
+    /// runners and whether the runner image exists.
+    Status {
+        /// Limits to the machines tagged with the specified tag.
+        #[arg(long, value_name = "TAG")]
+        machines_with_tag: Option<String>,
+    },
+    /// Verifies the SSH connectivity to all machines without running any Docker commands.
+    TestSsh {
+        /// Limits to the machines tagged with the specified tag.
+        #[arg(long, value_name = "TAG")]
+        machines_with_tag: Option<String>,
+    },
+    /// Downloads the output of the specified runner container into a local file via SFTP,
+    /// which works for a large output that '--tail-logs' would take long to print.
+    SaveLogs {
+        /// Sets the ID of the runner container. A unique prefix of the ID is accepted as well.
+        #[arg(value_name = "CONTAINER_ID")]
+        container_id: String,
+        /// Sets the local file to write the output to.
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+    },
+    /// Removes the unused container images from all machines.
+    Prune,
+    /// Stops the live runner containers of all machines.
+    Cleanup {
+        /// Kills the containers instead of stopping them gracefully.
+        #[arg(long)]
+        force: bool,
+        /// Sets the number of seconds to wait for a container to stop before killing it.
+        #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_STOP_TIMEOUT_SECS)]
+        timeout: u32,
+    },
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     // Determine the path of the configuration file.
     let cli = Cli::parse();
+    if cli.print_schema {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&schemars::schema_for!(Config))?
+        );
+        return Ok(());
+    }
+
     let config_path = cli.config.unwrap_or_else(|| {
         if let Some(user_config_dir) = dirs::config_dir() {
             let mut buf = PathBuf::new();
@@ -41,74 +182,518 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     });
 
-    pretty_env_logger::formatted_timed_builder()
-        .default_format()
-        .format_module_path(false)
-        .format_target(false)
-        // Make sure the messages at any log levels are preserved,
-        // so that we can dynamically adjust the log level after loading the configuration.
-        .filter_level(LevelFilter::Trace)
-        .init();
+    let logger = Logger::init(cli.log_format);
 
     // Start with INFO or CLI-provided level.
-    log::set_max_level(cli.log_level.unwrap_or(LogLevel::Info).to_level_filter());
+    logger.set_level(cli.log_level.unwrap_or(LogLevel::Info));
 
     info!("Using the configuration at: {}", config_path.display());
-    let config = match Config::try_from(config_path.as_path()) {
+    if let Some(override_config_path) = &cli.override_config {
+        info!(
+            "Using the override configuration at: {}",
+            override_config_path.display()
+        );
+    }
+    let config = Config::load(config_path.as_path(), cli.override_config.as_deref());
+    let config = match config {
         Ok(config) => config,
-        Err(err) => match err {
-            ConfigError::ReadFailure { path, cause } => {
-                error!(
-                    "Failed to read the configuration file: {} ({})",
-                    path, cause
-                );
-                exit(1);
+        Err(ConfigError::ValidationFailures { messages }) => {
+            error!("Invalid configuration: {} problems found", messages.len());
+            for message in messages {
+                error!("- {}", message);
             }
-            ConfigError::ParseFailure { path, cause } => {
-                error!(
-                    "Failed to parse the configuration file: {} ({})",
-                    path, cause
-                );
-                exit(1);
-            }
-            ConfigError::UnresolvedEnvironmentVariable { name, cause } => {
-                error!(
-                    "Failed to resolve an environment variable: {} ({})",
-                    name, cause
-                );
-                exit(1);
-            }
-            ConfigError::UnresolvedFileVariable { path, cause } => {
-                error!("Failed to resolve an external file: {} ({})", path, cause);
-                exit(1);
-            }
-            ConfigError::ValidationFailure { message } => {
-                error!("Invalid configuration: {}", message);
-                exit(1);
-            }
-        },
+            exit(1);
+        }
+        Err(err) => {
+            error!("{}", err);
+            exit(1);
+        }
     };
 
     // Use the log level specified in the configuration file, if CLI log level was not specified.
     if cli.log_level.is_none() {
-        log::set_max_level(config.log_level.to_level_filter());
+        logger.set_level(config.log_level);
     }
 
     debug!("Deserialized configuration: {:#?}", config);
 
-    let github_client = GithubClient::new(&config.github);
-    let queued_runs = github_client.fetch_queued_workflow_runs()?;
+    if cli.print_config {
+        print!("{}", config.redacted().to_yaml_string()?);
+        return Ok(());
+    }
+
+    if let Some(container_id) = &cli.tail_logs {
+        if !commands::tail_logs(&config, container_id, cli.tail_lines)? {
+            exit(1);
+        }
+        return Ok(());
+    }
+
+    let is_ok = match cli.command {
+        None => {
+            let machines = new_machines(&config, &[]);
+            let failed_machine_ids = preflight(&config, &machines);
+            let github_client = GithubClient::new(&config.github);
+            scale(
+                &config,
+                &machines,
+                &failed_machine_ids,
+                &github_client,
+                &mut HashMap::new(),
+            )?;
+            failed_machine_ids.is_empty()
+        }
+        Some(Command::ListMachines { output }) => commands::list_machines(&config, output)?,
+        Some(Command::ListRunners {
+            output,
+            machines_with_tag,
+        }) => commands::list_runners(&with_machines_tagged(config, machines_with_tag), output)?,
+        Some(Command::Daemon { interval }) => {
+            let (sender, receiver) = mpsc::channel();
+            Config::watch(&config_path, cli.override_config.as_deref(), sender)?;
+            run_daemon(config, &receiver, Duration::from_secs(interval), |config| {
+                if cli.log_level.is_none() {
+                    logger.set_level(config.log_level);
+                }
+            });
+            true
+        }
+        Some(Command::ListImages { machines_with_tag }) => {
+            commands::list_images(&with_machines_tagged(config, machines_with_tag))?
+        }
+        Some(Command::Status { machines_with_tag }) => {
+            commands::status(&with_machines_tagged(config, machines_with_tag))?
+        }
+        Some(Command::TestSsh { machines_with_tag }) => {
+            commands::test_ssh(&with_machines_tagged(config, machines_with_tag))?
+        }
+        Some(Command::SaveLogs {
+            container_id,
+            output,
+        }) => commands::save_logs(&config, &container_id, &output)?,
+        Some(Command::Prune) => commands::prune(&config)?,
+        Some(Command::Cleanup { force, timeout }) => commands::cleanup(&config, force, timeout)?,
+    };
+
+    if !is_ok {
+        exit(1);
+    }
+
+    Ok(())
+}
+
+/// Returns the configuration with only the machines tagged with `tag`, or as it is if unspecified.
+fn with_machines_tagged(config: Config, tag: Option<String>) -> Config {
+    match tag {
+        Some(tag) => {
+            let config = config.with_machines_tagged(&tag);
+            if config.machines.is_empty() {
+                warn!("No machine is tagged with '{}'.", tag);
+            }
+            config
+        }
+        None => config,
+    }
+}
+
+/// Scales the runners every `interval`, replacing the configuration whenever
+/// a reloaded one arrives from `receiver`.
+fn run_daemon<F>(mut config: Config, receiver: &Receiver<Config>, interval: Duration, on_reload: F)
+where
+    F: Fn(&Config),
+{
+    // Reuse the SSH session of each machine and the connections to GitHub across the passes.
+    let mut machines = new_machines(&config, &[]);
+    let mut failed_machine_ids = preflight(&config, &machines);
+    let mut github_client = GithubClient::new(&config.github);
+
+    // Start the warm pool before handling any run, so that it's ready even if GitHub isn't.
+    let decisions = keep_warm(&config, &machines, &failed_machine_ids, &github_client);
+    append_decisions(&config, &decisions);
+
+    // The containers started for each run, kept while the daemon is running.
+    let mut started_runners: HashMap<String, Vec<String>> = HashMap::new();
+    // Becomes `None` once the configuration watcher stops.
+    let mut receiver = Some(receiver);
+    loop {
+        if let Err(err) = scale(
+            &config,
+            &machines,
+            &failed_machine_ids,
+            &github_client,
+            &mut started_runners,
+        ) {
+            error!("Failed to scale the runners: {}", err);
+        }
+        ResourceMonitor::new(&machines).sample();
+
+        let Some(config_receiver) = receiver else {
+            thread::sleep(interval);
+            continue;
+        };
+        match config_receiver.recv_timeout(interval) {
+            Ok(new_config) => {
+                // Use the latest one if the configuration was reloaded more than once.
+                let new_config = config_receiver.try_iter().last().unwrap_or(new_config);
+                log_config_changes(&config, &new_config);
+                on_reload(&new_config);
+                machines = new_machines(&new_config, &machines);
+                failed_machine_ids = preflight(&new_config, &machines);
+                github_client = GithubClient::new(&new_config.github);
+                config = new_config;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                warn!("The configuration watcher has stopped; the configuration will not be reloaded.");
+                receiver = None;
+                // recv_timeout() returns immediately once disconnected.
+                thread::sleep(interval);
+            }
+        }
+    }
+}
+
+/// Returns a machine for each machine in the configuration, reusing the one in `old_machines`
+/// whose configuration hasn't changed, so that its SSH session is reused.
+fn new_machines(config: &Config, old_machines: &[Machine]) -> Vec<Machine> {
+    config
+        .machines
+        .iter()
+        .map(|machine_config| {
+            old_machines
+                .iter()
+                .find(|machine| machine.config() == machine_config.as_ref())
+                .cloned()
+                .unwrap_or_else(|| Machine::new(Arc::clone(machine_config)))
+        })
+        .collect()
+}
+
+/// Checks the machines that the runners are placed on, and returns the IDs of the ones that
+/// are not ready to run the runners, so that no runner is placed on them.
+fn preflight(config: &Config, machines: &[Machine]) -> HashSet<String> {
+    let failed_machine_ids = commands::preflight(config, machines);
+    if !failed_machine_ids.is_empty() {
+        let mut ids: Vec<&String> = failed_machine_ids.iter().collect();
+        ids.sort();
+        error!(
+            "Preflight check failed; not placing the runners on: {:?}",
+            ids
+        );
+    }
+    failed_machine_ids
+}
+
+/// Starts the runners of the machines that the runners are placed on, whose idle runners are
+/// fewer than 'runners.min'. The machines in `failed_machine_ids` are left out.
+fn keep_warm(
+    config: &Config,
+    machines: &[Machine],
+    failed_machine_ids: &HashSet<String>,
+    github_client: &GithubClient,
+) -> Vec<ScalingDecision> {
+    let mut decisions: Vec<ScalingDecision> = vec![];
+    for machine in machines {
+        if !config.places_runners_on(machine.config()) || failed_machine_ids.contains(machine.id())
+        {
+            continue;
+        }
+        let machine_decisions = machine
+            .keep_warm(config, github_client)
+            .unwrap_or_else(|err| {
+                vec![ScalingDecision {
+                    machine_id: machine.id().to_string(),
+                    action: ScalingAction::Error,
+                    reason: format!("Failed to keep the warm pool: {}", err),
+                    run_url: None,
+                    container_id: None,
+                    container_name: None,
+                }]
+            });
+        for decision in machine_decisions {
+            decision.log();
+            decisions.push(decision);
+        }
+    }
+    decisions
+}
+
+/// Appends the scaling decisions to the audit log if 'audit_log' is specified.
+fn append_decisions(config: &Config, decisions: &[ScalingDecision]) {
+    if let Some(audit_log) = &config.audit_log {
+        if let Err(err) = append_audit_log(audit_log, decisions) {
+            warn!(
+                "Failed to append the scaling decisions to {}: {}",
+                audit_log.display(),
+                err
+            );
+        }
+    }
+}
+
+fn log_config_changes(old_config: &Config, new_config: &Config) {
+    info!("Reloaded the configuration");
+    for machine in &new_config.machines {
+        match old_config.machines.iter().find(|m| m.id == machine.id) {
+            None => info!(machine = %machine.id, "Added a machine: {}", machine.id),
+            Some(old_machine) if old_machine != machine => {
+                info!(machine = %machine.id, "Changed a machine: {}", machine.id)
+            }
+            Some(_) => {}
+        }
+    }
+    for machine in &old_config.machines {
+        if !new_config.machines.iter().any(|m| m.id == machine.id) {
+            info!(machine = %machine.id, "Removed a machine: {}", machine.id);
+        }
+    }
+
+    // Log the changed lines of the configuration.
+    let to_yaml = |config: &Config| config.redacted().to_yaml_string().unwrap_or_default();
+    let (old_yaml, new_yaml) = (to_yaml(old_config), to_yaml(new_config));
+    let old_lines: Vec<&str> = old_yaml.lines().collect();
+    let new_lines: Vec<&str> = new_yaml.lines().collect();
+    for line in old_lines.iter().filter(|line| !new_lines.contains(line)) {
+        debug!("Configuration change: - {}", line);
+    }
+    for line in new_lines.iter().filter(|line| !old_lines.contains(line)) {
+        debug!("Configuration change: + {}", line);
+    }
+}
+
+/// Scales the runners of all machines once. `machines` must contain a machine for each machine in
+/// the configuration, and no runner is placed on the machines in `failed_machine_ids`. `started_runners` maps the URL of a run to the IDs of the containers started
+/// for it, so that a run that stays queued can be told apart.
+fn scale(
+    config: &Config,
+    machines: &[Machine],
+    failed_machine_ids: &HashSet<String>,
+    github_client: &GithubClient,
+    started_runners: &mut HashMap<String, Vec<String>>,
+) -> Result<(), Box<dyn Error>> {
+    let queued_runs: Vec<WorkflowRun> = github_client
+        .fetch_queued_workflow_runs()?
+        .into_iter()
+        .filter(|run| {
+            if run.is_completed() {
+                debug!(
+                    run_url = %run.url,
+                    "Skipping {}; it has completed already with the conclusion '{}'",
+                    run.url,
+                    run.conclusion.as_deref().unwrap_or_default()
+                );
+                return false;
+            }
+            true
+        })
+        .collect();
 
     info!("{:#?}", queued_runs);
 
-    let first_machine = Machine::new(&config.machines[0]);
-    debug!("{:#?}", first_machine.fetch_runners()?);
+    // Forget the runs that are not queued anymore.
+    started_runners.retain(|run_url, _| queued_runs.iter().any(|run| &run.url == run_url));
+    for run in &queued_runs {
+        if let Some(container_ids) = started_runners.get(&run.url) {
+            debug!(
+                run_url = %run.url,
+                "{} is still queued; waiting for the runner(s) started for it: {:?}",
+                run.url, container_ids
+            );
+        }
+    }
+
+    // The runners dedicated to these runs are still waiting for them.
+    let queued_run_ids: HashSet<u64> = queued_runs.iter().map(|run| run.run_id).collect();
+
+    // Clean up the runners left behind by the containers that died unexpectedly.
+    if let Err(err) = deregister_orphaned_runners(machines, github_client, config) {
+        warn!("Failed to deregister orphaned runners: {}", err);
+    }
+
+    // Assign the queued runs to the machines that can run them. A run whose jobs require
+    // different labels is assigned once per job.
+    let places_runners_on = |machine: &Machine| {
+        config.places_runners_on(machine.config()) && !failed_machine_ids.contains(machine.id())
+    };
+    let placeable_machines: Vec<Machine> = machines
+        .iter()
+        .filter(|machine| places_runners_on(machine))
+        .cloned()
+        .collect();
+    let mut pool = MachinePool::new(&placeable_machines, config.selection_strategy);
+    let mut runs_by_machine: HashMap<String, Vec<&WorkflowRun>> = HashMap::new();
+    for run in &queued_runs {
+        for labels in run.runner_labels() {
+            let machine = match pool.next_machine(labels) {
+                Ok(machine) => machine,
+                Err(err) => {
+                    warn!(
+                        run_url = %run.url,
+                        "Failed to assign {} requiring {:?} to a machine: {}",
+                        run.url, labels, err
+                    );
+                    continue;
+                }
+            };
+            runs_by_machine
+                .entry(machine.id().to_string())
+                .or_default()
+                .push(run);
+        }
+    }
+
+    // Start a runner for each assigned run, and keep 'runners.min' idle runners on top of them,
+    // which also replaces the warm pool runners that exited or were cleaned up.
+    // Leave the runners of the disabled, filtered out or failed machines as they are.
+    let mut decisions: Vec<ScalingDecision> = vec![];
+    for machine in machines {
+        if !places_runners_on(machine) {
+            continue;
+        }
+        let machine_config = machine.config();
+        let runs = runs_by_machine
+            .remove(&machine_config.id)
+            .unwrap_or_default();
+        // Connect first, so that an unreachable machine fails only once.
+        if let Err(err) = machine.connect_if_disconnected() {
+            let decision = ScalingDecision {
+                machine_id: machine_config.id.clone(),
+                action: ScalingAction::Error,
+                reason: format!("Failed to connect: {}", err),
+                run_url: None,
+                container_id: None,
+                container_name: None,
+            };
+            decision.log();
+            decisions.push(decision);
+            continue;
+        }
+        let target = u32::try_from(runs.len())
+            .unwrap_or(u32::MAX)
+            .saturating_add(machine_config.runners.min.unwrap_or(0))
+            .min(machine_config.runners.max);
+
+        for decision in machine.start_runners(config, github_client, &runs, target) {
+            decision.log();
+            if let (ScalingAction::Start, Some(run_url), Some(container_id)) =
+                (decision.action, &decision.run_url, &decision.container_id)
+            {
+                started_runners
+                    .entry(run_url.clone())
+                    .or_default()
+                    .push(container_id.clone());
+            }
+            decisions.push(decision);
+        }
+
+        let machine_decisions = machine
+            .scale_to(target, config, github_client, &queued_run_ids)
+            .unwrap_or_else(|err| {
+                vec![ScalingDecision {
+                    machine_id: machine.id().to_string(),
+                    action: ScalingAction::Error,
+                    reason: format!("Failed to scale to {} runner(s): {}", target, err),
+                    run_url: None,
+                    container_id: None,
+                    container_name: None,
+                }]
+            });
+        for decision in machine_decisions {
+            decision.log();
+            decisions.push(decision);
+        }
+
+        let machine_decisions = machine
+            .stop_idle_runners(github_client)
+            .unwrap_or_else(|err| {
+                vec![ScalingDecision {
+                    machine_id: machine.id().to_string(),
+                    action: ScalingAction::Error,
+                    reason: format!("Failed to stop the idle runners: {}", err),
+                    run_url: None,
+                    container_id: None,
+                    container_name: None,
+                }]
+            });
+        for decision in machine_decisions {
+            decision.log();
+            decisions.push(decision);
+        }
+    }
+
+    append_decisions(config, &decisions);
+
+    // Reclaim the disk space taken by the images that are not used anymore.
+    let mut total_reclaimed: u64 = 0;
+    for machine in machines {
+        if machine.config().docker.auto_prune != Some(true) {
+            continue;
+        }
 
-    for run in queued_runs {
-        info!("Starting a new runner for: {}", run.url);
-        first_machine.start_runner(&config)?;
-        debug!("{:#?}", first_machine.fetch_runners()?);
+        match machine.prune_docker_images() {
+            Ok(reclaimed) => total_reclaimed += reclaimed,
+            Err(err) => warn!(
+                machine = machine.id(),
+                "[{}] Failed to prune the unused container images: {}",
+                machine.id(),
+                err
+            ),
+        }
+    }
+    if total_reclaimed > 0 {
+        info!("Reclaimed {} bytes by pruning images", total_reclaimed);
     }
 
     Ok(())
 }
+
+/// The installed logger, whose level can be adjusted after loading the configuration.
+enum Logger {
+    Text,
+    Json(reload::Handle<LevelFilter, Registry>),
+}
+
+impl Logger {
+    fn init(format: LogFormat) -> Self {
+        match format {
+            LogFormat::Text => {
+                pretty_env_logger::formatted_timed_builder()
+                    .default_format()
+                    .format_module_path(false)
+                    .format_target(false)
+                    // Make sure the messages at any log levels are preserved,
+                    // so that we can dynamically adjust the log level after loading the configuration.
+                    .filter_level(log::LevelFilter::Trace)
+                    .init();
+                Logger::Text
+            }
+            LogFormat::Json => {
+                let (filter, handle) = reload::Layer::new(LevelFilter::TRACE);
+                tracing_subscriber::registry()
+                    .with(filter)
+                    .with(
+                        tracing_subscriber::fmt::layer()
+                            .json()
+                            .flatten_event(true)
+                            .with_target(false)
+                            .with_current_span(false)
+                            .with_span_list(false),
+                    )
+                    .init();
+                Logger::Json(handle)
+            }
+        }
+    }
+
+    fn set_level(&self, level: LogLevel) {
+        let level_filter = level.to_level_filter();
+        log::set_max_level(level_filter);
+        if let Logger::Json(handle) = self {
+            let level_filter = LevelFilter::from_str(level_filter.as_str())
+                .expect("Failed to convert LogLevel into LevelFilter");
+            if let Err(err) = handle.reload(level_filter) {
+                eprintln!("Failed to change the log level: {}", err);
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::machine::{ContainerState, ContainerStats, Machine, RunnerQuery};
+use std::error::Error;
+use tracing::{debug, warn, Level};
+
+/// Samples the CPU and memory usage of the running runner containers of the machines,
+/// and logs the averages of each machine at the `DEBUG` level.
+pub struct ResourceMonitor {
+    machines: Vec<Machine>,
+}
+
+impl ResourceMonitor {
+    /// Creates a monitor that samples the specified machines, reusing their SSH sessions.
+    pub fn new(machines: &[Machine]) -> Self {
+        ResourceMonitor {
+            machines: machines.to_vec(),
+        }
+    }
+
+    /// Samples all machines once. Does nothing unless the `DEBUG` level is enabled,
+    /// because sampling takes a few SSH round trips per machine.
+    pub fn sample(&self) {
+        if !tracing::enabled!(Level::DEBUG) {
+            return;
+        }
+
+        for machine in &self.machines {
+            match Self::sample_machine(machine) {
+                Ok((count, Some(average))) => debug!(
+                    machine = machine.id(),
+                    "[{}] Average resource usage of {} running runner(s): CPU {:.2}%, memory {} / {} bytes",
+                    machine.id(),
+                    count,
+                    average.cpu_percent,
+                    average.memory_bytes,
+                    average.memory_limit_bytes
+                ),
+                Ok((_, None)) => debug!(
+                    machine = machine.id(),
+                    "[{}] No running runners to sample",
+                    machine.id()
+                ),
+                Err(err) => warn!(
+                    machine = machine.id(),
+                    "[{}] Failed to sample the resource usage: {}",
+                    machine.id(),
+                    err
+                ),
+            }
+        }
+    }
+
+    fn sample_machine(
+        machine: &Machine,
+    ) -> Result<(usize, Option<ContainerStats>), Box<dyn Error>> {
+        let runners = RunnerQuery::new()
+            .in_state(ContainerState::Running)
+            .execute(machine)?;
+        let container_ids: Vec<&str> = runners.iter().map(|runner| runner.container_id()).collect();
+        let stats = machine.get_resource_usages(&container_ids)?;
+        Ok((stats.len(), average_stats(&stats)))
+    }
+}
+
+/// Returns the average of the specified samples, or `None` if empty.
+pub fn average_stats(stats: &[ContainerStats]) -> Option<ContainerStats> {
+    if stats.is_empty() {
+        return None;
+    }
+
+    let count = stats.len();
+    Some(ContainerStats {
+        cpu_percent: stats.iter().map(|s| s.cpu_percent).sum::<f32>() / count as f32,
+        memory_bytes: stats.iter().map(|s| s.memory_bytes).sum::<u64>() / count as u64,
+        memory_limit_bytes: stats.iter().map(|s| s.memory_limit_bytes).sum::<u64>() / count as u64,
+    })
+}
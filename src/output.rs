@@ -0,0 +1,298 @@
+use crate::display::render_grid;
+use crate::machine::{ContainerState, RunnerInfo};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::IsTerminal;
+use termcolor::Color;
+use ureq::serde_json;
+
+/// The output format of the `list-machines` and `list-runners` subcommands.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// A human-readable table
+    #[value(alias = "text")]
+    Table,
+    /// A machine-readable JSON array
+    Json,
+    /// Comma-separated values with a header row
+    Csv,
+}
+
+impl OutputFormat {
+    /// Returns the [`Formatter`] of this format. A table is colored only if the standard output
+    /// is a terminal.
+    pub fn formatter(self) -> Box<dyn Formatter> {
+        match self {
+            OutputFormat::Table => Box::new(TableFormatter::new(std::io::stdout().is_terminal())),
+            OutputFormat::Json => Box::new(JsonFormatter),
+            OutputFormat::Csv => Box::new(CsvFormatter),
+        }
+    }
+}
+
+/// A row of the `list-machines` output.
+#[derive(Serialize)]
+pub struct MachineRow<'a> {
+    pub id: &'a str,
+    pub host: &'a str,
+    pub port: u16,
+    pub status: MachineStatus,
+    /// The round-trip time of a ping, or `None` if the machine didn't respond.
+    pub latency_ms: Option<u64>,
+    pub runners: Option<usize>,
+    pub error: Option<String>,
+}
+
+/// The health of a machine in the `list-machines` output.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum MachineStatus {
+    /// The machine responded to a ping and its runners were retrieved.
+    Online,
+    /// The machine responded to a ping, but its runners couldn't be retrieved.
+    Degraded,
+    /// The machine didn't respond to a ping.
+    Offline,
+}
+
+impl MachineStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            MachineStatus::Online => "ONLINE",
+            MachineStatus::Degraded => "DEGRADED",
+            MachineStatus::Offline => "OFFLINE",
+        }
+    }
+}
+
+/// A row of the `list-runners` output.
+#[derive(Serialize)]
+pub struct RunnerRow<'a> {
+    pub machine_id: &'a str,
+    #[serde(flatten)]
+    pub runner: &'a RunnerInfo,
+}
+
+/// Renders the rows of the `list-machines` and `list-runners` subcommands. The rendered output
+/// ends with a line break.
+pub trait Formatter {
+    fn format_machines(&self, machines: &[MachineRow]) -> String;
+
+    fn format_runners(&self, runners: &[RunnerRow]) -> String;
+}
+
+/// Renders a table with the box-drawing characters, coloring the container states if `color`
+/// is `true`: green for `running`, yellow for `exited` and red for `dead`.
+pub struct TableFormatter {
+    color: bool,
+}
+
+impl TableFormatter {
+    pub fn new(color: bool) -> Self {
+        TableFormatter { color }
+    }
+}
+
+impl Formatter for TableFormatter {
+    fn format_machines(&self, machines: &[MachineRow]) -> String {
+        let rows: Vec<_> = machines
+            .iter()
+            .map(|row| {
+                let status_color = match row.status {
+                    MachineStatus::Online => Color::Green,
+                    MachineStatus::Degraded => Color::Yellow,
+                    MachineStatus::Offline => Color::Red,
+                };
+                let runners = match (row.runners, &row.error) {
+                    (Some(runners), _) => (runners.to_string(), None),
+                    (None, Some(error)) => (format!("error: {}", error), Some(Color::Red)),
+                    (None, None) => ("-".to_string(), None),
+                };
+                vec![
+                    (row.id.to_string(), None),
+                    (row.host.to_string(), None),
+                    (row.port.to_string(), None),
+                    (row.status.as_str().to_string(), Some(status_color)),
+                    (
+                        row.latency_ms
+                            .map(|latency| latency.to_string())
+                            .unwrap_or_else(|| "-".to_string()),
+                        None,
+                    ),
+                    runners,
+                ]
+            })
+            .collect();
+        render_grid(
+            &["ID", "HOST", "PORT", "STATUS", "LATENCY (ms)", "RUNNERS"],
+            &rows,
+            self.color,
+        )
+    }
+
+    fn format_runners(&self, runners: &[RunnerRow]) -> String {
+        let rows: Vec<_> = runners
+            .iter()
+            .map(|row| {
+                let runner = row.runner;
+                let state_color = match runner.state() {
+                    ContainerState::Running => Some(Color::Green),
+                    ContainerState::Exited => Some(Color::Yellow),
+                    ContainerState::Dead => Some(Color::Red),
+                    _ => None,
+                };
+                vec![
+                    (row.machine_id.to_string(), None),
+                    (
+                        runner.container_id()[..runner.container_id().len().min(12)].to_string(),
+                        None,
+                    ),
+                    (runner.state().to_string(), state_color),
+                    (format_timestamp(Some(runner.created_at())), None),
+                    (format_timestamp(runner.started_at()), None),
+                    (format_timestamp(runner.finished_at()), None),
+                ]
+            })
+            .collect();
+        render_grid(
+            &[
+                "MACHINE",
+                "CONTAINER",
+                "STATE",
+                "CREATED",
+                "STARTED",
+                "FINISHED",
+            ],
+            &rows,
+            self.color,
+        )
+    }
+}
+
+/// Renders a compact JSON array in a single line.
+pub struct JsonFormatter;
+
+impl JsonFormatter {
+    fn render<T: Serialize>(rows: &[T]) -> String {
+        // Serializing the rows never fails because they have no maps with non-string keys.
+        let mut out = serde_json::to_string(rows).unwrap();
+        out.push('\n');
+        out
+    }
+}
+
+impl Formatter for JsonFormatter {
+    fn format_machines(&self, machines: &[MachineRow]) -> String {
+        Self::render(machines)
+    }
+
+    fn format_runners(&self, runners: &[RunnerRow]) -> String {
+        Self::render(runners)
+    }
+}
+
+/// Renders the comma-separated values with a header row, as specified in RFC 4180.
+/// The fields are named after the fields of the JSON output.
+pub struct CsvFormatter;
+
+impl CsvFormatter {
+    fn render(headers: &[&str], rows: Vec<Vec<String>>) -> String {
+        let mut out = String::new();
+        let headers: Vec<String> = headers.iter().map(|header| header.to_string()).collect();
+        for row in std::iter::once(headers).chain(rows) {
+            let fields: Vec<String> = row.iter().map(|field| quote_csv_field(field)).collect();
+            out.push_str(&fields.join(","));
+            out.push_str("\r\n");
+        }
+        out
+    }
+}
+
+impl Formatter for CsvFormatter {
+    fn format_machines(&self, machines: &[MachineRow]) -> String {
+        let rows = machines
+            .iter()
+            .map(|row| {
+                vec![
+                    row.id.to_string(),
+                    row.host.to_string(),
+                    row.port.to_string(),
+                    row.status.as_str().to_string(),
+                    row.latency_ms.map(|n| n.to_string()).unwrap_or_default(),
+                    row.runners.map(|n| n.to_string()).unwrap_or_default(),
+                    row.error.clone().unwrap_or_default(),
+                ]
+            })
+            .collect();
+        Self::render(
+            &[
+                "id",
+                "host",
+                "port",
+                "status",
+                "latency_ms",
+                "runners",
+                "error",
+            ],
+            rows,
+        )
+    }
+
+    fn format_runners(&self, runners: &[RunnerRow]) -> String {
+        let rows = runners
+            .iter()
+            .map(|row| {
+                let runner = row.runner;
+                vec![
+                    row.machine_id.to_string(),
+                    runner.container_id.clone(),
+                    runner.container_state.to_string(),
+                    runner.created_at.to_rfc3339(),
+                    runner
+                        .started_at
+                        .map(|t| t.to_rfc3339())
+                        .unwrap_or_default(),
+                    runner
+                        .finished_at
+                        .map(|t| t.to_rfc3339())
+                        .unwrap_or_default(),
+                    runner.runner_name().to_string(),
+                    runner.run_url().unwrap_or_default().to_string(),
+                    runner.run_id().map(|id| id.to_string()).unwrap_or_default(),
+                ]
+            })
+            .collect();
+        Self::render(
+            &[
+                "machine_id",
+                "container_id",
+                "container_state",
+                "created_at",
+                "started_at",
+                "finished_at",
+                "runner_name",
+                "run_url",
+                "run_id",
+            ],
+            rows,
+        )
+    }
+}
+
+/// Quotes a CSV field if it contains a comma, a double quote or a line break,
+/// doubling the double quotes in it.
+pub fn quote_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn format_timestamp(timestamp: Option<&DateTime<Utc>>) -> String {
+    match timestamp {
+        Some(timestamp) => timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => "-".to_string(),
+    }
+}
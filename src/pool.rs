@@ -0,0 +1,278 @@
+use crate::config::SelectionStrategy;
+use crate::machine::{ContainerState, Machine, RunnerQuery};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::thread;
+use tracing::{debug, warn};
+
+/// Distributes the runner assignments across the enabled machines using a [`MachineSelector`].
+pub struct MachinePool {
+    machines: Vec<Machine>,
+    selector: Box<dyn MachineSelector>,
+}
+
+impl MachinePool {
+    /// Creates a pool of the enabled machines among the specified ones, sharing their SSH sessions.
+    pub fn new(machines: &[Machine], strategy: SelectionStrategy) -> Self {
+        let selector: Box<dyn MachineSelector> = match strategy {
+            SelectionStrategy::RoundRobin => Box::new(RoundRobinSelector::new()),
+            SelectionStrategy::WeightedRandom => {
+                Box::new(WeightedRandomSelector::new(StdRng::from_os_rng()))
+            }
+            SelectionStrategy::LeastLoaded => Box::new(LeastLoadedSelector::new()),
+        };
+        Self::with_selector(machines, selector)
+    }
+
+    pub fn with_selector(machines: &[Machine], selector: Box<dyn MachineSelector>) -> Self {
+        MachinePool {
+            machines: machines
+                .iter()
+                .filter(|machine| machine.is_enabled())
+                .cloned()
+                .collect(),
+            selector,
+        }
+    }
+
+    /// Selects one of the machines that satisfy the specified labels, and counts it as
+    /// a new assignment.
+    pub fn next_machine(&mut self, required_labels: &[String]) -> Result<&Machine, PoolError> {
+        let candidates: Vec<Machine> = self
+            .machines
+            .iter()
+            .filter(|machine| machine.satisfies(required_labels))
+            .cloned()
+            .collect();
+        if candidates.is_empty() {
+            return Err(PoolError::NoMachineWithLabels);
+        }
+
+        let selected_id = self.selector.select(&candidates)?.id();
+        // Never fails because the candidates are the clones of the machines.
+        Ok(self
+            .machines
+            .iter()
+            .find(|machine| machine.id() == selected_id)
+            .unwrap())
+    }
+}
+
+/// Returns the number of the running runners on the machine, or `None` if it's unreachable.
+fn running_count(machine: &Machine) -> Option<u32> {
+    // Skip the machine that doesn't respond in time before running the expensive Docker commands.
+    if let Err(err) = machine.ping() {
+        warn!(
+            machine = %machine.id(),
+            "[{}] Failed to ping: {}",
+            machine.id(), err
+        );
+        return None;
+    }
+
+    match RunnerQuery::new()
+        .in_state(ContainerState::Running)
+        .execute(machine)
+    {
+        Ok(runners) => Some(u32::try_from(runners.len()).unwrap_or(u32::MAX)),
+        Err(err) => {
+            warn!(
+                machine = %machine.id(),
+                "[{}] Failed to retrieve the list of runners: {}",
+                machine.id(), err
+            );
+            None
+        }
+    }
+}
+
+/// Returns the index of the least loaded machine, given the number of the running runners and
+/// `runners.max` of each machine, or `None` for an unreachable machine. The first one wins a tie.
+pub fn select_least_loaded(loads: &[Option<(u32, u32)>]) -> Result<usize, PoolError> {
+    if loads.iter().all(Option::is_none) {
+        return Err(PoolError::AllMachinesUnreachable);
+    }
+
+    let mut selected: Option<(usize, u32)> = None;
+    for (index, load) in loads.iter().enumerate() {
+        let Some((running, max)) = *load else {
+            continue;
+        };
+        if running >= max {
+            continue;
+        }
+        if selected.is_none_or(|(_, selected_running)| running < selected_running) {
+            selected = Some((index, running));
+        }
+    }
+    selected
+        .map(|(index, _)| index)
+        .ok_or(PoolError::AllMachinesAtCapacity)
+}
+
+/// An error raised when [`MachinePool`] can't select a machine.
+#[derive(Debug, PartialEq)]
+pub enum PoolError {
+    /// No machine has all the labels required by a job.
+    NoMachineWithLabels,
+    /// Every reachable machine runs as many runners as its `runners.max`.
+    AllMachinesAtCapacity,
+    /// The runners of no machine could be retrieved.
+    AllMachinesUnreachable,
+}
+
+impl Display for PoolError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::NoMachineWithLabels => {
+                write!(f, "No machine has all the required labels.")
+            }
+            PoolError::AllMachinesAtCapacity => {
+                write!(
+                    f,
+                    "All machines are running as many runners as 'runners.max'."
+                )
+            }
+            PoolError::AllMachinesUnreachable => {
+                write!(f, "Failed to retrieve the runners of all machines.")
+            }
+        }
+    }
+}
+
+impl Error for PoolError {}
+
+/// Selects the machine for a new assignment.
+pub trait MachineSelector {
+    /// Selects one of the specified machines, which is never empty, and counts it as
+    /// a new assignment. Fails if none of them can take a new assignment.
+    fn select<'a>(&mut self, machines: &'a [Machine]) -> Result<&'a Machine, PoolError>;
+}
+
+/// Selects the machine with the lowest `assignments / weight` ratio.
+#[derive(Default)]
+pub struct RoundRobinSelector {
+    assignments: HashMap<String, u64>,
+}
+
+impl RoundRobinSelector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MachineSelector for RoundRobinSelector {
+    fn select<'a>(&mut self, machines: &'a [Machine]) -> Result<&'a Machine, PoolError> {
+        let assignments = |machine: &Machine| *self.assignments.get(machine.id()).unwrap_or(&0);
+        // Compare `a1 / w1 < a2 / w2` as `a1 * w2 < a2 * w1` to avoid floating-point arithmetic.
+        let mut selected = &machines[0];
+        for machine in &machines[1..] {
+            if assignments(machine) * u64::from(selected.weight())
+                < assignments(selected) * u64::from(machine.weight())
+            {
+                selected = machine;
+            }
+        }
+
+        *self
+            .assignments
+            .entry(selected.id().to_string())
+            .or_default() += 1;
+        Ok(selected)
+    }
+}
+
+/// Selects a random machine with a probability proportional to its weight.
+pub struct WeightedRandomSelector {
+    rng: StdRng,
+}
+
+impl WeightedRandomSelector {
+    /// Creates a new instance. Specify a seeded random number generator for reproducible
+    /// selections.
+    pub fn new(rng: StdRng) -> Self {
+        WeightedRandomSelector { rng }
+    }
+}
+
+impl MachineSelector for WeightedRandomSelector {
+    fn select<'a>(&mut self, machines: &'a [Machine]) -> Result<&'a Machine, PoolError> {
+        let total_weight: u64 = machines.iter().map(|m| u64::from(m.weight())).sum();
+        let mut point = self.rng.random_range(0..total_weight);
+        for machine in machines {
+            let weight = u64::from(machine.weight());
+            if point < weight {
+                return Ok(machine);
+            }
+            point -= weight;
+        }
+        unreachable!("the random point must be less than the total weight")
+    }
+}
+
+/// Selects the machine with the fewest running runners among the ones below their `runners.max`,
+/// counting the assignments made by this selector as running runners. The runners of the machines
+/// are retrieved concurrently when they're selected from for the first time. A machine that
+/// doesn't respond to a ping is skipped. A tie is broken by the order of the machines.
+#[derive(Default)]
+pub struct LeastLoadedSelector {
+    /// The number of the running runners of each machine including the assignments,
+    /// or `None` if unreachable.
+    loads: HashMap<String, Option<u32>>,
+}
+
+impl LeastLoadedSelector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Retrieves the number of the running runners of the machines whose load is unknown yet.
+    fn retrieve_loads(&mut self, machines: &[Machine]) {
+        let unknown_machines: Vec<&Machine> = machines
+            .iter()
+            .filter(|machine| !self.loads.contains_key(machine.id()))
+            .collect();
+        let running_counts: Vec<Option<u32>> = thread::scope(|scope| {
+            let handles: Vec<_> = unknown_machines
+                .iter()
+                .map(|machine| scope.spawn(move || running_count(machine)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or(None))
+                .collect()
+        });
+        for (machine, count) in unknown_machines.into_iter().zip(running_counts) {
+            self.loads.insert(machine.id().to_string(), count);
+        }
+    }
+}
+
+impl MachineSelector for LeastLoadedSelector {
+    fn select<'a>(&mut self, machines: &'a [Machine]) -> Result<&'a Machine, PoolError> {
+        self.retrieve_loads(machines);
+        let loads: Vec<Option<(u32, u32)>> = machines
+            .iter()
+            .map(|machine| {
+                self.loads[machine.id()].map(|load| (load, machine.config().runners.max))
+            })
+            .collect();
+        let index = select_least_loaded(&loads)?;
+        let selected = &machines[index];
+        debug!(
+            machine = %selected.id(),
+            "[{}] Selected the least loaded machine with {} running or assigned runner(s)",
+            selected.id(),
+            loads[index].unwrap().0
+        );
+
+        if let Some(Some(load)) = self.loads.get_mut(selected.id()) {
+            *load = load.saturating_add(1);
+        }
+        Ok(selected)
+    }
+}
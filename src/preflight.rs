@@ -0,0 +1,144 @@
+use crate::config::{DockerConfig, DEFAULT_CRITICAL_FREE_DISK_BYTES, DEFAULT_MIN_FREE_DISK_BYTES};
+use crate::machine::{should_pull_image, DiskUsage, Machine, MemoryInfo, MIN_DOCKER_VERSION};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// The available memory below which a warning is reported.
+const MIN_MEMORY_AVAILABLE_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// The headroom over the memory limits of the running runners below which a warning is reported.
+const MEMORY_HEADROOM_FACTOR: f64 = 1.2;
+
+/// The state of a machine checked by [`MachinePreflight`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PreflightReport {
+    pub docker_version: (u32, u32, u32),
+    /// The disk space of `/var/lib/docker`, or `None` if unknown
+    pub disk_usage: Option<DiskUsage>,
+    /// The memory of the machine, or `None` if unknown
+    pub memory: Option<MemoryInfo>,
+    /// The number of the running runners, or `None` if unknown
+    pub running_runners: Option<u32>,
+    /// Whether the runner image exists on the machine
+    pub image_cached: bool,
+    /// The issues that don't prevent the machine from running the runners
+    pub warnings: Vec<PreflightIssue>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PreflightIssue {
+    /// An issue that is worth attention but allows scaling.
+    Warning(String),
+    /// An issue that prevents the machine from running the runners.
+    Error(String),
+}
+
+impl Display for PreflightIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PreflightIssue::Warning(message) => write!(f, "warning: {}", message),
+            PreflightIssue::Error(message) => write!(f, "error: {}", message),
+        }
+    }
+}
+
+/// Checks whether a machine is ready to run the runners before scaling begins.
+pub struct MachinePreflight;
+
+impl MachinePreflight {
+    /// Checks the specified machine, returning the issues if there's any error.
+    pub fn check(machine: &Machine) -> Result<PreflightReport, Vec<PreflightIssue>> {
+        let report = machine
+            .fetch_preflight_report()
+            .map_err(|err| vec![PreflightIssue::Error(err.to_string())])?;
+        Self::evaluate(&machine.config().docker, report)
+    }
+
+    /// Finds the issues in the specified report against the specified Docker configuration,
+    /// i.e. `pull_policy`, `min_free_disk_bytes` and `critical_free_disk_bytes`. Returns the
+    /// report with the warnings if there's no error, or all issues otherwise.
+    pub fn evaluate(
+        docker: &DockerConfig,
+        mut report: PreflightReport,
+    ) -> Result<PreflightReport, Vec<PreflightIssue>> {
+        let mut issues: Vec<PreflightIssue> = vec![];
+
+        let version = report.docker_version;
+        if version < MIN_DOCKER_VERSION {
+            issues.push(PreflightIssue::Error(format!(
+                "Docker {}.{}.{} is too old; {}.{} or later is required",
+                version.0, version.1, version.2, MIN_DOCKER_VERSION.0, MIN_DOCKER_VERSION.1
+            )));
+        }
+
+        let min_free_disk_bytes = docker
+            .min_free_disk_bytes
+            .unwrap_or(DEFAULT_MIN_FREE_DISK_BYTES);
+        let critical_free_disk_bytes = docker
+            .critical_free_disk_bytes
+            .unwrap_or(DEFAULT_CRITICAL_FREE_DISK_BYTES);
+        match report.disk_usage.map(|usage| usage.available_bytes) {
+            Some(available) if available < critical_free_disk_bytes => {
+                issues.push(PreflightIssue::Error(format!(
+                    "Only {} bytes of disk space is available in /var/lib/docker; at least {} bytes are required",
+                    available, critical_free_disk_bytes
+                )));
+            }
+            Some(available) if available < min_free_disk_bytes => {
+                issues.push(PreflightIssue::Warning(format!(
+                    "Only {} bytes of disk space is available in /var/lib/docker",
+                    available
+                )));
+            }
+            Some(_) => {}
+            None => issues.push(PreflightIssue::Warning(
+                "Failed to determine the available disk space".to_string(),
+            )),
+        }
+
+        match report.memory.map(|memory| memory.available_bytes) {
+            Some(available) if available < MIN_MEMORY_AVAILABLE_BYTES => {
+                issues.push(PreflightIssue::Warning(format!(
+                    "Only {} bytes of memory is available",
+                    available
+                )));
+            }
+            Some(_) => {}
+            None => issues.push(PreflightIssue::Warning(
+                "Failed to determine the available memory".to_string(),
+            )),
+        }
+
+        // The running runners could grow up to their memory limits.
+        if let (Some(memory), Some(running_runners), Some(memory_limit_bytes)) = (
+            report.memory,
+            report.running_runners,
+            docker.memory_limit_bytes,
+        ) {
+            let required =
+                memory_limit_bytes as f64 * f64::from(running_runners) * MEMORY_HEADROOM_FACTOR;
+            if (memory.available_bytes as f64) < required {
+                issues.push(PreflightIssue::Warning(format!(
+                    "The machine is near its memory capacity; only {} bytes of memory is available for {} running runner(s) limited to {} bytes each",
+                    memory.available_bytes, running_runners, memory_limit_bytes
+                )));
+            }
+        }
+
+        if let Err(err) =
+            should_pull_image(docker.pull_policy.unwrap_or_default(), report.image_cached)
+        {
+            issues.push(PreflightIssue::Error(err));
+        }
+
+        if issues
+            .iter()
+            .any(|issue| matches!(issue, PreflightIssue::Error(_)))
+        {
+            Err(issues)
+        } else {
+            report.warnings = issues;
+            Ok(report)
+        }
+    }
+}
@@ -0,0 +1,39 @@
+#[cfg(test)]
+mod render_grid_tests {
+    use gh_actions_scaler::display::{render_grid, Cell};
+    use speculoos::prelude::*;
+    use termcolor::Color;
+
+    fn rows() -> Vec<Vec<Cell>> {
+        vec![
+            vec![
+                ("machine-alpha".to_string(), None),
+                ("ok".to_string(), Some(Color::Green)),
+            ],
+            vec![
+                ("machine-beta".to_string(), None),
+                ("err".to_string(), Some(Color::Red)),
+            ],
+        ]
+    }
+
+    #[test]
+    fn plain() {
+        let output = render_grid(&["ID", "SSH"], &rows(), false);
+        assert_that!(output.as_str()).is_equal_to(concat!(
+            "┌───────────────┬─────┐\n",
+            "│ ID            │ SSH │\n",
+            "├───────────────┼─────┤\n",
+            "│ machine-alpha │ ok  │\n",
+            "│ machine-beta  │ err │\n",
+            "└───────────────┴─────┘\n",
+        ));
+    }
+
+    #[test]
+    fn colored() {
+        let output = render_grid(&["ID", "SSH"], &rows(), true);
+        assert_that!(output.as_str()).contains("\x1b[32mok\x1b[0m");
+        assert_that!(output.as_str()).contains("\x1b[31merr\x1b[0m");
+    }
+}
@@ -0,0 +1,723 @@
+#[cfg(test)]
+mod github_client_tests {
+    use gh_actions_scaler::config::{Config, GithubConfig, GithubRunnerConfig, RetryConfig};
+    use gh_actions_scaler::github::{GithubClient, JobInfo, WorkflowRun};
+    use gh_actions_scaler::machine::{Machine, ScalingAction};
+    use speculoos::prelude::*;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    /// A minimal HTTP server that serves the responses returned by `handler`
+    /// for the requested path and query, e.g. `/repos/foo/bar/actions/runs?page=2`.
+    /// The connections are kept alive, so that a client can reuse them.
+    struct MockServer {
+        base_url: String,
+        connections: Arc<AtomicUsize>,
+        /// The method and path of the received requests in order, e.g. `GET /repos/foo/bar`
+        requests: Arc<Mutex<Vec<String>>>,
+        /// The header lines of the received requests in order, e.g. `Accept: */*`
+        headers: Arc<Mutex<Vec<String>>>,
+    }
+
+    struct MockResponse {
+        status: u16,
+        body: String,
+        link: Option<String>,
+    }
+
+    impl MockServer {
+        fn start<F>(handler: F) -> MockServer
+        where
+            F: Fn(&str, &str) -> MockResponse + Send + Sync + 'static,
+        {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let base_url = format!("http://{}", listener.local_addr().unwrap());
+            let connections = Arc::new(AtomicUsize::new(0));
+            let requests = Arc::new(Mutex::new(vec![]));
+            let headers = Arc::new(Mutex::new(vec![]));
+            let handler = Arc::new(handler);
+            let handler_base_url = base_url.clone();
+            let handler_connections = connections.clone();
+            let handler_requests = requests.clone();
+            let handler_headers = headers.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    handler_connections.fetch_add(1, Ordering::SeqCst);
+                    let stream = stream.unwrap();
+                    let handler = handler.clone();
+                    let base_url = handler_base_url.clone();
+                    let requests = handler_requests.clone();
+                    let headers = handler_headers.clone();
+                    thread::spawn(move || {
+                        serve(stream, &base_url, &requests, &headers, handler.as_ref())
+                    });
+                }
+            });
+
+            MockServer {
+                base_url,
+                connections,
+                requests,
+                headers,
+            }
+        }
+    }
+
+    fn serve<F>(
+        mut stream: TcpStream,
+        base_url: &str,
+        requests: &Mutex<Vec<String>>,
+        headers: &Mutex<Vec<String>>,
+        handler: &F,
+    ) where
+        F: Fn(&str, &str) -> MockResponse,
+    {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        loop {
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                // The client closed the connection.
+                return;
+            }
+            // Consume the headers.
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                headers.lock().unwrap().push(line.trim().to_string());
+            }
+
+            let mut parts = request_line.split(' ');
+            let method = parts.next().unwrap();
+            let path = parts.next().unwrap();
+            requests
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", method, path));
+            let res = handler(base_url, path);
+            let mut headers = format!(
+                "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\n",
+                res.status,
+                res.body.len()
+            );
+            if let Some(link) = res.link {
+                headers.push_str(&format!("Link: {}\r\n", link));
+            }
+            headers.push_str("\r\n");
+            // Write at once to avoid the delay caused by Nagle's algorithm.
+            stream
+                .write_all(format!("{}{}", headers, res.body).as_bytes())
+                .unwrap();
+        }
+    }
+
+    fn new_client(server: &MockServer, max_runs: Option<usize>) -> GithubClient {
+        GithubClient::new(&new_client_config(server, max_runs))
+    }
+
+    fn new_client_config(server: &MockServer, max_runs: Option<usize>) -> GithubConfig {
+        GithubConfig {
+            personal_access_token: "ghp_my_secret_token".to_string(),
+            runners: GithubRunnerConfig {
+                repo_url: "https://github.com/trustin/gh-actions-scaler".to_string(),
+                api_endpoint_url: server.base_url.clone(),
+                repo_user: "trustin".to_string(),
+                repo_name: "gh-actions-scaler".to_string(),
+                ..GithubRunnerConfig::default()
+            },
+            max_runs,
+            retry: RetryConfig::default(),
+            api_version: "2022-11-28".to_string(),
+            timeout_secs: 10,
+            connect_timeout_secs: 5,
+        }
+    }
+
+    fn new_org_client(server: &MockServer, max_runs: Option<usize>) -> GithubClient {
+        GithubClient::new(&GithubConfig {
+            personal_access_token: "ghp_my_secret_token".to_string(),
+            runners: GithubRunnerConfig {
+                scope: "org".to_string(),
+                org_name: "acme".to_string(),
+                api_endpoint_url: server.base_url.clone(),
+                ..GithubRunnerConfig::default()
+            },
+            max_runs,
+            retry: RetryConfig::default(),
+            api_version: "2022-11-28".to_string(),
+            timeout_secs: 10,
+            connect_timeout_secs: 5,
+        })
+    }
+
+    /// Serves the repositories of the 'acme' organization and a queued run in each of them.
+    fn org_runs(base_url: &str, path: &str) -> MockResponse {
+        if path.starts_with("/orgs/acme/repos") {
+            return MockResponse {
+                status: 200,
+                body: fs::read_to_string("tests/fixtures/github/org_repos.json").unwrap(),
+                link: None,
+            };
+        }
+        if path.contains("/jobs") {
+            return MockResponse {
+                status: 200,
+                body: r#"{"jobs": [{"id": 1, "status": "queued", "labels": ["self-hosted"]}]}"#
+                    .to_string(),
+                link: None,
+            };
+        }
+
+        assert_that!(path).ends_with("/actions/runs?status=queued&per_page=100");
+        let repo = path
+            .trim_start_matches("/repos/")
+            .split("/actions/")
+            .next()
+            .unwrap();
+        let run_id = if repo == "acme/foo" { 1 } else { 2 };
+        MockResponse {
+            status: 200,
+            body: format!(
+                r#"{{"workflow_runs": [{{"id": {}, "url": "{}/repos/{}/actions/runs/{}"}}]}}"#,
+                run_id, base_url, repo, run_id
+            ),
+            link: None,
+        }
+    }
+
+    #[test]
+    fn org_workflow_runs() {
+        let server = MockServer::start(org_runs);
+        let runs = new_org_client(&server, None)
+            .fetch_queued_workflow_runs()
+            .unwrap();
+
+        // The archived repository is skipped.
+        let urls: Vec<String> = runs.iter().map(|run| run.url.clone()).collect();
+        assert_that!(urls).is_equal_to(vec![
+            format!("{}/repos/acme/foo/actions/runs/1", server.base_url),
+            format!("{}/repos/acme/bar/actions/runs/2", server.base_url),
+        ]);
+        assert_that!(runs[1].required_labels).is_equal_to(vec!["self-hosted".to_string()]);
+    }
+
+    #[test]
+    fn org_max_runs() {
+        let server = MockServer::start(org_runs);
+        let runs = new_org_client(&server, Some(1))
+            .fetch_queued_workflow_runs()
+            .unwrap();
+
+        assert_that!(runs).has_length(1);
+        assert_that!(runs[0].run_id).is_equal_to(1);
+    }
+
+    #[test]
+    fn org_runner_registration_token() {
+        let server = MockServer::start(|_, path| {
+            assert_that!(path).is_equal_to("/orgs/acme/actions/runners/registration-token");
+            MockResponse {
+                status: 200,
+                body: r#"{"token": "LLBF3JGZDX3P5PMEXLND6TS6FCWO6", "expires_at": "2020-01-22T12:13:35.123-08:00"}"#
+                    .to_string(),
+                link: None,
+            }
+        });
+        let (token, _) = new_org_client(&server, None)
+            .get_runner_registration_token()
+            .unwrap();
+
+        assert_that!(token.as_str()).is_equal_to("LLBF3JGZDX3P5PMEXLND6TS6FCWO6");
+    }
+
+    /// Serves 150 queued runs in two pages, 100 runs in the first and 50 runs in the second.
+    fn paginated_runs(base_url: &str, path: &str) -> MockResponse {
+        if path.contains("/jobs") {
+            return MockResponse {
+                status: 200,
+                body: r#"{"jobs": [{"id": 1, "status": "queued", "labels": ["self-hosted"]}]}"#
+                    .to_string(),
+                link: None,
+            };
+        }
+
+        let runs_url = format!("{}/repos/trustin/gh-actions-scaler/actions/runs", base_url);
+        let (range, link) = if path.contains("page=2") {
+            (
+                100..150,
+                Some(format!(r#"<{}?page=1>; rel="prev""#, runs_url)),
+            )
+        } else {
+            (
+                0..100,
+                Some(format!(
+                    r#"<{}?status=queued&per_page=100&page=2>; rel="next", <{}?status=queued&per_page=100&page=2>; rel="last""#,
+                    runs_url, runs_url
+                )),
+            )
+        };
+
+        let runs: Vec<String> = range
+            .map(|i| {
+                format!(
+                    r#"{{"id": {}, "url": "{}/{}", "jobs_url": "{}/jobs/{}"}}"#,
+                    i, runs_url, i, base_url, i
+                )
+            })
+            .collect();
+        MockResponse {
+            status: 200,
+            body: format!(r#"{{"workflow_runs": [{}]}}"#, runs.join(",")),
+            link,
+        }
+    }
+
+    #[test]
+    fn paginated_workflow_runs() {
+        let server = MockServer::start(paginated_runs);
+        let runs = new_client(&server, None)
+            .fetch_queued_workflow_runs()
+            .unwrap();
+
+        assert_that!(runs).has_length(150);
+        assert_that!(runs[0].url.as_str()).ends_with("/actions/runs/0");
+        assert_that!(runs[149].url.as_str()).ends_with("/actions/runs/149");
+        assert_that!(runs[149].required_labels).is_equal_to(vec!["self-hosted".to_string()]);
+    }
+
+    #[test]
+    fn max_runs() {
+        let server = MockServer::start(paginated_runs);
+        let runs = new_client(&server, Some(120))
+            .fetch_queued_workflow_runs()
+            .unwrap();
+
+        assert_that!(runs).has_length(120);
+        assert_that!(runs[119].url.as_str()).ends_with("/actions/runs/119");
+    }
+
+    #[test]
+    fn enriched_workflow_run() {
+        let server = MockServer::start(|base_url, path| {
+            let fixture = if path.contains("/jobs") {
+                "tests/fixtures/github/workflow_run_jobs.json"
+            } else {
+                "tests/fixtures/github/workflow_runs.json"
+            };
+            MockResponse {
+                status: 200,
+                body: fs::read_to_string(fixture)
+                    .unwrap()
+                    .replace("{base_url}", base_url),
+                link: None,
+            }
+        });
+        let runs = new_client(&server, None)
+            .fetch_queued_workflow_runs()
+            .unwrap();
+
+        assert_that!(runs).is_equal_to(vec![WorkflowRun {
+            run_id: 11029873211,
+            url: format!(
+                "{}/repos/trustin/gh-actions-scaler/actions/runs/11029873211",
+                server.base_url
+            ),
+            head_sha: "6dcb09b5b57875f334f61aebed695e2e4193db5e".to_string(),
+            event: "push".to_string(),
+            workflow_name: "CI".to_string(),
+            status: "queued".to_string(),
+            conclusion: None,
+            required_labels: vec![
+                "self-hosted".to_string(),
+                "linux".to_string(),
+                "gpu".to_string(),
+            ],
+            queued_jobs: vec![JobInfo {
+                job_id: 30645523519,
+                name: "build".to_string(),
+                labels: vec![
+                    "self-hosted".to_string(),
+                    "linux".to_string(),
+                    "gpu".to_string(),
+                ],
+                status: "queued".to_string(),
+            }],
+        }]);
+    }
+
+    #[test]
+    fn workflow_run_jobs() {
+        let server = MockServer::start(|base_url, path| {
+            assert_that!(path).starts_with(
+                "/repos/trustin/gh-actions-scaler/actions/runs/11029873211/jobs?filter=latest",
+            );
+            MockResponse {
+                status: 200,
+                body: fs::read_to_string("tests/fixtures/github/workflow_run_jobs.json")
+                    .unwrap()
+                    .replace("{base_url}", base_url),
+                link: None,
+            }
+        });
+        let jobs = new_client(&server, None)
+            .fetch_workflow_run_jobs("trustin/gh-actions-scaler", 11029873211)
+            .unwrap();
+
+        assert_that!(jobs).has_length(2);
+        assert_that!(jobs[1]).is_equal_to(JobInfo {
+            job_id: 30645523520,
+            name: "lint".to_string(),
+            labels: vec!["ubuntu-latest".to_string()],
+            status: "completed".to_string(),
+        });
+    }
+
+    #[test]
+    fn runner_registration_token() {
+        let server = MockServer::start(|_, path| {
+            assert_that!(path)
+                .is_equal_to("/repos/trustin/gh-actions-scaler/actions/runners/registration-token");
+            MockResponse {
+                status: 200,
+                body: r#"{"token": "LLBF3JGZDX3P5PMEXLND6TS6FCWO6", "expires_at": "2020-01-22T12:13:35.123-08:00"}"#
+                    .to_string(),
+                link: None,
+            }
+        });
+        let (token, expires_at) = new_client(&server, None)
+            .get_runner_registration_token()
+            .unwrap();
+
+        assert_that!(token.as_str()).is_equal_to("LLBF3JGZDX3P5PMEXLND6TS6FCWO6");
+        assert_that!(expires_at.to_rfc3339())
+            .is_equal_to("2020-01-22T20:13:35.123+00:00".to_string());
+    }
+
+    #[test]
+    fn self_hosted_runner() {
+        let server = MockServer::start(|_, path| {
+            assert_that!(path).is_equal_to("/repos/trustin/gh-actions-scaler/actions/runners/42");
+            MockResponse {
+                status: 200,
+                body: r#"{"id": 42, "name": "alpha-1", "status": "offline", "busy": false}"#
+                    .to_string(),
+                link: None,
+            }
+        });
+        let runner = new_client(&server, None)
+            .fetch_self_hosted_runner(42)
+            .unwrap()
+            .unwrap();
+
+        assert_that!(runner.id).is_equal_to(42);
+        assert_that!(runner.name.as_str()).is_equal_to("alpha-1");
+        assert_that!(runner.status.as_str()).is_equal_to("offline");
+        assert_that!(runner.busy).is_false();
+    }
+
+    #[test]
+    fn api_version() {
+        let server = flaky_runner(0);
+        let mut config = new_client_config(&server, None);
+        config.api_version = "2026-03-10".to_string();
+        GithubClient::new(&config)
+            .fetch_self_hosted_runner(42)
+            .unwrap();
+
+        let headers = server.headers.lock().unwrap();
+        assert_that!(headers
+            .iter()
+            .any(|header| header.eq_ignore_ascii_case("X-GitHub-Api-Version: 2026-03-10")))
+        .is_true();
+    }
+
+    /// Fails the first `failures` requests with `503 Service Unavailable`.
+    fn flaky_runner(failures: usize) -> MockServer {
+        let count = AtomicUsize::new(0);
+        MockServer::start(move |_, _| {
+            if count.fetch_add(1, Ordering::SeqCst) < failures {
+                MockResponse {
+                    status: 503,
+                    body: r#"{"message": "Service Unavailable"}"#.to_string(),
+                    link: None,
+                }
+            } else {
+                MockResponse {
+                    status: 200,
+                    body: r#"{"id": 42, "name": "alpha-1", "status": "online", "busy": false}"#
+                        .to_string(),
+                    link: None,
+                }
+            }
+        })
+    }
+
+    fn new_retrying_client(server: &MockServer, max_retries: u32) -> GithubClient {
+        let mut config = new_client_config(server, None);
+        config.retry = RetryConfig {
+            max_retries,
+            base_delay_ms: 1,
+            max_delay_ms: 10,
+        };
+        GithubClient::new(&config)
+    }
+
+    #[test]
+    fn retry_transient_failures() {
+        let server = flaky_runner(2);
+        let runner = new_retrying_client(&server, 3)
+            .fetch_self_hosted_runner(42)
+            .unwrap()
+            .unwrap();
+
+        assert_that!(runner.id).is_equal_to(42);
+        assert_that!(server.requests.lock().unwrap().len()).is_equal_to(3);
+    }
+
+    #[test]
+    fn retry_exhausted() {
+        let server = flaky_runner(usize::MAX);
+        let err = new_retrying_client(&server, 2)
+            .fetch_self_hosted_runner(42)
+            .unwrap_err();
+
+        assert_that!(err.to_string()).contains("503");
+        // The first attempt and 2 retries
+        assert_that!(server.requests.lock().unwrap().len()).is_equal_to(3);
+    }
+
+    #[test]
+    fn deregister_offline_runners() {
+        let server = MockServer::start(|_, path| {
+            let body = if path.starts_with("/repos/trustin/gh-actions-scaler/actions/runners?") {
+                r#"{"runners": [
+                    {"id": 1, "name": "runner-4f2b3c1e-0d6a-4b8e-9c3f-2a1d5e7f8b90", "status": "offline"},
+                    {"id": 2, "name": "runner-7c9d8e6f-5a4b-4c3d-8e2f-1a0b9c8d7e6f", "status": "online"},
+                    {"id": 3, "name": "other-0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d", "status": "offline"}
+                ]}"#
+            } else {
+                ""
+            };
+            MockResponse {
+                status: 200,
+                body: body.to_string(),
+                link: None,
+            }
+        });
+        let mut config = Config::try_from("tests/fixtures/config/minimal.yaml").unwrap();
+        config.github.runners.api_endpoint_url = server.base_url.clone();
+        let machine = Machine::new(config.machines[0].clone());
+
+        let deregistered = machine
+            .deregister_offline_runners(&GithubClient::new(&config.github), &config)
+            .unwrap();
+
+        // Only the offline runner with our name prefix is deregistered.
+        assert_that!(deregistered).is_equal_to(vec![1]);
+        assert_that!(*server.requests.lock().unwrap()).is_equal_to(vec![
+            "GET /repos/trustin/gh-actions-scaler/actions/runners?per_page=100".to_string(),
+            "DELETE /repos/trustin/gh-actions-scaler/actions/runners/1".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn deregister_offline_runners_before_start() {
+        let server = MockServer::start(|_, path| {
+            let body = if path.starts_with("/repos/trustin/gh-actions-scaler/actions/runners?") {
+                r#"{"runners": [
+                    {"id": 1, "name": "runner-4f2b3c1e-0d6a-4b8e-9c3f-2a1d5e7f8b90", "status": "offline"}
+                ]}"#
+            } else {
+                ""
+            };
+            MockResponse {
+                status: 200,
+                body: body.to_string(),
+                link: None,
+            }
+        });
+        let mut config = Config::try_from("tests/fixtures/config/minimal.yaml").unwrap();
+        config.github.runners.api_endpoint_url = server.base_url.clone();
+        config.github.runners.deregister_offline = true;
+        let mut machine_config = (*config.machines[0]).clone();
+        // Nothing listens on the port once the listener is dropped.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        machine_config.ssh.host = "127.0.0.1".to_string();
+        machine_config.ssh.port = port;
+        machine_config.ssh.max_retries = Some(0);
+        let machine = Machine::new(Arc::new(machine_config));
+        let run = WorkflowRun {
+            run_id: 42,
+            url: "https://api.github.com/repos/trustin/gh-actions-scaler/actions/runs/42"
+                .to_string(),
+            head_sha: String::new(),
+            event: "push".to_string(),
+            workflow_name: "CI".to_string(),
+            status: "queued".to_string(),
+            conclusion: None,
+            required_labels: vec![],
+            queued_jobs: vec![],
+        };
+
+        let decisions =
+            machine.start_runners(&config, &GithubClient::new(&config.github), &[&run], 1);
+
+        // The offline runner was deregistered before the container run failed on
+        // the unreachable machine.
+        assert_that!(*server.requests.lock().unwrap()).is_equal_to(vec![
+            "GET /repos/trustin/gh-actions-scaler/actions/runners?per_page=100".to_string(),
+            "DELETE /repos/trustin/gh-actions-scaler/actions/runners/1".to_string(),
+        ]);
+        assert_that!(decisions).has_length(1);
+        assert_that!(decisions[0].action).is_equal_to(ScalingAction::Error);
+        assert_that!(decisions[0].reason.as_str()).starts_with("Failed to start a runner");
+        assert_that!(decisions[0].run_url.as_deref()).is_equal_to(Some(run.url.as_str()));
+    }
+
+    #[test]
+    fn cloned_client_shares_connection_pool() {
+        let server = MockServer::start(|_, _| {
+            MockResponse {
+            status: 200,
+            body: r#"{"token": "LLBF3JGZDX3P5PMEXLND6TS6FCWO6", "expires_at": "2020-01-22T12:13:35.123-08:00"}"#
+                .to_string(),
+            link: None,
+        }
+        });
+        let client = new_client(&server, None);
+        let cloned = client.clone();
+
+        client.get_runner_registration_token().unwrap();
+        let handle = thread::spawn(move || cloned.get_runner_registration_token().unwrap());
+        handle.join().unwrap();
+        client.get_runner_registration_token().unwrap();
+
+        assert_that!(server.connections.load(Ordering::SeqCst)).is_equal_to(1);
+    }
+
+    #[test]
+    fn builder() {
+        let server = MockServer::start(|_, _| {
+            MockResponse {
+            status: 200,
+            body: r#"{"token": "LLBF3JGZDX3P5PMEXLND6TS6FCWO6", "expires_at": "2020-01-22T12:13:35.123-08:00"}"#
+                .to_string(),
+            link: None,
+        }
+        });
+        let config = new_client_config(&server, None);
+        // Don't keep any idle connections.
+        let client = GithubClient::builder(&config)
+            .max_idle_connections(0)
+            .timeout(Duration::from_secs(5))
+            .build();
+
+        client.get_runner_registration_token().unwrap();
+        client.get_runner_registration_token().unwrap();
+
+        assert_that!(server.connections.load(Ordering::SeqCst)).is_equal_to(2);
+    }
+
+    #[test]
+    fn timeout() {
+        // Respond later than 'github.timeout_secs'.
+        let server = MockServer::start(|_, _| {
+            thread::sleep(Duration::from_secs(3));
+            MockResponse {
+                status: 200,
+                body: "{}".to_string(),
+                link: None,
+            }
+        });
+        let mut config = new_client_config(&server, None);
+        config.timeout_secs = 1;
+        config.retry.max_retries = 0;
+
+        let start_time = std::time::Instant::now();
+        assert_that!(GithubClient::new(&config).fetch_self_hosted_runner(42)).is_err();
+        assert_that!(start_time.elapsed()).is_less_than(Duration::from_secs(3));
+    }
+}
+
+#[cfg(test)]
+mod workflow_run_tests {
+    use gh_actions_scaler::github::{JobInfo, WorkflowRun};
+    use speculoos::prelude::*;
+
+    fn run(jobs: &[&[&str]]) -> WorkflowRun {
+        let queued_jobs: Vec<JobInfo> = jobs
+            .iter()
+            .enumerate()
+            .map(|(i, labels)| JobInfo {
+                job_id: i as u64,
+                name: format!("job-{}", i),
+                labels: labels.iter().map(|l| l.to_string()).collect(),
+                status: "queued".to_string(),
+            })
+            .collect();
+        let mut required_labels: Vec<String> = vec![];
+        for label in queued_jobs.iter().flat_map(|job| &job.labels) {
+            if !required_labels
+                .iter()
+                .any(|l| l.eq_ignore_ascii_case(label))
+            {
+                required_labels.push(label.clone());
+            }
+        }
+        WorkflowRun {
+            run_id: 1,
+            url: "https://api.github.com/repos/foo/bar/actions/runs/1".to_string(),
+            head_sha: String::new(),
+            event: "push".to_string(),
+            workflow_name: "CI".to_string(),
+            status: "queued".to_string(),
+            conclusion: None,
+            required_labels,
+            queued_jobs,
+        }
+    }
+
+    #[test]
+    fn completed() {
+        assert_that!(run(&[]).is_completed()).is_false();
+
+        let cancelled = WorkflowRun {
+            status: "completed".to_string(),
+            conclusion: Some("cancelled".to_string()),
+            ..run(&[])
+        };
+        assert_that!(cancelled.is_completed()).is_true();
+    }
+
+    #[test]
+    fn no_jobs() {
+        assert_that!(run(&[]).runner_labels()).is_equal_to(vec![&[] as &[String]]);
+    }
+
+    #[test]
+    fn same_labels() {
+        let run = run(&[&["self-hosted", "gpu"], &["GPU", "self-hosted"]]);
+        assert_that!(run.runner_labels())
+            .is_equal_to(vec![&["self-hosted".to_string(), "gpu".to_string()][..]]);
+    }
+
+    #[test]
+    fn different_labels() {
+        let run = run(&[&["self-hosted", "gpu"], &["self-hosted"]]);
+        assert_that!(run.runner_labels()).is_equal_to(vec![
+            &["self-hosted".to_string(), "gpu".to_string()][..],
+            &["self-hosted".to_string()][..],
+        ]);
+    }
+}
@@ -1,3 +1,6 @@
+#[macro_use(defer)]
+extern crate scopeguard;
+
 #[cfg(test)]
 mod string_ext_tests {
     use gh_actions_scaler::machine::StringExt;
@@ -13,12 +16,46 @@ mod string_ext_tests {
     #[test_case("'foo'bar'baz'", r#""'foo'bar'baz'""#; "single quotes")]
     #[test_case(r"\foo\bar\baz\", r#""\\foo\\bar\\baz\\""#; "backslashes")]
     #[test_case(r#""foo" \bar\ 'baz'"#, r#""\"foo\" \\bar\\ 'baz'""#; "mixed special characters")]
+    #[test_case("/runner work dir/", r#""/runner work dir/""#; "a path with spaces")]
+    #[test_case("foo\0bar", r"$'foo\x00bar'"; "a null byte")]
+    #[test_case("foo\rbar", r"$'foo\rbar'"; "a carriage return")]
+    #[test_case("foo\nbar", r"$'foo\nbar'"; "a line feed")]
+    #[test_case("foo\x7fbar", r"$'foo\x7fbar'"; "DEL")]
+    #[test_case("foo\x1b[0m\u{85}", r"$'foo\x1b[0m\u0085'"; "other control characters")]
+    #[test_case("'$foo' \\bar\t\"baz\"", r#"$'\'$foo\' \\bar\t"baz"'"#; "control and special characters")]
     fn push_str_escaped(input: &str, expected: &str) {
         let mut actual = String::new();
         actual.push_str_escaped(input);
         assert_that!(actual).is_equal_to(expected.to_string());
     }
 
+    #[test_case("", "$''"; "empty string")]
+    #[test_case("foo\nbar\n", r"$'foo\nbar\n'"; "line feeds")]
+    #[test_case("foo\tbar", r"$'foo\tbar'"; "a tab")]
+    #[test_case("foo\0bar", r"$'foo\x00bar'"; "a null byte")]
+    #[test_case("'foo' \\bar", r"$'\'foo\' \\bar'"; "single quotes and backslashes")]
+    #[test_case("$HOME \"안녕\"", r#"$'$HOME "안녕"'"#; "no escaping needed")]
+    fn push_str_ansi_c_quoted(input: &str, expected: &str) {
+        let mut actual = String::new();
+        actual.push_str_ansi_c_quoted(input);
+        assert_that!(actual).is_equal_to(expected.to_string());
+    }
+
+    #[test]
+    fn push_str_ansi_c_quoted_round_trip() {
+        let input = "'foo'\n\tbar\\baz\r\x1b[0m\u{85}$HOME`id`\n";
+        let mut cmd = "printf '%s' ".to_string();
+        cmd.push_str_ansi_c_quoted(input);
+        // bash expands '\uXXXX' only in a UTF-8 locale.
+        let output = std::process::Command::new("bash")
+            .env("LC_ALL", "C.UTF-8")
+            .arg("-c")
+            .arg(cmd)
+            .output()
+            .unwrap();
+        assert_that!(String::from_utf8(output.stdout).unwrap()).is_equal_to(input.to_string());
+    }
+
     #[test]
     fn push_str_escaped_special_chars() {
         let chars_to_escape = "'|&!;$()[]{}<>#`";
@@ -27,10 +64,1294 @@ mod string_ext_tests {
                 "{}foo{}bar{}",
                 char_to_escape, char_to_escape, char_to_escape
             );
-            let expected = format!("\"{}\"", input);
+            let expected = match char_to_escape {
+                // '$' and '`' must be escaped even in double quotes.
+                '$' | '`' => format!(
+                    "\"{}\"",
+                    input.replace(char_to_escape, &format!("\\{}", char_to_escape))
+                ),
+                _ => format!("\"{}\"", input),
+            };
             let mut actual = String::new();
             actual.push_str_escaped(&input);
             assert_that!(actual).is_equal_to(expected);
         }
     }
 }
+
+#[cfg(test)]
+mod render_runner_name_tests {
+    use gh_actions_scaler::machine::render_runner_name;
+    use maplit::hashmap;
+    use speculoos::assert_that;
+    use test_case::test_case;
+
+    #[test_case("{prefix}-{uuid}", "runner-1234"; "default template")]
+    #[test_case("{machine_id}-{prefix}-{uuid}", "alpha-runner-1234"; "all placeholders")]
+    #[test_case("static-{uuid}-{uuid}", "static-1234-1234"; "repeated placeholders")]
+    #[test_case("{prefix}-{unknown}", "runner-{unknown}"; "unknown placeholder")]
+    #[test_case("no-placeholders", "no-placeholders"; "no placeholders")]
+    fn render(template: &str, expected: &str) {
+        let vars = hashmap! {
+            "prefix" => "runner",
+            "machine_id" => "alpha",
+            "uuid" => "1234",
+        };
+        assert_that!(render_runner_name(template, &vars)).is_equal_to(expected.to_string());
+    }
+}
+
+#[cfg(test)]
+mod render_env_script_tests {
+    use gh_actions_scaler::machine::render_env_script;
+    use maplit::hashmap;
+    use speculoos::prelude::*;
+    use std::process::Command;
+    use test_case::test_case;
+
+    #[test_case("ghp_my_secret_token"; "plain value")]
+    #[test_case("foo\n========\nbar"; "heredoc delimiter")]
+    #[test_case("'\"$HOME`id`\\"; "shell special characters")]
+    #[test_case(""; "empty value")]
+    #[test_case("-----BEGIN KEY-----\n\tfoo\n-----END KEY-----\n"; "trailing line feed")]
+    fn source(value: &str) {
+        let script = render_env_script(&hashmap! { "ACCESS_TOKEN" => value });
+        // Use bash rather than sh, which may not support ANSI-C quoting, e.g. dash.
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(format!("{}printf '%s' \"$ACCESS_TOKEN\"", script))
+            .output()
+            .unwrap();
+        assert_that!(output.status.success()).is_true();
+        assert_that!(String::from_utf8(output.stdout).unwrap()).is_equal_to(value.to_string());
+    }
+}
+
+#[cfg(test)]
+mod render_argv_tests {
+    use gh_actions_scaler::machine::render_argv;
+    use speculoos::prelude::*;
+    use std::process::Command;
+    use test_case::test_case;
+
+    #[test_case(&["echo", "ok"], "echo ok"; "simple arguments")]
+    #[test_case(&["echo", ""], "echo ''"; "an empty argument")]
+    #[test_case(&["echo", "$HOME; rm -rf /"], r#"echo "\$HOME; rm -rf /""#; "special characters")]
+    #[test_case(&["echo", "*", "?", "~"], r#"echo "*" "?" "~""#; "glob and tilde")]
+    fn render(argv: &[&str], expected: &str) {
+        assert_that!(render_argv(argv)).is_equal_to(expected.to_string());
+    }
+
+    #[test]
+    fn round_trip() {
+        let args = [
+            "",
+            "hello world",
+            "$HOME",
+            "`id`",
+            "a;b|c&d",
+            "'quoted' \"double\"",
+            "*",
+            "~",
+            "line\nbreak",
+        ];
+        // Let the shell print every argument it received, terminated by NUL.
+        let mut argv = vec!["printf", "%s\\0"];
+        argv.extend_from_slice(&args);
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(render_argv(&argv))
+            .output()
+            .unwrap();
+        let received: Vec<String> = String::from_utf8(output.stdout)
+            .unwrap()
+            .split_terminator('\0')
+            .map(|arg| arg.to_string())
+            .collect();
+        assert_that!(received)
+            .is_equal_to(args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>());
+    }
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod connect_from_tests {
+    use gh_actions_scaler::machine::connect_from;
+    use speculoos::prelude::*;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpListener};
+    use std::time::Duration;
+
+    #[test]
+    fn binds_to_local_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server_addr = listener.local_addr().unwrap();
+        let stream = connect_from(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            &server_addr,
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        let (_, peer_addr) = listener.accept().unwrap();
+        assert_that!(stream.local_addr().unwrap()).is_equal_to(peer_addr);
+        assert_that!(stream.peer_addr().unwrap()).is_equal_to(server_addr);
+    }
+
+    #[test]
+    fn rejects_different_ip_version() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let result = connect_from(
+            IpAddr::V6(Ipv6Addr::LOCALHOST),
+            &listener.local_addr().unwrap(),
+            Duration::from_secs(5),
+        );
+        assert_that!(result).is_err();
+    }
+}
+
+mod parse_container_id_tests {
+    use gh_actions_scaler::machine::parse_container_id;
+    use speculoos::prelude::*;
+
+    #[test]
+    fn full_id() {
+        let id = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+        let container_id = parse_container_id(&format!("{}\n", id)).unwrap();
+        assert_that!(container_id.len()).is_equal_to(64);
+        assert_that!(container_id.bytes().all(|b| b.is_ascii_hexdigit())).is_true();
+        assert_that!(container_id.as_str()).is_equal_to(id);
+    }
+
+    #[test]
+    fn after_pull_progress() {
+        let id = "fedcba9876543210fedcba9876543210fedcba9876543210fedcba9876543210";
+        let output = format!("Status: Downloaded newer image\n{}", id);
+        assert_that!(parse_container_id(&output)).is_ok_containing(id.to_string());
+    }
+
+    #[test]
+    fn invalid() {
+        assert_that!(parse_container_id("")).is_err();
+        assert_that!(parse_container_id("0123456789ab")).is_err();
+        assert_that!(parse_container_id(&"z".repeat(64))).is_err();
+    }
+}
+
+#[cfg(test)]
+mod render_extra_env_args_tests {
+    use gh_actions_scaler::machine::render_extra_env_args;
+    use maplit::hashmap;
+    use speculoos::assert_that;
+    use std::collections::HashMap;
+
+    #[test]
+    fn empty() {
+        assert_that!(render_extra_env_args(&HashMap::new())).is_equal_to(Vec::<String>::new());
+    }
+
+    #[test]
+    fn sorted() {
+        let extra_env = hashmap! {
+            "TZ".to_string() => "UTC".to_string(),
+            "PRICE".to_string() => "$HOME costs $5 'each'".to_string(),
+        };
+        assert_that!(render_extra_env_args(&extra_env)).is_equal_to(vec![
+            "--env".to_string(),
+            "PRICE=$HOME costs $5 'each'".to_string(),
+            "--env".to_string(),
+            "TZ=UTC".to_string(),
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod render_extra_label_args_tests {
+    use gh_actions_scaler::machine::render_extra_label_args;
+    use maplit::hashmap;
+    use speculoos::assert_that;
+    use std::collections::HashMap;
+
+    #[test]
+    fn empty() {
+        assert_that!(render_extra_label_args(&HashMap::new())).is_equal_to(Vec::<String>::new());
+    }
+
+    #[test]
+    fn sorted() {
+        let extra_labels = hashmap! {
+            "team".to_string() => "infra".to_string(),
+            "com.example.cost-center".to_string() => "1234".to_string(),
+        };
+        assert_that!(render_extra_label_args(&extra_labels)).is_equal_to(vec![
+            "--label".to_string(),
+            "com.example.cost-center=1234".to_string(),
+            "--label".to_string(),
+            "team=infra".to_string(),
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod render_volume_args_tests {
+    use gh_actions_scaler::config::VolumeMount;
+    use gh_actions_scaler::machine::render_volume_args;
+    use speculoos::assert_that;
+
+    #[test]
+    fn render() {
+        let volumes = vec![
+            VolumeMount {
+                host_path: "/var/cache/maven".to_string(),
+                container_path: "/home/runner/.m2".to_string(),
+                read_only: true,
+            },
+            VolumeMount {
+                host_path: "/var/cache/npm cache".to_string(),
+                container_path: "/home/runner/.npm".to_string(),
+                read_only: false,
+            },
+        ];
+        assert_that!(render_volume_args(&volumes)).is_equal_to(vec![
+            "--volume".to_string(),
+            "/var/cache/maven:/home/runner/.m2:ro".to_string(),
+            "--volume".to_string(),
+            "/var/cache/npm cache:/home/runner/.npm".to_string(),
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod render_update_argv_tests {
+    use gh_actions_scaler::machine::{render_argv, render_update_argv, DockerLimits};
+    use speculoos::assert_that;
+    use test_case::test_case;
+
+    #[test_case(Some(1.5), Some(536870912), "docker container update --cpus 1.5 --memory 536870912 0123456789ab"; "all limits")]
+    #[test_case(Some(2.0), None, "docker container update --cpus 2 0123456789ab"; "cpus only")]
+    #[test_case(None, Some(1073741824), "docker container update --memory 1073741824 0123456789ab"; "memory only")]
+    fn render(cpus: Option<f32>, memory_bytes: Option<u64>, expected: &str) {
+        let limits = DockerLimits { cpus, memory_bytes };
+        assert_that!(render_argv(&render_update_argv("0123456789ab", &limits)))
+            .is_equal_to(expected.to_string());
+    }
+
+    #[test]
+    fn display() {
+        let limits = DockerLimits {
+            cpus: Some(0.5),
+            memory_bytes: None,
+        };
+        assert_that!(limits.to_string()).is_equal_to("cpus=0.5, memory=unchanged".to_string());
+    }
+}
+
+#[cfg(test)]
+mod render_image_prune_argv_tests {
+    use gh_actions_scaler::machine::{render_argv, render_image_prune_argv};
+    use speculoos::assert_that;
+    use test_case::test_case;
+
+    #[test_case(48, "docker image prune --force --filter until=48h"; "prune_older_than_hours")]
+    #[test_case(7 * 24, "docker image prune --force --filter until=168h"; "prune_keep_days")]
+    fn render(hours: u64, expected: &str) {
+        assert_that!(render_argv(&render_image_prune_argv(hours)))
+            .is_equal_to(expected.to_string());
+    }
+}
+
+#[cfg(test)]
+mod should_pull_image_tests {
+    use gh_actions_scaler::config::PullPolicy;
+    use gh_actions_scaler::machine::{missing_image_message, should_pull_image};
+    use speculoos::prelude::*;
+    use test_case::test_case;
+
+    #[test_case(PullPolicy::Always, true, true; "always with image")]
+    #[test_case(PullPolicy::Always, false, true; "always without image")]
+    #[test_case(PullPolicy::IfNotPresent, true, false; "if-not-present with image")]
+    #[test_case(PullPolicy::IfNotPresent, false, true; "if-not-present without image")]
+    #[test_case(PullPolicy::Never, true, false; "never with image")]
+    fn should_pull(policy: PullPolicy, image_present: bool, expected: bool) {
+        assert_that!(should_pull_image(policy, image_present)).is_equal_to(Ok(expected));
+    }
+
+    #[test]
+    fn never_without_image() {
+        assert_that!(should_pull_image(PullPolicy::Never, false))
+            .is_err()
+            .contains("pull_policy");
+    }
+
+    #[test]
+    fn missing_image() {
+        let message = missing_image_message("foo/bar:1.0", PullPolicy::Never);
+        assert_that!(message.as_str()).contains("'foo/bar:1.0' is missing");
+        assert_that!(message.as_str()).contains("'docker.pull_policy' is 'never'");
+        assert_that!(message.as_str()).contains("docker image pull foo/bar:1.0");
+    }
+}
+
+#[cfg(test)]
+mod image_pull_steps_tests {
+    use gh_actions_scaler::config::Config;
+    use gh_actions_scaler::machine::{image_pull_steps, ImagePullStep};
+    use speculoos::prelude::*;
+
+    #[test]
+    fn prune_before_pull() {
+        let config = Config::try_from("tests/fixtures/config/docker_prune.yaml").unwrap();
+        // 'prune_keep_days' is 3.
+        let docker = &config.machines[0].docker;
+        assert_that!(image_pull_steps(docker, false)).is_equal_to(vec![
+            ImagePullStep::Prune { hours: 72 },
+            ImagePullStep::Pull,
+        ]);
+        // Pruned already in this scaling pass.
+        assert_that!(image_pull_steps(docker, true)).is_equal_to(vec![ImagePullStep::Pull]);
+    }
+
+    #[test]
+    fn no_prune() {
+        let config = Config::try_from("tests/fixtures/config/docker_prune.yaml").unwrap();
+        // 'auto_prune_on_start' is false.
+        let docker = &config.machines[1].docker;
+        assert_that!(image_pull_steps(docker, false)).is_equal_to(vec![ImagePullStep::Pull]);
+    }
+}
+
+#[cfg(test)]
+mod parse_docker_version_tests {
+    use gh_actions_scaler::machine::parse_docker_version;
+    use speculoos::assert_that;
+    use test_case::test_case;
+
+    #[test_case("20.10.17", Some((20, 10, 17)); "release")]
+    #[test_case("24.0.7\n", Some((24, 0, 7)); "trailing newline")]
+    #[test_case("20.10.21+dfsg1", Some((20, 10, 21)); "distribution suffix")]
+    #[test_case("25.0.0-rc.1", Some((25, 0, 0)); "pre-release")]
+    #[test_case("19.03", Some((19, 3, 0)); "no patch version")]
+    #[test_case("", None; "empty")]
+    #[test_case("unknown", None; "not a version")]
+    fn parse(version: &str, expected: Option<(u32, u32, u32)>) {
+        assert_that!(parse_docker_version(version)).is_equal_to(expected);
+    }
+}
+
+#[cfg(test)]
+mod parse_reclaimed_space_tests {
+    use gh_actions_scaler::machine::parse_reclaimed_space;
+    use speculoos::assert_that;
+    use test_case::test_case;
+
+    #[test_case("Total reclaimed space: 0B", Some(0); "nothing reclaimed")]
+    #[test_case("Total reclaimed space: 512kB", Some(512_000); "kilobytes")]
+    #[test_case("Total reclaimed space: 1.5GB", Some(1_500_000_000); "fractional gigabytes")]
+    #[test_case(
+        "Deleted Images:\ndeleted: sha256:0123\n\nTotal reclaimed space: 12.34MB",
+        Some(12_340_000);
+        "deleted images"
+    )]
+    #[test_case("Total reclaimed space: 1.5XB", None; "unknown unit")]
+    #[test_case("", None; "empty")]
+    fn parse(output: &str, expected: Option<u64>) {
+        assert_that!(parse_reclaimed_space(output)).is_equal_to(expected);
+    }
+}
+
+#[cfg(test)]
+mod parse_df_usage_tests {
+    use gh_actions_scaler::machine::{parse_df_usage, DiskUsage};
+    use speculoos::assert_that;
+    use test_case::test_case;
+
+    #[test_case(
+        "Filesystem     1024-blocks     Used Available Capacity Mounted on\n/dev/sda1        102400000 51200000  51200000      50% /",
+        Some(DiskUsage { total_bytes: 104_857_600_000, available_bytes: 52_428_800_000 });
+        "posix format"
+    )]
+    #[test_case("Filesystem 1024-blocks Used Available Capacity Mounted on", None; "no filesystem")]
+    #[test_case("Filesystem 1024-blocks Used Available Capacity Mounted on\n/dev/sda1 102400000 -", None; "truncated")]
+    #[test_case("", None; "empty")]
+    fn parse(output: &str, expected: Option<DiskUsage>) {
+        assert_that!(parse_df_usage(output)).is_equal_to(expected);
+    }
+}
+
+#[cfg(test)]
+mod parse_free_memory_tests {
+    use gh_actions_scaler::machine::{parse_free_memory, MemoryInfo};
+    use speculoos::assert_that;
+    use test_case::test_case;
+
+    #[test_case(
+        "               total        used        free      shared  buff/cache   available\n\
+         Mem:     8229157339  2147483648  1073741824    10485760  5000000000  6000000000\n\
+         Swap:    2147483648           0  2147483648",
+        Some(MemoryInfo { total_bytes: 8_229_157_339, available_bytes: 6_000_000_000 });
+        "procps"
+    )]
+    #[test_case(
+        "             total       used       free     shared    buffers     cached\n\
+         Mem:    8229157339 2147483648 1073741824   10485760  100000000 5000000000",
+        None;
+        "no available column"
+    )]
+    #[test_case("", None; "empty")]
+    fn parse(output: &str, expected: Option<MemoryInfo>) {
+        assert_that!(parse_free_memory(output)).is_equal_to(expected);
+    }
+}
+
+#[cfg(test)]
+mod parse_container_stats_tests {
+    use gh_actions_scaler::machine::{parse_container_stats, ContainerStats};
+    use speculoos::prelude::*;
+
+    #[test]
+    fn binary_units() {
+        let line = r#"{"BlockIO":"0B / 0B","CPUPerc":"150.25%","Container":"0123456789ab","ID":"0123456789ab","MemPerc":"19.57%","MemUsage":"1.5GiB / 7.664GiB","Name":"runner-1","NetIO":"1.2kB / 0B","PIDs":"42"}"#;
+        let stats = parse_container_stats(line).unwrap();
+        assert_that!(stats.cpu_percent).is_equal_to(150.25);
+        assert_that!(stats.memory_bytes).is_equal_to(1610612736);
+        assert_that!(stats.memory_limit_bytes).is_equal_to(8229157339);
+    }
+
+    #[test]
+    fn small_usage() {
+        let line = r#"{"CPUPerc":"0.00%","MemUsage":"512KiB / 1MiB"}"#;
+        assert_that!(parse_container_stats(line)).is_ok_containing(ContainerStats {
+            cpu_percent: 0.0,
+            memory_bytes: 524288,
+            memory_limit_bytes: 1048576,
+        });
+    }
+
+    #[test]
+    fn malformed() {
+        assert_that!(parse_container_stats("stats")).is_err();
+        assert_that!(parse_container_stats(
+            r#"{"CPUPerc":"--","MemUsage":"0B / 0B"}"#
+        ))
+        .is_err()
+        .contains("CPUPerc");
+        assert_that!(parse_container_stats(
+            r#"{"CPUPerc":"0.00%","MemUsage":"0B"}"#
+        ))
+        .is_err()
+        .contains("MemUsage");
+    }
+}
+
+#[cfg(test)]
+mod parse_docker_images_tests {
+    use chrono::{DateTime, Utc};
+    use gh_actions_scaler::machine::{parse_docker_images, DockerImage};
+    use speculoos::prelude::*;
+    use test_case::test_case;
+
+    const OUTPUT: &str = r#"
+{"Containers":"N/A","CreatedAt":"2024-05-01 21:34:56 +0900 KST","CreatedSince":"2 weeks ago","Digest":"<none>","ID":"sha256:0123456789abcdef","Repository":"ghcr.io/myoung34/docker-github-actions-runner","SharedSize":"N/A","Size":"1.2GB","Tag":"ubuntu-focal","UniqueSize":"N/A","VirtualSize":"1.2GB"}
+{"Containers":"N/A","CreatedAt":"2024-04-01 00:00:00 +0000 UTC","CreatedSince":"6 weeks ago","Digest":"<none>","ID":"sha256:fedcba9876543210","Repository":"<none>","SharedSize":"N/A","Size":"512kB","Tag":"<none>","UniqueSize":"N/A","VirtualSize":"512kB"}
+"#;
+
+    fn image(repository: &str, tag: &str) -> DockerImage {
+        DockerImage {
+            id: "sha256:0123456789abcdef".to_string(),
+            repository: repository.to_string(),
+            tag: tag.to_string(),
+            size_bytes: 0,
+            created_at: DateTime::<Utc>::default(),
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_that!(parse_docker_images(OUTPUT)).is_ok_containing(vec![
+            DockerImage {
+                id: "sha256:0123456789abcdef".to_string(),
+                repository: "ghcr.io/myoung34/docker-github-actions-runner".to_string(),
+                tag: "ubuntu-focal".to_string(),
+                size_bytes: 1_200_000_000,
+                created_at: "2024-05-01T12:34:56Z".parse().unwrap(),
+            },
+            DockerImage {
+                id: "sha256:fedcba9876543210".to_string(),
+                repository: "<none>".to_string(),
+                tag: "<none>".to_string(),
+                size_bytes: 512_000,
+                created_at: "2024-04-01T00:00:00Z".parse().unwrap(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn empty_or_malformed() {
+        assert_that!(parse_docker_images("")).is_ok_containing(vec![]);
+        assert_that!(parse_docker_images("IMAGE ID")).is_err();
+        assert_that!(parse_docker_images(
+            r#"{"ID":"sha256:0","Repository":"a","Tag":"b","Size":"1MB","CreatedAt":"yesterday"}"#
+        ))
+        .is_err();
+    }
+
+    #[test_case("ubuntu", "latest", "ubuntu", true; "implicit latest tag")]
+    #[test_case("ubuntu", "24.04", "ubuntu:24.04", true; "explicit tag")]
+    #[test_case("ubuntu", "24.04", "ubuntu", false; "different tag")]
+    #[test_case("registry:5000/foo", "latest", "registry:5000/foo", true; "registry port")]
+    #[test_case("registry:5000/foo", "1.0", "registry:5000/foo:1.0", true; "registry port and tag")]
+    #[test_case("ubuntu", "latest", "debian", false; "different repository")]
+    fn is(repository: &str, tag: &str, reference: &str, expected: bool) {
+        assert_that!(image(repository, tag).is(reference)).is_equal_to(expected);
+    }
+}
+
+#[cfg(test)]
+mod container_inspect_tests {
+    use gh_actions_scaler::machine::{parse_container_inspect, RunnerInfo};
+    use speculoos::prelude::*;
+
+    const INSPECT_OUTPUT: &str = include_str!("fixtures/docker/container_inspect.json");
+
+    #[test]
+    fn array_and_object() {
+        let from_array = parse_container_inspect(INSPECT_OUTPUT).unwrap();
+        assert_that!(from_array["Id"].as_str()).is_equal_to(Some("0123456789abcdef"));
+
+        let object = from_array.to_string();
+        assert_that!(parse_container_inspect(&object)).is_ok_containing(from_array);
+    }
+
+    #[test]
+    fn empty_or_malformed() {
+        assert_that!(parse_container_inspect("[]")).is_err();
+        assert_that!(parse_container_inspect("json")).is_err();
+    }
+
+    #[test]
+    fn runner_info() {
+        let inspected = parse_container_inspect(INSPECT_OUTPUT).unwrap();
+        let runner = RunnerInfo::from_inspect(&inspected).unwrap();
+        assert_that!(runner.container_id()).is_equal_to("0123456789abcdef");
+        assert_that!(runner.state().as_str()).is_equal_to("running");
+        assert_that!(runner.started_at().is_some()).is_true();
+        assert_that!(runner.finished_at()).is_none();
+        assert_that!(runner.runner_name()).is_equal_to("runner|1");
+        assert_that!(runner.run_url())
+            .is_equal_to(Some("https://github.com/foo/bar/actions/runs/42?a|b"));
+        assert_that!(runner.run_id()).is_equal_to(Some(42));
+        assert_that!(runner.to_string()).is_equal_to(
+            "0123456789ab (running, created at 2024-05-01T12:34:56.123456789+00:00, \
+             runner: runner|1, run: https://github.com/foo/bar/actions/runs/42?a|b)"
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn runner_info_without_run() {
+        // A warm pool runner is not dedicated to any run.
+        let mut inspected = parse_container_inspect(INSPECT_OUTPUT).unwrap();
+        let labels = inspected["Config"]["Labels"].as_object_mut().unwrap();
+        labels.remove("github-run-url");
+        labels.remove("github-run-id");
+        let runner = RunnerInfo::from_inspect(&inspected).unwrap();
+        assert_that!(runner.run_url()).is_none();
+        assert_that!(runner.run_id()).is_none();
+    }
+}
+
+#[cfg(test)]
+mod runner_query_tests {
+    use chrono::{DateTime, TimeDelta, Utc};
+    use gh_actions_scaler::machine::{ContainerState, RunnerInfo, RunnerQuery};
+    use speculoos::prelude::*;
+    use ureq::serde_json::json;
+
+    fn runner(state: &str, created_at: &str) -> RunnerInfo {
+        RunnerInfo::from_inspect(&json!({
+            "Id": "0123456789abcdef",
+            "Created": created_at,
+            "State": {
+                "Status": state,
+                "StartedAt": "0001-01-01T00:00:00Z",
+                "FinishedAt": "0001-01-01T00:00:00Z"
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn all() {
+        assert_that!(RunnerQuery::new().matches(&runner("exited", "2024-05-01T00:00:00Z")))
+            .is_true();
+    }
+
+    #[test]
+    fn in_state() {
+        let query = RunnerQuery::new()
+            .in_state(ContainerState::Running)
+            .in_state(ContainerState::Created);
+        assert_that!(query.matches(&runner("running", "2024-05-01T00:00:00Z"))).is_true();
+        assert_that!(query.matches(&runner("created", "2024-05-01T00:00:00Z"))).is_true();
+        assert_that!(query.matches(&runner("exited", "2024-05-01T00:00:00Z"))).is_false();
+    }
+
+    #[test]
+    fn alive() {
+        let query = RunnerQuery::alive();
+        for state in [
+            "created",
+            "restarting",
+            "running",
+            "paused",
+            "removing",
+            "exited",
+            "dead",
+        ] {
+            let runner = runner(state, "2024-05-01T00:00:00Z");
+            assert_that!(query.matches(&runner)).is_equal_to(runner.state().is_alive());
+        }
+    }
+
+    #[test]
+    fn created_after_and_before() {
+        let time: DateTime<Utc> = "2024-05-01T00:00:00Z".parse().unwrap();
+        let query = RunnerQuery::new()
+            .created_after(time)
+            .created_before(time + TimeDelta::hours(1));
+        assert_that!(query.matches(&runner("running", "2024-04-30T23:59:59Z"))).is_false();
+        assert_that!(query.matches(&runner("running", "2024-05-01T00:00:00Z"))).is_false();
+        assert_that!(query.matches(&runner("running", "2024-05-01T00:30:00Z"))).is_true();
+        assert_that!(query.matches(&runner("running", "2024-05-01T01:00:00Z"))).is_false();
+    }
+
+    #[test]
+    fn state_and_time() {
+        let query = RunnerQuery::new()
+            .in_state(ContainerState::Exited)
+            .created_before(Utc::now() - TimeDelta::hours(1));
+        assert_that!(query.matches(&runner("exited", "2024-05-01T00:00:00Z"))).is_true();
+        assert_that!(query.matches(&runner("running", "2024-05-01T00:00:00Z"))).is_false();
+        assert_that!(query.matches(&runner("exited", &Utc::now().to_rfc3339()))).is_false();
+    }
+}
+
+#[cfg(test)]
+mod find_idle_runners_to_stop_tests {
+    use chrono::{DateTime, Utc};
+    use gh_actions_scaler::machine::{find_idle_runners_to_stop, RunnerInfo};
+    use speculoos::prelude::*;
+    use std::collections::HashSet;
+    use std::time::Duration;
+    use ureq::serde_json::json;
+
+    const TIMEOUT: Duration = Duration::from_secs(300);
+
+    fn runner(name: &str, state: &str, started_at: &str) -> RunnerInfo {
+        RunnerInfo::from_inspect(&json!({
+            "Id": format!("{}-container", name),
+            "Created": "2024-05-01T00:00:00Z",
+            "State": {
+                "Status": state,
+                "StartedAt": started_at,
+                "FinishedAt": "0001-01-01T00:00:00Z"
+            },
+            "Config": {
+                "Labels": {
+                    "github-runner-name": name
+                }
+            },
+        }))
+        .unwrap()
+    }
+
+    fn now() -> DateTime<Utc> {
+        "2024-05-01T01:00:00Z".parse().unwrap()
+    }
+
+    fn idle(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn container_ids(runners: Vec<&RunnerInfo>) -> Vec<&str> {
+        runners.into_iter().map(RunnerInfo::container_id).collect()
+    }
+
+    #[test]
+    fn timed_out() {
+        let runners = [
+            runner("alpha", "running", "2024-05-01T00:54:59Z"),
+            runner("beta", "running", "2024-05-01T00:55:01Z"),
+        ];
+        let found =
+            find_idle_runners_to_stop(&runners, &idle(&["alpha", "beta"]), 0, TIMEOUT, now());
+        assert_that!(container_ids(found)).is_equal_to(vec!["alpha-container"]);
+    }
+
+    #[test]
+    fn busy_or_not_running() {
+        let runners = [
+            runner("alpha", "running", "2024-05-01T00:00:00Z"),
+            runner("beta", "exited", "2024-05-01T00:00:00Z"),
+        ];
+        // 'alpha' is busy, and 'beta' has exited already.
+        let found = find_idle_runners_to_stop(&runners, &idle(&["beta"]), 0, TIMEOUT, now());
+        assert_that!(found).is_empty();
+    }
+
+    #[test]
+    fn keep_most_recent() {
+        let runners = [
+            runner("alpha", "running", "2024-05-01T00:30:00Z"),
+            runner("beta", "running", "2024-05-01T00:10:00Z"),
+            runner("gamma", "running", "2024-05-01T00:20:00Z"),
+        ];
+        let names = idle(&["alpha", "beta", "gamma"]);
+        let found = find_idle_runners_to_stop(&runners, &names, 1, TIMEOUT, now());
+        assert_that!(container_ids(found)).is_equal_to(vec!["beta-container", "gamma-container"]);
+        let found = find_idle_runners_to_stop(&runners, &names, 3, TIMEOUT, now());
+        assert_that!(found).is_empty();
+    }
+
+    #[test]
+    fn not_started() {
+        // The creation time is used if the start time is unknown.
+        let runners = [runner("alpha", "running", "0001-01-01T00:00:00Z")];
+        let found = find_idle_runners_to_stop(&runners, &idle(&["alpha"]), 0, TIMEOUT, now());
+        assert_that!(container_ids(found)).is_equal_to(vec!["alpha-container"]);
+    }
+}
+
+#[cfg(test)]
+mod plan_scaling_tests {
+    use gh_actions_scaler::github::SelfHostedRunner;
+    use gh_actions_scaler::machine::{plan_scaling, RunnerInfo, ScalingPlan};
+    use speculoos::prelude::*;
+    use std::collections::HashSet;
+    use ureq::serde_json::json;
+
+    fn runner(name: &str, run_id: Option<u64>) -> RunnerInfo {
+        RunnerInfo::from_inspect(&json!({
+            "Id": format!("{}-container", name),
+            "Created": "2024-05-01T00:00:00Z",
+            "State": {
+                "Status": "running",
+                "StartedAt": "2024-05-01T00:00:00Z",
+                "FinishedAt": "0001-01-01T00:00:00Z"
+            },
+            "Config": {
+                "Labels": {
+                    "github-runner-name": name,
+                    "github-run-id": run_id.map(|id| id.to_string()).unwrap_or_default()
+                }
+            },
+        }))
+        .unwrap()
+    }
+
+    fn github_runner(id: u64, name: &str, busy: bool) -> SelfHostedRunner {
+        SelfHostedRunner {
+            id,
+            name: name.to_string(),
+            status: "online".to_string(),
+            busy,
+        }
+    }
+
+    fn stopped<'a>(plan: &ScalingPlan<'a>) -> Vec<(&'a str, u64)> {
+        plan.stop
+            .iter()
+            .map(|(runner, id)| (runner.container_id(), *id))
+            .collect()
+    }
+
+    #[test]
+    fn keep_idle_runners_while_busy() {
+        // 3 busy runners and 2 idle runners, with no queued runs and 'runners.min' of 2.
+        let runners = [
+            runner("alpha", None),
+            runner("beta", None),
+            runner("gamma", None),
+            runner("delta", None),
+            runner("epsilon", None),
+        ];
+        let github_runners = [
+            github_runner(1, "alpha", true),
+            github_runner(2, "beta", true),
+            github_runner(3, "gamma", true),
+            github_runner(4, "delta", false),
+            github_runner(5, "epsilon", false),
+        ];
+        let plan = plan_scaling(&runners, &github_runners, &HashSet::new(), 2, 10);
+        assert_that!(plan.not_busy).is_equal_to(2);
+        assert_that!(plan.start).is_equal_to(0);
+        assert_that!(plan.stop).is_empty();
+        assert_that!(plan.kept).is_equal_to(0);
+    }
+
+    #[test]
+    fn replenish_idle_runners_while_busy() {
+        // 'runners.min' of 2, with 2 busy runners and an idle runner.
+        let runners = [
+            runner("alpha", None),
+            runner("beta", None),
+            runner("gamma", None),
+        ];
+        let github_runners = [
+            github_runner(1, "alpha", true),
+            github_runner(2, "beta", true),
+            github_runner(3, "gamma", false),
+        ];
+        let plan = plan_scaling(&runners, &github_runners, &HashSet::new(), 2, 5);
+        assert_that!(plan.start).is_equal_to(1);
+        assert_that!(plan.stop).is_empty();
+
+        // The jobs of 'alpha' and 'beta' completed, leaving 3 idle runners.
+        let github_runners = [
+            github_runner(1, "alpha", false),
+            github_runner(2, "beta", false),
+            github_runner(3, "gamma", false),
+        ];
+        let plan = plan_scaling(&runners, &github_runners, &HashSet::new(), 2, 5);
+        assert_that!(plan.start).is_equal_to(0);
+        assert_that!(stopped(&plan)).is_equal_to(vec![("alpha-container", 1)]);
+    }
+
+    #[test]
+    fn stop_excess_idle_runners_only() {
+        let runners = [
+            runner("alpha", None),
+            runner("beta", None),
+            runner("gamma", None),
+        ];
+        let github_runners = [
+            github_runner(1, "alpha", true),
+            github_runner(2, "beta", false),
+            github_runner(3, "gamma", false),
+        ];
+        let plan = plan_scaling(&runners, &github_runners, &HashSet::new(), 1, 10);
+        assert_that!(plan.not_busy).is_equal_to(2);
+        assert_that!(stopped(&plan)).is_equal_to(vec![("beta-container", 2)]);
+        assert_that!(plan.kept).is_equal_to(0);
+    }
+
+    #[test]
+    fn keep_runners_waiting_for_queued_runs() {
+        // 'alpha' is waiting for its queued run, and 'beta' has not registered itself yet.
+        let runners = [
+            runner("alpha", Some(42)),
+            runner("beta", None),
+            runner("gamma", Some(43)),
+        ];
+        let github_runners = [
+            github_runner(1, "alpha", false),
+            github_runner(3, "gamma", false),
+        ];
+        let queued_run_ids = HashSet::from([42]);
+        let plan = plan_scaling(&runners, &github_runners, &queued_run_ids, 0, 10);
+        assert_that!(plan.not_busy).is_equal_to(3);
+        assert_that!(stopped(&plan)).is_equal_to(vec![("gamma-container", 3)]);
+        assert_that!(plan.kept).is_equal_to(2);
+    }
+
+    #[test]
+    fn start_up_to_max() {
+        let runners = [
+            runner("alpha", None),
+            runner("beta", None),
+            runner("gamma", None),
+        ];
+        let github_runners = [
+            github_runner(1, "alpha", true),
+            github_runner(2, "beta", true),
+            github_runner(3, "gamma", false),
+        ];
+        let plan = plan_scaling(&runners, &github_runners, &HashSet::new(), 3, 10);
+        assert_that!(plan.not_busy).is_equal_to(1);
+        assert_that!(plan.start).is_equal_to(2);
+        // The busy runners count toward 'runners.max'.
+        let plan = plan_scaling(&runners, &github_runners, &HashSet::new(), 3, 4);
+        assert_that!(plan.start).is_equal_to(1);
+    }
+}
+
+#[cfg(test)]
+mod find_orphaned_runners_tests {
+    use gh_actions_scaler::config::Config;
+    use gh_actions_scaler::github::SelfHostedRunner;
+    use gh_actions_scaler::machine::{find_orphaned_runners, Machine};
+    use speculoos::prelude::*;
+    use std::collections::{HashMap, HashSet};
+
+    fn runner(id: u64, name: &str, status: &str) -> SelfHostedRunner {
+        SelfHostedRunner {
+            id,
+            name: name.to_string(),
+            status: status.to_string(),
+            busy: false,
+        }
+    }
+
+    fn alive(machines: &[(&str, &[&str])]) -> HashMap<String, HashSet<String>> {
+        machines
+            .iter()
+            .map(|(machine_id, names)| {
+                let names = names.iter().map(|name| name.to_string()).collect();
+                (machine_id.to_string(), names)
+            })
+            .collect()
+    }
+
+    fn found(orphans: Vec<(&str, &SelfHostedRunner)>) -> Vec<(String, u64)> {
+        orphans
+            .into_iter()
+            .map(|(machine_id, runner)| (machine_id.to_string(), runner.id))
+            .collect()
+    }
+
+    fn machines(config: &Config) -> Vec<Machine> {
+        config.machines.iter().cloned().map(Machine::new).collect()
+    }
+
+    #[test]
+    fn alive_on_other_machine() {
+        // Both machines share the default runner name template.
+        let config = Config::try_from("tests/fixtures/config/machine_labels.yaml").unwrap();
+        let machines = machines(&config);
+        let runners = [
+            runner(1, "runner-1", "offline"),
+            runner(2, "runner-2", "offline"),
+            runner(3, "runner-3", "online"),
+            runner(4, "other-4", "offline"),
+        ];
+        let alive_runner_names = alive(&[
+            ("machine-alpha", &[]),
+            ("machine-beta", &["runner-2", "runner-3"]),
+        ]);
+
+        // 'runner-2' is alive on 'machine-beta', so 'machine-alpha' must not deregister it.
+        let orphans = find_orphaned_runners(&runners, &machines, &config, &alive_runner_names);
+        assert_that!(found(orphans)).is_equal_to(vec![("machine-alpha".to_string(), 1)]);
+    }
+
+    #[test]
+    fn unreachable_machine() {
+        let config = Config::try_from("tests/fixtures/config/machine_labels.yaml").unwrap();
+        let machines = machines(&config);
+        let runners = [runner(1, "runner-1", "offline")];
+
+        // 'runner-1' may be alive on 'machine-beta', which couldn't be reached.
+        let alive_runner_names = alive(&[("machine-alpha", &[])]);
+        let orphans = find_orphaned_runners(&runners, &machines, &config, &alive_runner_names);
+        assert_that!(orphans).is_empty();
+    }
+
+    #[test]
+    fn machine_id_in_template() {
+        let mut config = Config::try_from("tests/fixtures/config/machine_labels.yaml").unwrap();
+        config.github.runners.runner_name_template = "{prefix}-{machine_id}-{uuid}".to_string();
+        let machines = machines(&config);
+        let runners = [
+            runner(1, "runner-machine-alpha-1", "offline"),
+            runner(2, "runner-machine-beta-2", "offline"),
+        ];
+
+        // Only 'machine-alpha' can own 'runner-machine-alpha-1', so 'machine-beta' being
+        // unreachable doesn't matter.
+        let alive_runner_names = alive(&[("machine-alpha", &[])]);
+        let orphans = find_orphaned_runners(&runners, &machines, &config, &alive_runner_names);
+        assert_that!(found(orphans)).is_equal_to(vec![("machine-alpha".to_string(), 1)]);
+    }
+
+    #[test]
+    fn machine_id_in_name_prefix() {
+        let mut config = Config::try_from("tests/fixtures/config/machine_labels.yaml").unwrap();
+        config.github.runners.name_prefix = "ci-{machine_id}".to_string();
+        let machines = machines(&config);
+        let runners = [
+            runner(1, "ci-machine-alpha-1", "offline"),
+            runner(2, "ci-{machine_id}-2", "offline"),
+        ];
+
+        let alive_runner_names = alive(&[("machine-alpha", &[])]);
+        let orphans = find_orphaned_runners(&runners, &machines, &config, &alive_runner_names);
+        assert_that!(found(orphans)).is_equal_to(vec![("machine-alpha".to_string(), 1)]);
+    }
+}
+
+#[cfg(test)]
+mod trace_command_tests {
+    use gh_actions_scaler::machine::trace_command;
+    use speculoos::prelude::*;
+    use std::io;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+    use tracing::Level;
+
+    /// Returns what `f` logged at `trace` level.
+    fn capture_trace<F: FnOnce()>(f: F) -> String {
+        let buf = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let writer_buf = buf.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(Level::TRACE)
+            .with_ansi(false)
+            .with_writer(move || CaptureWriter(writer_buf.clone()))
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+        let output = String::from_utf8(buf.lock().unwrap().clone()).unwrap();
+        output
+    }
+
+    struct CaptureWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for CaptureWriter {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn command() {
+        let socket_addr: SocketAddr = "127.0.0.1:22".parse().unwrap();
+        let output = capture_trace(|| {
+            trace_command(&socket_addr, "docker container stop --time 10 'foo bar'");
+        });
+        assert_that!(output.as_str()).contains("TRACE");
+        assert_that!(output.as_str())
+            .contains("[127.0.0.1:22] Executing: docker container stop --time 10 'foo bar'");
+    }
+
+    #[test]
+    fn here_document() {
+        let socket_addr: SocketAddr = "127.0.0.1:22".parse().unwrap();
+        let output = capture_trace(|| {
+            trace_command(
+                &socket_addr,
+                "cat <<'DELIM' >'/tmp/env.1234'\nexport ACCESS_TOKEN=Z2hwX3NlY3JldA==\nDELIM\n",
+            );
+        });
+        assert_that!(output.as_str()).contains("Executing: cat <<'DELIM' >'/tmp/env.1234'");
+        assert_that!(output.as_str()).contains("here-document omitted");
+        assert_that!(output.as_str()).does_not_contain("Z2hwX3NlY3JldA==");
+    }
+}
+
+#[cfg(test)]
+mod run_with_timeout_tests {
+    use gh_actions_scaler::machine::{raise_session_timeout, run_with_timeout, TimedOutError};
+    use speculoos::prelude::*;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use test_case::test_case;
+
+    #[test]
+    fn completed() {
+        let res = run_with_timeout("echo ok", Duration::from_secs(10), || "ok");
+        assert_that!(res.ok()).is_equal_to(Some("ok"));
+    }
+
+    #[test]
+    fn unlimited() {
+        let res = run_with_timeout("echo ok", Duration::ZERO, || "ok");
+        assert_that!(res.ok()).is_equal_to(Some("ok"));
+    }
+
+    #[test]
+    fn timed_out() {
+        let started_at = Instant::now();
+        let res = run_with_timeout("sleep 9999", Duration::from_millis(100), || {
+            thread::sleep(Duration::from_secs(9999));
+        });
+        assert_that!(started_at.elapsed()).is_less_than(Duration::from_secs(5));
+
+        let err = res.unwrap_err();
+        assert_that!(err.command.as_str()).is_equal_to("sleep 9999");
+        assert_that!(err.timeout).is_equal_to(Duration::from_millis(100));
+        assert_that!(err.to_string()).contains("sleep 9999");
+    }
+
+    #[test]
+    fn silent_longer_than_session_timeout() {
+        // A command that prints nothing for a while must complete within its own timeout.
+        let res = run_with_timeout("docker image pull", Duration::from_secs(10), || {
+            thread::sleep(Duration::from_millis(200));
+            "pulled"
+        });
+        assert_that!(res.ok()).is_equal_to(Some("pulled"));
+
+        let res: Result<(), TimedOutError> =
+            run_with_timeout("docker image pull", Duration::from_millis(100), || {
+                thread::sleep(Duration::from_secs(9999));
+            });
+        assert_that!(res.unwrap_err().timeout).is_equal_to(Duration::from_millis(100));
+    }
+
+    #[test_case(60_000, 600, Some(600_000); "shorter session timeout")]
+    #[test_case(60_000, 60, None; "same timeout")]
+    #[test_case(60_000, 10, None; "longer session timeout")]
+    #[test_case(60_000, 0, Some(0); "unlimited command timeout")]
+    #[test_case(0, 600, None; "unlimited session timeout")]
+    fn raise(session_timeout: u32, timeout_secs: u64, expected: Option<u32>) {
+        let timeout = Duration::from_secs(timeout_secs);
+        assert_that!(raise_session_timeout(session_timeout, timeout)).is_equal_to(expected);
+    }
+}
+
+#[cfg(test)]
+mod container_state_tests {
+    use gh_actions_scaler::machine::ContainerState;
+    use speculoos::assert_that;
+    use test_case::test_case;
+
+    #[test_case("running", "running"; "known state")]
+    #[test_case("exited", "exited"; "another known state")]
+    #[test_case("foo", "unknown(foo)"; "unknown state")]
+    fn display(state: &str, expected: &str) {
+        assert_that!(ContainerState::from(state).to_string()).is_equal_to(expected.to_string());
+    }
+}
+
+#[cfg(test)]
+mod machine_tests {
+    use gh_actions_scaler::config::Config;
+    use gh_actions_scaler::machine::Machine;
+    use speculoos::prelude::*;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use test_case::test_case;
+
+    #[test_case(&[], true, true; "no labels")]
+    #[test_case(&["self-hosted", "Linux", "X64"], true, true; "implicit labels")]
+    #[test_case(&["self-hosted", "gpu"], true, false; "custom label")]
+    #[test_case(&["GPU"], true, false; "custom label (case-insensitive)")]
+    #[test_case(&["gpu", "arm64"], false, false; "unsatisfiable labels")]
+    fn satisfies(required_labels: &[&str], expected_alpha: bool, expected_beta: bool) {
+        let config = Config::try_from("tests/fixtures/config/machine_labels.yaml").unwrap();
+        let alpha = Machine::new(config.machines[0].clone());
+        let beta = Machine::new(config.machines[1].clone());
+        let required_labels: Vec<String> = required_labels.iter().map(|l| l.to_string()).collect();
+        assert_that!(alpha.satisfies(&required_labels)).is_equal_to(expected_alpha);
+        assert_that!(beta.satisfies(&required_labels)).is_equal_to(expected_beta);
+    }
+
+    #[test_case(0, 0, 2; "no live runners")]
+    #[test_case(1, 1, 1; "fewer idle runners")]
+    #[test_case(2, 2, 0; "as many idle runners")]
+    #[test_case(4, 4, 0; "more idle runners")]
+    #[test_case(2, 4, 0; "busy runners with as many idle runners")]
+    #[test_case(0, 3, 2; "busy runners only")]
+    #[test_case(1, 4, 1; "busy runners up to 'runners.max'")]
+    #[test_case(0, 5, 0; "busy runners as many as 'runners.max'")]
+    fn warm_up_count(not_busy: u32, live: u32, expected: u32) {
+        let config = Config::try_from("tests/fixtures/config/warm_pool.yaml").unwrap();
+        let alpha = Machine::new(config.machines[0].clone());
+        assert_that!(alpha.warm_up_count(not_busy, live)).is_equal_to(expected);
+        // 'runners.min' is 0 by default.
+        let beta = Machine::new(config.machines[1].clone());
+        assert_that!(beta.warm_up_count(not_busy, live)).is_equal_to(0);
+    }
+
+    #[test]
+    fn clone_across_threads() {
+        let config = Config::try_from("tests/fixtures/config/machine_labels.yaml").unwrap();
+        let machine = Machine::new(config.machines[1].clone());
+        let cloned = machine.clone();
+        let handle = std::thread::spawn(move || {
+            (
+                cloned.id().to_string(),
+                cloned.satisfies(&["gpu".to_string()]),
+            )
+        });
+        assert_that!(handle.join().unwrap()).is_equal_to((
+            machine.id().to_string(),
+            machine.satisfies(&["gpu".to_string()]),
+        ));
+    }
+
+    #[test]
+    fn share_config() {
+        let mut config = Config::try_from("tests/fixtures/config/machine_labels.yaml").unwrap();
+        let machine_config = config.machines.remove(0);
+        assert_that!(Arc::strong_count(&machine_config)).is_equal_to(1);
+
+        let machine = Machine::new(Arc::clone(&machine_config));
+        let cloned = machine.clone();
+        assert_that!(Arc::strong_count(&machine_config)).is_equal_to(3);
+
+        drop(machine);
+        drop(cloned);
+        assert_that!(Arc::strong_count(&machine_config)).is_equal_to(1);
+    }
+
+    #[test]
+    fn new_with_session_unreachable() {
+        let config = Config::try_from("tests/fixtures/config/minimal.yaml").unwrap();
+        let mut machine_config = (*config.machines[0]).clone();
+        // Nothing listens on the port once the listener is dropped.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        machine_config.ssh.host = "127.0.0.1".to_string();
+        machine_config.ssh.port = port;
+        machine_config.ssh.max_retries = Some(0);
+        assert_that!(Machine::new_with_session(Arc::new(machine_config)).is_err()).is_true();
+    }
+}
+
+#[cfg(test)]
+mod append_audit_log_tests {
+    use gh_actions_scaler::machine::{append_audit_log, ScalingAction, ScalingDecision};
+    use speculoos::prelude::*;
+    use std::fs;
+    use ureq::serde_json;
+    use uuid::Uuid;
+
+    #[test]
+    fn append() {
+        let dir = std::env::temp_dir().join(format!("gh-actions-scaler-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        defer! {
+            let _ = fs::remove_dir_all(&dir);
+        }
+        let audit_log = dir.join("audit.log");
+
+        let start = ScalingDecision {
+            machine_id: "alpha".to_string(),
+            action: ScalingAction::Start,
+            reason: "0 live runner(s) are fewer than 1".to_string(),
+            run_url: Some("https://github.com/foo/bar/actions/runs/1".to_string()),
+            container_id: Some("0123456789ab".to_string()),
+            container_name: Some("github-self-hosted-runner-foo".to_string()),
+        };
+        let skip = ScalingDecision {
+            machine_id: "beta".to_string(),
+            action: ScalingAction::Skip,
+            reason: "1 live runner(s) are as many as needed".to_string(),
+            run_url: None,
+            container_id: None,
+            container_name: None,
+        };
+        append_audit_log(&audit_log, &[start]).unwrap();
+        append_audit_log(&audit_log, &[skip]).unwrap();
+
+        let content = fs::read_to_string(&audit_log).unwrap();
+        let records: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_that!(records).has_length(2);
+        assert_that!(records[0]["machine_id"].as_str()).is_equal_to(Some("alpha"));
+        assert_that!(records[0]["action"].as_str()).is_equal_to(Some("start"));
+        assert_that!(records[0]["container_id"].as_str()).is_equal_to(Some("0123456789ab"));
+        assert_that!(records[0]["container_name"].as_str())
+            .is_equal_to(Some("github-self-hosted-runner-foo"));
+        assert_that!(records[0]["timestamp"].is_string()).is_true();
+        assert_that!(records[1]["action"].as_str()).is_equal_to(Some("skip"));
+        assert_that!(records[1].get("run_url")).is_none();
+    }
+}
@@ -0,0 +1,32 @@
+#[cfg(test)]
+mod average_stats_tests {
+    use gh_actions_scaler::machine::ContainerStats;
+    use gh_actions_scaler::monitor::average_stats;
+    use speculoos::prelude::*;
+
+    #[test]
+    fn empty() {
+        assert_that!(average_stats(&[])).is_none();
+    }
+
+    #[test]
+    fn average() {
+        let stats = [
+            ContainerStats {
+                cpu_percent: 50.0,
+                memory_bytes: 1000,
+                memory_limit_bytes: 8000,
+            },
+            ContainerStats {
+                cpu_percent: 150.0,
+                memory_bytes: 3000,
+                memory_limit_bytes: 8000,
+            },
+        ];
+        assert_that!(average_stats(&stats)).is_equal_to(Some(ContainerStats {
+            cpu_percent: 100.0,
+            memory_bytes: 2000,
+            memory_limit_bytes: 8000,
+        }));
+    }
+}
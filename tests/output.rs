@@ -0,0 +1,168 @@
+#[cfg(test)]
+mod formatter_tests {
+    use gh_actions_scaler::machine::RunnerInfo;
+    use gh_actions_scaler::output::{
+        quote_csv_field, CsvFormatter, Formatter, JsonFormatter, MachineRow, MachineStatus,
+        RunnerRow, TableFormatter,
+    };
+    use speculoos::prelude::*;
+    use test_case::test_case;
+    use ureq::serde_json::json;
+
+    fn runners() -> Vec<RunnerInfo> {
+        vec![
+            RunnerInfo::from_inspect(&json!({
+                "Id": "0123456789abcdef",
+                "Created": "2024-05-01T12:34:56Z",
+                "State": {
+                    "Status": "running",
+                    "StartedAt": "2024-05-01T12:35:00Z",
+                    "FinishedAt": "0001-01-01T00:00:00Z"
+                },
+                "Config": {
+                    "Labels": {
+                        "github-runner-name": "runner-1",
+                        "github-run-url": "https://github.com/foo/bar/actions/runs/42?a=1,2",
+                        "github-run-id": "42"
+                    }
+                }
+            }))
+            .unwrap(),
+            RunnerInfo::from_inspect(&json!({
+                "Id": "fedcba9876543210",
+                "Created": "2024-05-01T10:00:00Z",
+                "State": {
+                    "Status": "dead",
+                    "StartedAt": "2024-05-01T10:00:01Z",
+                    "FinishedAt": "2024-05-01T11:00:00Z"
+                }
+            }))
+            .unwrap(),
+        ]
+    }
+
+    fn runner_rows(runners: &[RunnerInfo]) -> Vec<RunnerRow<'_>> {
+        vec![
+            RunnerRow {
+                machine_id: "machine-alpha",
+                runner: &runners[0],
+            },
+            RunnerRow {
+                machine_id: "machine-beta",
+                runner: &runners[1],
+            },
+        ]
+    }
+
+    fn machine_rows() -> Vec<MachineRow<'static>> {
+        vec![
+            MachineRow {
+                id: "machine-alpha",
+                host: "alpha.example.tld",
+                port: 22,
+                status: MachineStatus::Online,
+                latency_ms: Some(12),
+                runners: Some(1),
+                error: None,
+            },
+            MachineRow {
+                id: "machine-beta",
+                host: "beta.example.tld",
+                port: 8022,
+                status: MachineStatus::Degraded,
+                latency_ms: Some(345),
+                runners: None,
+                error: Some("Connection refused".to_string()),
+            },
+        ]
+    }
+
+    #[test]
+    fn table_runners() {
+        let runners = runners();
+        let output = TableFormatter::new(false).format_runners(&runner_rows(&runners));
+        assert_that!(output.as_str()).is_equal_to(concat!(
+            "┌───────────────┬──────────────┬─────────┬─────────────────────┬─────────────────────┬─────────────────────┐\n",
+            "│ MACHINE       │ CONTAINER    │ STATE   │ CREATED             │ STARTED             │ FINISHED            │\n",
+            "├───────────────┼──────────────┼─────────┼─────────────────────┼─────────────────────┼─────────────────────┤\n",
+            "│ machine-alpha │ 0123456789ab │ running │ 2024-05-01 12:34:56 │ 2024-05-01 12:35:00 │ -                   │\n",
+            "│ machine-beta  │ fedcba987654 │ dead    │ 2024-05-01 10:00:00 │ 2024-05-01 10:00:01 │ 2024-05-01 11:00:00 │\n",
+            "└───────────────┴──────────────┴─────────┴─────────────────────┴─────────────────────┴─────────────────────┘\n",
+        ));
+    }
+
+    #[test]
+    fn table_runners_colored() {
+        let runners = runners();
+        let output = TableFormatter::new(true).format_runners(&runner_rows(&runners));
+        // Green for 'running' and red for 'dead'
+        assert_that!(output.as_str()).contains("\x1b[32mrunning\x1b[0m");
+        assert_that!(output.as_str()).contains("\x1b[31mdead\x1b[0m");
+    }
+
+    #[test]
+    fn table_machines() {
+        let output = TableFormatter::new(false).format_machines(&machine_rows());
+        assert_that!(output.as_str()).is_equal_to(concat!(
+            "┌───────────────┬───────────────────┬──────┬──────────┬──────────────┬───────────────────────────┐\n",
+            "│ ID            │ HOST              │ PORT │ STATUS   │ LATENCY (ms) │ RUNNERS                   │\n",
+            "├───────────────┼───────────────────┼──────┼──────────┼──────────────┼───────────────────────────┤\n",
+            "│ machine-alpha │ alpha.example.tld │ 22   │ ONLINE   │ 12           │ 1                         │\n",
+            "│ machine-beta  │ beta.example.tld  │ 8022 │ DEGRADED │ 345          │ error: Connection refused │\n",
+            "└───────────────┴───────────────────┴──────┴──────────┴──────────────┴───────────────────────────┘\n",
+        ));
+    }
+
+    #[test]
+    fn json_runners() {
+        let runners = runners();
+        let output = JsonFormatter.format_runners(&runner_rows(&runners));
+        assert_that!(output.lines().count()).is_equal_to(1);
+        let parsed: ureq::serde_json::Value = ureq::serde_json::from_str(&output).unwrap();
+        assert_that!(parsed[0]["machine_id"]).is_equal_to(json!("machine-alpha"));
+        assert_that!(parsed[0]["container_state"]).is_equal_to(json!("running"));
+        assert_that!(parsed[0]["run_id"]).is_equal_to(json!(42));
+        assert_that!(parsed[1]["machine_id"]).is_equal_to(json!("machine-beta"));
+        assert_that!(parsed[1]["container_state"]).is_equal_to(json!("dead"));
+    }
+
+    #[test]
+    fn json_machines() {
+        let output = JsonFormatter.format_machines(&machine_rows());
+        assert_that!(output.as_str()).is_equal_to(concat!(
+            r#"[{"id":"machine-alpha","host":"alpha.example.tld","port":22,"status":"ONLINE","latency_ms":12,"runners":1,"error":null},"#,
+            r#"{"id":"machine-beta","host":"beta.example.tld","port":8022,"status":"DEGRADED","latency_ms":345,"runners":null,"error":"Connection refused"}]"#,
+            "\n",
+        ));
+    }
+
+    #[test]
+    fn csv_runners() {
+        let runners = runners();
+        let output = CsvFormatter.format_runners(&runner_rows(&runners));
+        assert_that!(output.as_str()).is_equal_to(concat!(
+            "machine_id,container_id,container_state,created_at,started_at,finished_at,runner_name,run_url,run_id\r\n",
+            "machine-alpha,0123456789abcdef,running,2024-05-01T12:34:56+00:00,2024-05-01T12:35:00+00:00,,runner-1,\"https://github.com/foo/bar/actions/runs/42?a=1,2\",42\r\n",
+            "machine-beta,fedcba9876543210,dead,2024-05-01T10:00:00+00:00,2024-05-01T10:00:01+00:00,2024-05-01T11:00:00+00:00,,,\r\n",
+        ));
+    }
+
+    #[test]
+    fn csv_machines() {
+        let output = CsvFormatter.format_machines(&machine_rows());
+        assert_that!(output.as_str()).is_equal_to(concat!(
+            "id,host,port,status,latency_ms,runners,error\r\n",
+            "machine-alpha,alpha.example.tld,22,ONLINE,12,1,\r\n",
+            "machine-beta,beta.example.tld,8022,DEGRADED,345,,Connection refused\r\n",
+        ));
+    }
+
+    #[test_case("foo", "foo"; "plain")]
+    #[test_case("", ""; "empty")]
+    #[test_case("foo,bar", "\"foo,bar\""; "comma")]
+    #[test_case("say \"hi\"", "\"say \"\"hi\"\"\""; "double quote")]
+    #[test_case("foo\nbar", "\"foo\nbar\""; "line break")]
+    fn quote_csv(field: &str, expected: &str) {
+        assert_that!(quote_csv_field(field).as_str()).is_equal_to(expected);
+    }
+}
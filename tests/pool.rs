@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod machine_pool_tests {
+    use gh_actions_scaler::config::{Config, SelectionStrategy};
+    use gh_actions_scaler::machine::Machine;
+    use gh_actions_scaler::pool::{
+        select_least_loaded, MachinePool, PoolError, WeightedRandomSelector,
+    };
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use speculoos::prelude::*;
+    use std::collections::HashMap;
+    use test_case::test_case;
+
+    fn machines(config: &Config) -> Vec<Machine> {
+        config.machines.iter().cloned().map(Machine::new).collect()
+    }
+
+    #[test]
+    fn weighted_distribution() {
+        let config = Config::try_from("tests/fixtures/config/machine_weights.yaml").unwrap();
+        let mut pool = MachinePool::new(&machines(&config), SelectionStrategy::RoundRobin);
+        let mut assignments: HashMap<String, u32> = HashMap::new();
+        for _ in 0..100 {
+            let machine = pool.next_machine(&[]).unwrap();
+            *assignments.entry(machine.id().to_string()).or_default() += 1;
+        }
+
+        assert_that!(assignments["machine-alpha"]).is_equal_to(75);
+        assert_that!(assignments["machine-beta"]).is_equal_to(25);
+    }
+
+    #[test]
+    fn weighted_random_distribution() {
+        let config = Config::try_from("tests/fixtures/config/machine_weights.yaml").unwrap();
+        let selector = WeightedRandomSelector::new(StdRng::seed_from_u64(42));
+        let mut pool = MachinePool::with_selector(&machines(&config), Box::new(selector));
+        let mut assignments: HashMap<String, u32> = HashMap::new();
+        for _ in 0..10000 {
+            let machine = pool.next_machine(&[]).unwrap();
+            *assignments.entry(machine.id().to_string()).or_default() += 1;
+        }
+
+        // The expected numbers are 7500 and 2500.
+        assert_that!(assignments["machine-alpha"]).is_greater_than(7200);
+        assert_that!(assignments["machine-alpha"]).is_less_than(7800);
+        assert_that!(assignments["machine-alpha"] + assignments["machine-beta"]).is_equal_to(10000);
+    }
+
+    #[test]
+    fn disabled_machine() {
+        let config = Config::try_from("tests/fixtures/config/disabled_machine.yaml").unwrap();
+        let mut pool = MachinePool::new(&machines(&config), SelectionStrategy::RoundRobin);
+        for _ in 0..10 {
+            assert_that!(pool.next_machine(&[]).unwrap().id()).is_equal_to("machine-alpha");
+        }
+    }
+
+    #[test]
+    fn labels() {
+        for strategy in [
+            SelectionStrategy::RoundRobin,
+            SelectionStrategy::WeightedRandom,
+        ] {
+            let config = Config::try_from("tests/fixtures/config/machine_weights.yaml").unwrap();
+            let mut pool = MachinePool::new(&machines(&config), strategy);
+            let required_labels = vec!["gpu".to_string()];
+            for _ in 0..10 {
+                let machine = pool.next_machine(&required_labels).unwrap();
+                assert_that!(machine.id()).is_equal_to("machine-beta");
+            }
+
+            assert_that!(pool.next_machine(&["arm64".to_string()]).err())
+                .is_equal_to(Some(PoolError::NoMachineWithLabels));
+        }
+    }
+
+    #[test_case(&[Some((2, 4)), Some((1, 4)), Some((3, 4))], Ok(1); "fewest running")]
+    #[test_case(&[Some((1, 4)), Some((1, 4))], Ok(0); "tie broken by order")]
+    #[test_case(&[Some((1, 1)), Some((3, 4))], Ok(1); "skip machine at capacity")]
+    #[test_case(&[None, Some((3, 4))], Ok(1); "skip unreachable machine")]
+    #[test_case(&[Some((1, 1)), None], Err(PoolError::AllMachinesAtCapacity); "all at capacity")]
+    #[test_case(&[None, None], Err(PoolError::AllMachinesUnreachable); "all unreachable")]
+    fn least_loaded_selection(loads: &[Option<(u32, u32)>], expected: Result<usize, PoolError>) {
+        assert_that!(select_least_loaded(loads)).is_equal_to(expected);
+    }
+
+    #[test]
+    fn least_loaded_unreachable() {
+        let config = Config::try_from("tests/fixtures/config/unreachable_machines.yaml").unwrap();
+        let mut pool = MachinePool::new(&machines(&config), SelectionStrategy::LeastLoaded);
+        assert_that!(pool
+            .next_machine(&[])
+            .map(|machine| machine.id().to_string()))
+        .is_equal_to(Err(PoolError::AllMachinesUnreachable));
+    }
+}
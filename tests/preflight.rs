@@ -0,0 +1,151 @@
+#[cfg(test)]
+mod evaluate_tests {
+    use gh_actions_scaler::config::{DockerConfig, PullPolicy};
+    use gh_actions_scaler::machine::{DiskUsage, MemoryInfo};
+    use gh_actions_scaler::preflight::{MachinePreflight, PreflightIssue, PreflightReport};
+    use speculoos::prelude::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn docker_config(pull_policy: PullPolicy) -> DockerConfig {
+        DockerConfig {
+            pull_policy: Some(pull_policy),
+            ..DockerConfig::default()
+        }
+    }
+
+    fn disk_usage(available_bytes: u64) -> Option<DiskUsage> {
+        Some(DiskUsage {
+            total_bytes: 200 * GIB,
+            available_bytes,
+        })
+    }
+
+    fn memory(available_bytes: u64) -> Option<MemoryInfo> {
+        Some(MemoryInfo {
+            total_bytes: 16 * GIB,
+            available_bytes,
+        })
+    }
+
+    fn healthy_report() -> PreflightReport {
+        PreflightReport {
+            docker_version: (24, 0, 7),
+            disk_usage: disk_usage(100 * GIB),
+            memory: memory(8 * GIB),
+            running_runners: Some(2),
+            image_cached: true,
+            warnings: vec![],
+        }
+    }
+
+    #[test]
+    fn healthy() {
+        let report = healthy_report();
+        assert_that!(MachinePreflight::evaluate(
+            &docker_config(PullPolicy::Never),
+            report.clone()
+        ))
+        .is_ok_containing(report);
+    }
+
+    #[test]
+    fn low_resources() {
+        let report = PreflightReport {
+            disk_usage: disk_usage(2 * GIB),
+            memory: None,
+            ..healthy_report()
+        };
+        let report =
+            MachinePreflight::evaluate(&docker_config(PullPolicy::Always), report).unwrap();
+        assert_that!(report.warnings).has_length(2);
+        assert_that!(report
+            .warnings
+            .iter()
+            .all(|issue| matches!(issue, PreflightIssue::Warning(_))))
+        .is_true();
+    }
+
+    #[test]
+    fn critically_low_disk() {
+        let report = PreflightReport {
+            disk_usage: disk_usage(512 * 1024 * 1024),
+            ..healthy_report()
+        };
+        let issues =
+            MachinePreflight::evaluate(&docker_config(PullPolicy::Always), report).unwrap_err();
+        assert_that!(issues).has_length(1);
+        assert_that!(matches!(issues[0], PreflightIssue::Error(_))).is_true();
+        assert_that!(issues[0].to_string()).contains("536870912 bytes of disk space");
+    }
+
+    #[test]
+    fn custom_disk_thresholds() {
+        let docker = DockerConfig {
+            min_free_disk_bytes: Some(200 * GIB),
+            critical_free_disk_bytes: Some(50 * GIB),
+            ..docker_config(PullPolicy::Always)
+        };
+
+        // Below the minimum
+        let report = MachinePreflight::evaluate(&docker, healthy_report()).unwrap();
+        assert_that!(report.warnings).has_length(1);
+
+        // Below the critical level
+        let report = PreflightReport {
+            disk_usage: disk_usage(10 * GIB),
+            ..healthy_report()
+        };
+        assert_that!(MachinePreflight::evaluate(&docker, report)).is_err();
+    }
+
+    #[test]
+    fn near_memory_capacity() {
+        let docker = DockerConfig {
+            memory_limit_bytes: Some(4 * GIB),
+            ..docker_config(PullPolicy::Always)
+        };
+
+        // 8 GiB is enough for 1 runner, but not for 2 runners with the 20% headroom.
+        let report = PreflightReport {
+            running_runners: Some(1),
+            ..healthy_report()
+        };
+        assert_that!(
+            MachinePreflight::evaluate(&docker, report)
+                .unwrap()
+                .warnings
+        )
+        .is_empty();
+
+        let report = MachinePreflight::evaluate(&docker, healthy_report()).unwrap();
+        assert_that!(report.warnings).has_length(1);
+        assert_that!(report.warnings[0].to_string()).contains("near its memory capacity");
+    }
+
+    #[test]
+    fn old_docker() {
+        let report = PreflightReport {
+            docker_version: (19, 3, 15),
+            ..healthy_report()
+        };
+        let issues =
+            MachinePreflight::evaluate(&docker_config(PullPolicy::Always), report).unwrap_err();
+        assert_that!(issues).has_length(1);
+        assert_that!(issues[0].to_string()).contains("Docker 19.3.15 is too old");
+    }
+
+    #[test]
+    fn missing_image() {
+        let report = PreflightReport {
+            image_cached: false,
+            memory: memory(512 * 1024 * 1024),
+            ..healthy_report()
+        };
+        let issues =
+            MachinePreflight::evaluate(&docker_config(PullPolicy::Never), report).unwrap_err();
+        assert_that!(issues).has_length(2);
+        assert_that!(matches!(issues[0], PreflightIssue::Warning(_))).is_true();
+        assert_that!(matches!(issues[1], PreflightIssue::Error(_))).is_true();
+    }
+}